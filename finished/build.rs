@@ -0,0 +1,40 @@
+// Compiles the shaders in glsl_shaders/ into OUT_DIR, see shader_build.
+// The fragment shaders are compiled once per formula, the values of FORMULA must match Formula in formula.rs
+
+use shader_build::{compile, Shader};
+
+fn main() {
+    compile(&[
+        Shader::new("../glsl_shaders/mandelbrot.vert", "man_vert.spv"),
+        //Single precision
+        Shader::new("../glsl_shaders/mandelbrot.frag", "man_frag.spv"),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "man_frag_multibrot.spv", &[("FORMULA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "man_frag_burning_ship.spv", &[("FORMULA", "2")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "man_frag_tricorn.spv", &[("FORMULA", "3")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "man_frag_newton.spv", &[("FORMULA", "4")]),
+        //Double precision
+        Shader::new("../glsl_shaders/mandelbrot_f64.frag", "man_frag_f64.spv"),
+        Shader::with_defines("../glsl_shaders/mandelbrot_f64.frag", "man_frag_f64_multibrot.spv", &[("FORMULA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot_f64.frag", "man_frag_f64_burning_ship.spv", &[("FORMULA", "2")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot_f64.frag", "man_frag_f64_tricorn.spv", &[("FORMULA", "3")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot_f64.frag", "man_frag_f64_newton.spv", &[("FORMULA", "4")]),
+        //Perturbation, z² + c only
+        Shader::new("../glsl_shaders/mandelbrot_perturbation.frag", "man_frag_perturbation.spv"),
+        //Julia sets, single precision only
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "julia_frag.spv", &[("JULIA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "julia_frag_multibrot.spv", &[("JULIA", "1"), ("FORMULA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "julia_frag_burning_ship.spv", &[("JULIA", "1"), ("FORMULA", "2")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.frag", "julia_frag_tricorn.spv", &[("JULIA", "1"), ("FORMULA", "3")]),
+        //Compute renderer, single precision only
+        Shader::new("../glsl_shaders/colorize.frag", "colorize_frag.spv"),
+        Shader::new("../glsl_shaders/mandelbrot.comp", "man_comp.spv"),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "man_comp_multibrot.spv", &[("FORMULA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "man_comp_burning_ship.spv", &[("FORMULA", "2")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "man_comp_tricorn.spv", &[("FORMULA", "3")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "man_comp_newton.spv", &[("FORMULA", "4")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "julia_comp.spv", &[("JULIA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "julia_comp_multibrot.spv", &[("JULIA", "1"), ("FORMULA", "1")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "julia_comp_burning_ship.spv", &[("JULIA", "1"), ("FORMULA", "2")]),
+        Shader::with_defines("../glsl_shaders/mandelbrot.comp", "julia_comp_tricorn.spv", &[("JULIA", "1"), ("FORMULA", "3")]),
+    ]);
+}
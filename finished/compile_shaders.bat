@@ -1,2 +0,0 @@
-glslc ..\glsl_shaders\mandelbrot.vert -o .\src\man_vert.spv
-glslc ..\glsl_shaders\mandelbrot.frag -o .\src\man_frag.spv
\ No newline at end of file
@@ -0,0 +1,261 @@
+// Command line options. Everything that used to be a constant in main.rs or baked into the shaders.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use erupt::vk;
+use vulkan_bootstrap::{ContextOptions, DeviceSelector};
+
+use super::coloring::Coloring;
+use super::compute::Renderer;
+use super::deep_zoom::{DoubleDouble, Precision};
+use super::features::{DeviceFeatures, device_features};
+use super::formula::{Formula, Polynomial};
+use super::gpus::ListFormat;
+use super::hot_reload::GLSL_DIRECTORY;
+use super::julia::Layout;
+use super::supersampling::{SupersampleMode, Supersampling};
+
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(about = "Interactive Mandelbrot set viewer written with Vulkan")]
+pub struct Args {
+    /// Width of the window, or of the image when rendering to a file
+    #[arg(long, default_value_t = 800, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: u32,
+    /// Height of the window, or of the image when rendering to a file
+    #[arg(long, default_value_t = 800, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: u32,
+    /// Real part of the point in the middle of the view. Digits beyond what an f64 holds are kept for deep zooms
+    #[arg(long, default_value = "-0.55", allow_negative_numbers = true)]
+    pub center_re: DoubleDouble,
+    /// Imaginary part of the point in the middle of the view
+    #[arg(long, default_value = "0.55", allow_negative_numbers = true)]
+    pub center_im: DoubleDouble,
+    /// Magnification. At 1 the view is 2 units tall in the complex plane
+    #[arg(long, default_value_t = 10.0, value_parser = positive)]
+    pub zoom: f64,
+    /// Iterations before a point is considered part of the set
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+    /// Formula iterated, cycled with F
+    #[arg(long, value_enum, default_value_t = Formula::Mandelbrot)]
+    pub formula: Formula,
+    /// Exponent of the multibrot formula
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(2..=16))]
+    pub power: u32,
+    /// Real coefficients of the polynomial for the newton formula, highest degree first
+    #[arg(long, default_value = "1,0,0,-1", allow_hyphen_values = true)]
+    pub polynomial: Polynomial,
+    /// What the window shows, cycled with J. Clicking the Mandelbrot set picks the Julia set shown
+    #[arg(long, value_enum, default_value_t = Layout::Mandelbrot)]
+    pub layout: Layout,
+    /// Real part of the point the Julia set is drawn for
+    #[arg(long, default_value_t = -0.8, allow_negative_numbers = true)]
+    pub julia_re: f64,
+    /// Imaginary part of the point the Julia set is drawn for
+    #[arg(long, default_value_t = 0.156, allow_negative_numbers = true)]
+    pub julia_im: f64,
+    /// How points are colored, cycled with C
+    #[arg(long, value_enum, default_value_t = Coloring::Smooth)]
+    pub coloring: Coloring,
+    /// Palette file (.map, .ggr, .json or .csv) or directory of them, may be given several times. Cycled with P
+    #[arg(long, value_name = "PATH")]
+    pub palette: Vec<PathBuf>,
+    /// Speed of the zoom animation, toggled with space
+    #[arg(long, default_value_t = 0.1, value_parser = positive)]
+    pub speed: f64,
+    /// Preferred present mode, falls back to fifo if the surface does not support it
+    #[arg(long, value_enum, default_value_t = PresentMode::Mailbox)]
+    pub present_mode: PresentMode,
+    /// Arithmetic used by the fragment shader. By default the cheapest one that resolves the current zoom
+    #[arg(long, value_enum)]
+    pub precision: Option<Precision>,
+    /// How the fractal is computed. The compute renderer keeps the iterations when the palette or coloring changes, and shifts them when panning
+    #[arg(long, value_enum, default_value_t = Renderer::Graphics)]
+    pub renderer: Renderer,
+    /// Draw a coarse preview first and refine it in tiles over the following frames, keeping deep views responsive.
+    /// Implies the compute renderer
+    #[arg(long)]
+    pub progressive: bool,
+    /// Points averaged along each side of a pixel against aliasing on the boundary of the set, 1 for none. Costs factor² as much
+    #[arg(long, value_name = "FACTOR", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    pub supersample: u32,
+    /// How supersampling is done. MSAA needs the factor squared to be a sample count the device supports, e.g. 2 or 4
+    #[arg(long, value_enum, default_value_t = SupersampleMode::Msaa)]
+    pub supersample_mode: SupersampleMode,
+    /// Frames prepared on the CPU while the GPU still draws earlier ones. More keeps the GPU busier at the cost of input latency
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..=4))]
+    pub frames_in_flight: u32,
+    /// Write the timings and shader invocation counts of every frame drawn in the window to this CSV file, to compare drivers and settings
+    #[arg(long, value_name = "PATH")]
+    pub stats_csv: Option<PathBuf>,
+    /// Force the validation layers on or off. By default they are on in debug builds only
+    #[arg(long, value_name = "BOOL")]
+    pub validation: Option<bool>,
+    /// Least severe validation layer messages to log. RUST_LOG filters them further by type, e.g. RUST_LOG=vulkan::performance=off
+    #[arg(long, value_enum, value_name = "SEVERITY", default_value_t = MessageSeverity::Warning)]
+    pub vulkan_log: MessageSeverity,
+    /// Exit with an error after the first validation error instead of only logging it. For scripted runs that should fail on them
+    #[arg(long)]
+    pub fail_on_validation_error: bool,
+    /// GPU to use instead of picking the best one, by index or by part of its name. See --list-gpus
+    #[arg(long, value_name = "INDEX|NAME")]
+    pub gpu: Option<DeviceSelector>,
+    /// Print every GPU and what it supports, then exit
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text")]
+    pub list_gpus: Option<ListFormat>,
+    /// Recompile the shaders in this directory when they change, for development. Defaults to the repository's glsl_shaders
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = GLSL_DIRECTORY)]
+    pub watch_shaders: Option<PathBuf>,
+    /// Render a single frame to this PNG file without opening a window
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Render an image of any size to a file without opening a window, a tile at a time to keep memory use bounded.
+    /// The view options go before the command
+    RenderPoster {
+        /// PNG file to write, or TIFF if it ends in .tif or .tiff
+        output: PathBuf,
+        /// Width of the image
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        width: u32,
+        /// Height of the image
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        height: u32,
+        /// Pixels along each side of the tiles rendered at once
+        #[arg(long, default_value_t = 1024, value_parser = clap::value_parser!(u32).range(16..=4096))]
+        tile_size: u32,
+    },
+}
+//A finite number greater than zero
+fn positive(text: &str) -> Result<f64, String> {
+    let value: f64 = text.parse().map_err(|err| format!("{}", err))?;
+    if !(value.is_finite() && value > 0.0) {
+        return Err(format!("{} is not a number greater than zero", value));
+    }
+    Ok(value)
+}
+
+impl Args {
+    pub fn context_options(&self) -> ContextOptions {
+        let mut options = ContextOptions::default();
+        if let Some(validation) = self.validation {
+            options.validation = validation;
+        }
+        options.debug_messages.severity = self.vulkan_log.and_above();
+        options.debug_messages.fail_on_error = self.fail_on_validation_error;
+        options.device = self.gpu.clone();
+        let features = self.device_features();
+        options.required_features = features.required;
+        options.optional_features = features.optional;
+        options
+    }
+
+    pub fn device_features(&self) -> DeviceFeatures {
+        device_features(self.supersampling(), self.stats_csv.is_some())
+    }
+
+    pub fn supersampling(&self) -> Supersampling {
+        Supersampling{mode: self.supersample_mode, factor: self.supersample}
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        if self.progressive {Renderer::Compute} else {self.renderer}
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PresentMode {
+    Immediate,
+    Mailbox,
+    Fifo,
+    FifoRelaxed,
+}
+impl PresentMode {
+    pub fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE_KHR,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX_KHR,
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO_KHR,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED_KHR,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum MessageSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+impl MessageSeverity {
+    //The severities the debug messenger should report, this one and everything more severe
+    pub fn and_above(self) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+        [
+            (MessageSeverity::Verbose, vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE_EXT),
+            (MessageSeverity::Info, vk::DebugUtilsMessageSeverityFlagsEXT::INFO_EXT),
+            (MessageSeverity::Warning, vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT),
+            (MessageSeverity::Error, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT),
+        ].into_iter()
+            .filter(|(severity, _)| *severity >= self)
+            .fold(vk::DebugUtilsMessageSeverityFlagsEXT::empty(), |flags, (_, flag)| flags | flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn and_above_includes_the_more_severe() {
+        type Flags = vk::DebugUtilsMessageSeverityFlagsEXT;
+        assert_eq!(MessageSeverity::Verbose.and_above(), Flags::VERBOSE_EXT | Flags::INFO_EXT | Flags::WARNING_EXT | Flags::ERROR_EXT);
+        assert_eq!(MessageSeverity::Info.and_above(), Flags::INFO_EXT | Flags::WARNING_EXT | Flags::ERROR_EXT);
+        assert_eq!(MessageSeverity::Warning.and_above(), Flags::WARNING_EXT | Flags::ERROR_EXT);
+        assert_eq!(MessageSeverity::Error.and_above(), Flags::ERROR_EXT);
+    }
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("finished").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn empty_images_are_refused() {
+        assert!(parse(&["--width", "0"]).is_err());
+        assert!(parse(&["--height", "0"]).is_err());
+        assert!(parse(&["render-poster", "poster.png", "--width", "0", "--height", "100"]).is_err());
+        assert!(parse(&["render-poster", "poster.png", "--width", "100", "--height", "0"]).is_err());
+        assert!(parse(&["--width", "1", "--height", "1"]).is_ok());
+        assert!(parse(&["render-poster", "poster.png", "--width", "1", "--height", "1"]).is_ok());
+    }
+
+    #[test]
+    fn zoom_must_be_positive() {
+        for zoom in ["0", "-2", "nan", "inf", "x"] {
+            assert!(parse(&["--zoom", zoom]).is_err(), "--zoom {} was accepted", zoom);
+        }
+        assert_eq!(parse(&["--zoom", "0.5"]).unwrap().zoom, 0.5);
+    }
+
+    #[test]
+    fn speed_must_be_positive() {
+        for speed in ["0", "-0.1", "nan", "inf", "-inf"] {
+            assert!(parse(&["--speed", speed]).is_err(), "--speed {} was accepted", speed);
+        }
+        assert_eq!(parse(&["--speed", "0.25"]).unwrap().speed, 0.25);
+    }
+
+    #[test]
+    fn iterations_must_be_positive() {
+        for iterations in ["0", "-5", "1.5"] {
+            assert!(parse(&["--iterations", iterations]).is_err(), "--iterations {} was accepted", iterations);
+        }
+        assert_eq!(parse(&["--iterations", "1"]).unwrap().iterations, 1);
+    }
+}
@@ -0,0 +1,71 @@
+// Coloring algorithms. They all run in the fragment shaders, picked by the coloring push constant,
+// except the histogram equalization which needs the distribution of iteration counts over the whole view.
+// That is estimated here on a coarse grid of samples and handed to the shaders as a cumulative distribution.
+
+use super::deep_zoom::perturbed_smooth_iterations;
+
+//Escape radius of 256 rather than 2, otherwise the smooth iteration count visibly deviates from a smooth gradient.
+//Must match ESCAPE_RADIUS_SQUARED in the mandelbrot fragment shaders
+pub const ESCAPE_RADIUS_SQUARED: f64 = 65536.0;
+//Must match HISTOGRAM_BINS in the mandelbrot fragment shaders
+pub const HISTOGRAM_BINS: usize = 256;
+//Samples per side of the grid the histogram is estimated from
+const HISTOGRAM_GRID: usize = 48;
+
+//Values are passed as the coloring push constant and must match the COLORING_ constants in the shaders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Coloring {
+    /// Integer escape count, shows bands
+    EscapeTime = 0,
+    /// Normalized iteration count, continuous
+    Smooth = 1,
+    /// Smooth, with colors spread evenly over the pixels in view
+    Histogram = 2,
+    /// Shading by estimated distance to the set, brightest at the boundary
+    Distance = 3,
+    /// Closest approach of the orbit to the real and imaginary axes
+    OrbitTrap = 4,
+}
+impl Coloring {
+    const ALL: [Coloring; 5] = [Coloring::EscapeTime, Coloring::Smooth, Coloring::Histogram, Coloring::Distance, Coloring::OrbitTrap];
+
+    pub fn next(self) -> Coloring {
+        return Self::ALL[(self as usize + 1) % Self::ALL.len()];
+    }
+}
+
+//Normalized iteration count, continuous across the iteration bands (z_squared is |z|^2 after escaping)
+pub fn smooth_iterations(iterations: u32, z_squared: f64) -> f64 {
+    return iterations as f64 + 1.0 - (0.5 * z_squared.ln()).log2();
+}
+
+//Cumulative distribution of smooth iteration counts, normalized by max_iterations and divided in HISTOGRAM_BINS.
+//Sampled on a grid over the view with half size scale, through perturbation from the orbit of its center
+pub fn iteration_cdf(orbit: &[[f64; 2]], scale: [f64; 2], max_iterations: u32) -> Vec<f32> {
+    let mut histogram = vec![0u32; HISTOGRAM_BINS];
+    let mut escaped = 0;
+    for y in 0..HISTOGRAM_GRID {
+        for x in 0..HISTOGRAM_GRID {
+            //Sample pixel centers, -1 to 1 across the view
+            let ndc = [
+                (2.0 * x as f64 + 1.0) / HISTOGRAM_GRID as f64 - 1.0,
+                (2.0 * y as f64 + 1.0) / HISTOGRAM_GRID as f64 - 1.0,
+            ];
+            let dc = [ndc[0] * scale[0], ndc[1] * scale[1]];
+            if let Some(iterations) = perturbed_smooth_iterations(orbit, dc, max_iterations) {
+                let normalized = (iterations / max_iterations as f64).clamp(0.0, 1.0);
+                let bin = ((normalized * HISTOGRAM_BINS as f64) as usize).min(HISTOGRAM_BINS - 1);
+                histogram[bin] += 1;
+                escaped += 1;
+            }
+        }
+    }
+
+    let mut cdf = Vec::with_capacity(HISTOGRAM_BINS);
+    let mut sum = 0;
+    for count in histogram {
+        sum += count;
+        cdf.push(if escaped == 0 {0.0} else {sum as f32 / escaped as f32});
+    }
+    return cdf;
+}
@@ -0,0 +1,443 @@
+// Compute renderer, selected with --renderer compute. A compute shader (mandelbrot.comp) iterates every pixel once
+// and stores what the coloring needs in the sample buffer of ShaderData, then the render pass only colors the
+// samples (colorize.frag). Changing the palette or the coloring reuses the samples instead of iterating again,
+// and panning shifts them so only the pixels panned into view are iterated.
+// Single precision only, panes needing more precision are drawn by the graphics pipelines as before.
+
+use erupt::{vk, DeviceLoader};
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, GRAPHICS_Q_IDX};
+
+use std::os::raw::c_void;
+use std::mem::size_of;
+
+use super::{PushConstants, push_constant_stages};
+use super::shader_data::ShaderData;
+
+//Bytes per pixel in the sample buffer, must match Sample in mandelbrot.comp and colorize.frag
+pub const SAMPLE_SIZE: usize = 24;
+//Invocations along each side of a workgroup, must match local_size in mandelbrot.comp
+const WORKGROUP_SIZE: u32 = 8;
+//Progressive refinement: the coarse pass iterates one pixel per block of this many pixels along each side,
+//then tiles of this size are iterated at full resolution, a few with each frame
+const COARSE_STRIDE: u32 = 8;
+const TILE_SIZE: u32 = 64;
+//How far from whole pixels a pan may be and still reuse the samples, as the pixels kept are that far off
+const PAN_TOLERANCE: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Renderer {
+    /// Iterate in the fragment shader, every frame
+    Graphics,
+    /// Iterate in a compute shader only what the view change exposed, and color the stored iterations every frame
+    Compute,
+}
+impl Renderer {
+    //Compute dispatches are recorded on the graphics queue, so its family must support compute as well
+    pub fn available(self, context: &VulkanContext) -> Renderer {
+        if self == Renderer::Graphics {return self}
+        let families = unsafe {context.instance.get_physical_device_queue_family_properties(context.physical_device, None)};
+        let family = &families[context.queue_family_indices[GRAPHICS_Q_IDX] as usize];
+        if !family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
+            println!("The graphics queue does not support compute, using the graphics renderer instead");
+            return Renderer::Graphics;
+        }
+        return self;
+    }
+}
+
+//The compute pipeline and push constants iterating a pane, or a region of it
+pub type Pass = (vk::Pipeline, PushConstants);
+
+//Brings the samples up to date for passes, one per pane, in a submit of its own ahead of the render pass reading them,
+//which record orders it before. previous are the passes the samples were iterated with, see plan.
+//descriptor_set is the one of shader_data the dispatches read the formula parameters through.
+//The command buffer is freed with command_pool, which must outlive the submit
+#[allow(clippy::too_many_arguments)]
+pub fn iterate(
+    logical_device: &DeviceLoader,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    pipeline_layout: vk::PipelineLayout,
+    shader_data: &ShaderData,
+    descriptor_set: vk::DescriptorSet,
+    previous: &[Pass],
+    passes: &[Pass]
+) -> Result<(), VulkanAppError> {
+    let (shifts, dispatches) = plan(shader_data, previous, passes);
+    if shifts.is_empty() && dispatches.is_empty() {return Ok(())}
+
+    let command_buffer_allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+    let command_buffer = unsafe {logical_device.allocate_command_buffers(&command_buffer_allocate_info)}.map_err(VulkanAppError::vulkan("allocate command buffers"))?[0];
+
+    let command_buffer_begin_info = vk::CommandBufferBeginInfoBuilder::new()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {logical_device.begin_command_buffer(command_buffer, &command_buffer_begin_info)}.map_err(VulkanAppError::vulkan("begin command buffer"))?;
+    record(logical_device, command_buffer, pipeline_layout, shader_data, descriptor_set, &shifts, &dispatches);
+    unsafe {logical_device.end_command_buffer(command_buffer)}.map_err(VulkanAppError::vulkan("record command buffer"))?;
+
+    let cmd_buffers = [command_buffer];
+    let submits = [vk::SubmitInfoBuilder::new().command_buffers(&cmd_buffers)];
+    unsafe {logical_device.queue_submit(queue, &submits, vk::Fence::null())}.map_err(VulkanAppError::vulkan("submit to the queue"))?;
+    return Ok(());
+}
+
+//What it takes to go from samples iterated with previous to passes: copies shifting the samples of panes that were only
+//panned by whole pixels, and the passes iterating what is left, the newly exposed pixels of those and all of any other changed pane.
+//Panes that did not change are left as they are
+pub fn plan(shader_data: &ShaderData, previous: &[Pass], passes: &[Pass]) -> (Vec<vk::BufferCopyBuilder<'static>>, Vec<Pass>) {
+    let mut shifts = Vec::new();
+    let mut dispatches = Vec::new();
+    for (index, pass) in passes.iter().enumerate() {
+        let previous = previous.get(index);
+        if previous == Some(pass) {continue}
+        let offset = previous.and_then(|previous| pan_offset(previous, pass));
+        match (offset, shader_data.previous_samples()) {
+            (Some(offset), Some(_)) => {
+                shifts.extend(shifted_rows(&pass.1, offset));
+                dispatches.extend(exposed_regions(&pass.1, offset).into_iter().map(|push_constants| (pass.0, push_constants)));
+            },
+            _ => dispatches.push(*pass),
+        }
+    }
+    return (shifts, dispatches);
+}
+
+//Records copies shifting the samples, then dispatches, into command_buffer outside a render pass.
+//Barriers order them after whatever read or wrote the samples earlier in the queue, like the render passes of frames
+//still in flight, and before what reads them later, so the queue never has to be waited for
+pub fn record(
+    logical_device: &DeviceLoader,
+    command_buffer: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    shader_data: &ShaderData,
+    descriptor_set: vk::DescriptorSet,
+    shifts: &[vk::BufferCopyBuilder],
+    dispatches: &[Pass]
+) {
+    if shifts.is_empty() && dispatches.is_empty() {return}
+
+    let earlier_use = vk::MemoryBarrierBuilder::new()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE);
+    unsafe {
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[earlier_use], &[], &[]
+        );
+    }
+
+    //Copies can't overlap, so the samples are shifted by copying them out and back in place
+    if let (false, Some(previous_samples)) = (shifts.is_empty(), shader_data.previous_samples()) {
+        let whole_buffer = [vk::BufferCopyBuilder::new()
+            .src_offset(0)
+            .dst_offset(0)
+            .size((shader_data.sample_count() * SAMPLE_SIZE) as vk::DeviceSize)];
+        let copied_out = vk::MemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+        let copied_in = vk::MemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        unsafe {
+            logical_device.cmd_copy_buffer(command_buffer, shader_data.samples(), previous_samples, &whole_buffer);
+            logical_device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER, vk::DependencyFlags::empty(), &[copied_out], &[], &[]);
+            logical_device.cmd_copy_buffer(command_buffer, previous_samples, shader_data.samples(), shifts);
+            logical_device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[copied_in], &[], &[]);
+        }
+    }
+
+    unsafe {logical_device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline_layout, 0, &[descriptor_set], &[])};
+    for (pipeline, push_constants) in dispatches {
+        //One invocation per pixel of the region, or block when coarse, the shader skips the ones past its edges
+        let groups_x = push_constants.region_extent[0].div_ceil(push_constants.stride).div_ceil(WORKGROUP_SIZE);
+        let groups_y = push_constants.region_extent[1].div_ceil(push_constants.stride).div_ceil(WORKGROUP_SIZE);
+        unsafe {
+            logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, *pipeline);
+            logical_device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout,
+                push_constant_stages(),
+                0,
+                size_of::<PushConstants>() as u32,
+                push_constants as *const PushConstants as *const c_void
+            );
+            logical_device.cmd_dispatch(command_buffer, groups_x, groups_y, 1);
+        }
+    }
+    //The samples are read by the fragment shaders of later submissions, and copied by the next shift
+    let memory_barrier = vk::MemoryBarrierBuilder::new()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ);
+    unsafe {
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[memory_barrier], &[], &[]
+        );
+    }
+}
+
+//How many whole pixels the view of a pane was panned by, if that is all that changed:
+//the pixel at p now shows what the pixel at p + offset did. None if anything else changed,
+//or it was panned by a fraction of a pixel or further than the pane is wide
+fn pan_offset(previous: &Pass, current: &Pass) -> Option<[i64; 2]> {
+    let (previous_pipeline, previous) = previous;
+    let (pipeline, current) = current;
+    let mut panned = *previous;
+    panned.center = current.center;
+    if previous_pipeline != pipeline || panned != *current {return None}
+
+    let mut offset = [0; 2];
+    for (axis, offset) in offset.iter_mut().enumerate() {
+        let extent = current.pane_extent[axis] as f64;
+        //The center only has single precision, so pans by whole pixels come out slightly off
+        let pixels = (current.center[axis] as f64 - previous.center[axis] as f64) * extent / (2.0 * current.scale[axis] as f64);
+        let whole_pixels = pixels.round();
+        if (pixels - whole_pixels).abs() > PAN_TOLERANCE || whole_pixels.abs() >= extent {return None}
+        *offset = whole_pixels as i64;
+    }
+    return Some(offset);
+}
+
+//Copies from the previous samples moving the pixels of the pane that stay in view, one per row
+fn shifted_rows(push_constants: &PushConstants, offset: [i64; 2]) -> Vec<vk::BufferCopyBuilder<'static>> {
+    let [width, height] = push_constants.pane_extent.map(i64::from);
+    let [dx, dy] = offset;
+    let first_column = (-dx).max(0);
+    let kept_width = width - dx.abs();
+    let sample_offset = |x: i64, y: i64| {
+        let row = push_constants.pane_offset[1] as i64 + y;
+        let column = push_constants.pane_offset[0] as i64 + x;
+        return ((row * push_constants.image_width as i64 + column) as usize * SAMPLE_SIZE) as vk::DeviceSize;
+    };
+    return (0..height).filter(|y| (0..height).contains(&(y + dy))).map(|y| {
+        vk::BufferCopyBuilder::new()
+            .src_offset(sample_offset(first_column + dx, y + dy))
+            .dst_offset(sample_offset(first_column, y))
+            .size((kept_width as usize * SAMPLE_SIZE) as vk::DeviceSize)
+    }).collect();
+}
+
+//Push constants iterating the parts of the pane panned into view, the columns on one side and the rows on the top or bottom
+fn exposed_regions(push_constants: &PushConstants, offset: [i64; 2]) -> Vec<PushConstants> {
+    let [width, height] = push_constants.pane_extent.map(i64::from);
+    let [dx, dy] = offset;
+    let first_column = (-dx).max(0);
+    let kept_width = width - dx.abs();
+    let region = |offset: [i64; 2], extent: [i64; 2]| {
+        let mut push_constants = *push_constants;
+        push_constants.region_offset = offset.map(|value| value as u32);
+        push_constants.region_extent = extent.map(|value| value as u32);
+        return push_constants;
+    };
+
+    let mut regions = Vec::new();
+    if dx > 0 {
+        regions.push(region([width - dx, 0], [dx, height]));
+    } else if dx < 0 {
+        regions.push(region([0, 0], [-dx, height]));
+    }
+    if dy > 0 {
+        regions.push(region([first_column, height - dy], [kept_width, dy]));
+    } else if dy < 0 {
+        regions.push(region([first_column, 0], [kept_width, -dy]));
+    }
+    return regions;
+}
+
+//The region of pass iterated at one pixel per COARSE_STRIDE x COARSE_STRIDE block, a quick preview to refine
+pub fn coarse(pass: &Pass) -> Pass {
+    let (pipeline, mut push_constants) = *pass;
+    push_constants.stride = COARSE_STRIDE;
+    return (pipeline, push_constants);
+}
+
+//The region of pass split into tiles of at most TILE_SIZE x TILE_SIZE pixels, the ones nearest its middle first
+pub fn tiles(pass: &Pass) -> Vec<Pass> {
+    let (pipeline, push_constants) = *pass;
+    let [x0, y0] = push_constants.region_offset;
+    let [width, height] = push_constants.region_extent;
+    let mut tiles = Vec::new();
+    for y in (y0..y0 + height).step_by(TILE_SIZE as usize) {
+        for x in (x0..x0 + width).step_by(TILE_SIZE as usize) {
+            let mut tile = push_constants;
+            tile.region_offset = [x, y];
+            tile.region_extent = [TILE_SIZE.min(x0 + width - x), TILE_SIZE.min(y0 + height - y)];
+            tiles.push((pipeline, tile));
+        }
+    }
+    let middle = [x0 as f64 + width as f64 / 2.0, y0 as f64 + height as f64 / 2.0];
+    let distance = |(_, tile): &Pass| {
+        let tile_middle = [0, 1].map(|axis| tile.region_offset[axis] as f64 + tile.region_extent[axis] as f64 / 2.0);
+        return (tile_middle[0] - middle[0]).hypot(tile_middle[1] - middle[1]);
+    };
+    tiles.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+    return tiles;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_WIDTH: u32 = 100;
+    const IMAGE_HEIGHT: u32 = 40;
+
+    //A 40 x 30 pane of the image at (10, 2), with pixels 0.1 wide in the complex plane
+    fn pass() -> Pass {
+        let push_constants = PushConstants {
+            center: [-0.5, 0.25],
+            scale: [2.0, 1.5],
+            max_iterations: 256,
+            reference_length: 0,
+            coloring: 0,
+            supersample: 1,
+            center_f64: [0.0, 0.0],
+            julia_c: [0.0, 0.0],
+            pane_offset: [10, 2],
+            pane_extent: [40, 30],
+            image_width: IMAGE_WIDTH,
+            formula: 0,
+            region_offset: [0, 0],
+            region_extent: [40, 30],
+            stride: 1,
+            _padding_end: 0,
+        };
+        return (vk::Pipeline(1), push_constants);
+    }
+
+    fn panned(pixels: [f32; 2]) -> Pass {
+        let (pipeline, mut push_constants) = pass();
+        push_constants.center = [0, 1].map(|axis| {
+            push_constants.center[axis] + pixels[axis] * 2.0 * push_constants.scale[axis] / push_constants.pane_extent[axis] as f32
+        });
+        return (pipeline, push_constants);
+    }
+
+    //How many times each pixel of the image is iterated by passes
+    fn coverage(passes: &[Pass]) -> Vec<u32> {
+        let mut counts = vec![0; (IMAGE_WIDTH * IMAGE_HEIGHT) as usize];
+        for (_, push_constants) in passes {
+            let [x0, y0] = [0, 1].map(|axis| push_constants.pane_offset[axis] + push_constants.region_offset[axis]);
+            for y in y0..y0 + push_constants.region_extent[1] {
+                for x in x0..x0 + push_constants.region_extent[0] {
+                    counts[(y * IMAGE_WIDTH + x) as usize] += 1;
+                }
+            }
+        }
+        return counts;
+    }
+
+    #[test]
+    fn whole_pixel_pans_are_found() {
+        assert_eq!(pan_offset(&pass(), &pass()), Some([0, 0]));
+        assert_eq!(pan_offset(&pass(), &panned([3.0, -2.0])), Some([3, -2]));
+        assert_eq!(pan_offset(&pass(), &panned([-39.0, 29.0])), Some([-39, 29]));
+    }
+
+    #[test]
+    fn other_changes_are_not_pans() {
+        assert_eq!(pan_offset(&pass(), &panned([0.5, 0.0])), None);
+        assert_eq!(pan_offset(&pass(), &panned([2.0, 1.25])), None);
+        let (pipeline, mut zoomed) = panned([1.0, 0.0]);
+        zoomed.scale[0] *= 0.5;
+        assert_eq!(pan_offset(&pass(), &(pipeline, zoomed)), None);
+        assert_eq!(pan_offset(&pass(), &(vk::Pipeline(2), pass().1)), None);
+    }
+
+    #[test]
+    fn pans_past_the_pane_are_not_shifted() {
+        for pixels in [[40.0, 0.0], [-40.0, 0.0], [0.0, 30.0], [0.0, -30.0], [100.0, 100.0]] {
+            assert_eq!(pan_offset(&pass(), &panned(pixels)), None, "{:?}", pixels);
+        }
+    }
+
+    #[test]
+    fn shifted_and_exposed_pixels_cover_the_pane_once() {
+        let (_, push_constants) = pass();
+        let [width, height] = push_constants.pane_extent.map(i64::from);
+        let [left, top] = push_constants.pane_offset.map(i64::from);
+        let offsets = [[0, 0], [3, 0], [-3, 0], [0, 5], [0, -5], [7, -4], [-7, 4], [39, 29], [-39, -29]];
+        for offset in offsets {
+            //Every sample of the previous image holds its own index, the copies move them into the current one
+            let previous: Vec<usize> = (0..(IMAGE_WIDTH * IMAGE_HEIGHT) as usize).collect();
+            let mut current = vec![usize::MAX; previous.len()];
+            for copy in shifted_rows(&push_constants, offset) {
+                assert_eq!(copy.size as usize % SAMPLE_SIZE, 0);
+                let [src, dst, count] = [copy.src_offset, copy.dst_offset, copy.size].map(|bytes| bytes as usize / SAMPLE_SIZE);
+                current[dst..dst + count].copy_from_slice(&previous[src..src + count]);
+            }
+            let exposed = exposed_regions(&push_constants, offset).into_iter().map(|region| (vk::Pipeline(1), region)).collect::<Vec<Pass>>();
+            let iterated = coverage(&exposed);
+
+            for y in 0..IMAGE_HEIGHT as i64 {
+                for x in 0..IMAGE_WIDTH as i64 {
+                    let index = (y * IMAGE_WIDTH as i64 + x) as usize;
+                    let [pane_x, pane_y] = [x - left, y - top];
+                    let in_pane = (0..width).contains(&pane_x) && (0..height).contains(&pane_y);
+                    let [source_x, source_y] = [pane_x + offset[0], pane_y + offset[1]];
+                    let kept = in_pane && (0..width).contains(&source_x) && (0..height).contains(&source_y);
+                    if kept {
+                        assert_eq!(current[index], ((source_y + top) * IMAGE_WIDTH as i64 + source_x + left) as usize, "offset {:?} at ({}, {})", offset, x, y);
+                        assert_eq!(iterated[index], 0, "offset {:?} at ({}, {})", offset, x, y);
+                    } else {
+                        assert_eq!(current[index], usize::MAX, "offset {:?} at ({}, {})", offset, x, y);
+                        assert_eq!(iterated[index], in_pane as u32, "offset {:?} at ({}, {})", offset, x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_cover_regions_of_any_size_once() {
+        for (region_offset, region_extent) in [([0, 0], [40, 30]), ([5, 3], [35, 27]), ([0, 0], [1, 1]), ([3, 0], [37, 30])] {
+            let (pipeline, mut push_constants) = pass();
+            push_constants.region_offset = region_offset;
+            push_constants.region_extent = region_extent;
+            let tiles = tiles(&(pipeline, push_constants));
+            let region = coverage(&[(pipeline, push_constants)]);
+            assert_eq!(coverage(&tiles), region);
+            for (tile_pipeline, tile) in &tiles {
+                assert_eq!(*tile_pipeline, pipeline);
+                assert!(tile.region_extent[0] <= TILE_SIZE && tile.region_extent[1] <= TILE_SIZE);
+                assert_eq!(tile.stride, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_of_larger_images_start_in_the_middle() {
+        let (pipeline, mut push_constants) = pass();
+        push_constants.pane_extent = [1000, 700];
+        push_constants.region_extent = [1000, 700];
+        let tiles = tiles(&(pipeline, push_constants));
+        //16 x 11 tiles, the last column 40 pixels wide and the last row 60 high
+        assert_eq!(tiles.len(), 16 * 11);
+        let area: u32 = tiles.iter().map(|(_, tile)| tile.region_extent[0] * tile.region_extent[1]).sum();
+        assert_eq!(area, 1000 * 700);
+        assert!(tiles.iter().any(|(_, tile)| tile.region_offset == [960, 640] && tile.region_extent == [40, 60]));
+        let (_, first) = tiles[0];
+        assert!(first.region_offset[0] <= 500 && 500 <= first.region_offset[0] + first.region_extent[0]);
+        assert!(first.region_offset[1] <= 350 && 350 <= first.region_offset[1] + first.region_extent[1]);
+        let (_, last) = tiles[tiles.len() - 1];
+        assert!([0, 960].contains(&last.region_offset[0]) && [0, 640].contains(&last.region_offset[1]));
+    }
+
+    #[test]
+    fn coarse_passes_keep_the_region() {
+        let (pipeline, push_constants) = pass();
+        let (coarse_pipeline, coarse) = coarse(&(pipeline, push_constants));
+        assert_eq!(coarse_pipeline, pipeline);
+        assert_eq!(coarse.stride, COARSE_STRIDE);
+        assert_eq!(coarse.region_offset, push_constants.region_offset);
+        assert_eq!(coarse.region_extent, push_constants.region_extent);
+    }
+}
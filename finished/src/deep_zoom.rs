@@ -0,0 +1,291 @@
+// Deep zoom support. Single precision floats run out of digits at a zoom of about 1e4, doubles at about 1e12.
+// Beyond that the perturbation renderer takes over: the orbit of the view center is computed here on the CPU
+// in double-double precision, and the fragment shader only iterates the small offset of each pixel from it.
+
+use std::ops::{Add, Sub, Mul, Div, Neg};
+use std::str::FromStr;
+
+use super::coloring::{smooth_iterations, ESCAPE_RADIUS_SQUARED};
+
+const SINGLE_MAX_ZOOM: f64 = 1e4;
+const DOUBLE_MAX_ZOOM: f64 = 1e12;
+//Double-double has about 32 significant digits, leave a few for telling neighbouring pixels apart
+pub const MAX_ZOOM: f64 = 1e28;
+
+
+//// Double-double arithmetic
+//An unevaluated sum hi + lo of two doubles with |lo| <= ulp(hi)/2, giving about twice the precision of an f64.
+//Algorithms from Hida, Li & Bailey, "Library for Double-Double and Quad-Double Arithmetic"
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+impl DoubleDouble {
+    pub const ZERO: DoubleDouble = DoubleDouble{hi: 0.0, lo: 0.0};
+
+    //Sum of two doubles and its rounding error, assuming |a| >= |b|
+    fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+        let s = a + b;
+        return DoubleDouble{hi: s, lo: b - (s - a)};
+    }
+    fn two_sum(a: f64, b: f64) -> DoubleDouble {
+        let s = a + b;
+        let bb = s - a;
+        return DoubleDouble{hi: s, lo: (a - (s - bb)) + (b - bb)};
+    }
+    fn two_prod(a: f64, b: f64) -> DoubleDouble {
+        let p = a * b;
+        return DoubleDouble{hi: p, lo: a.mul_add(b, -p)};
+    }
+}
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        return DoubleDouble{hi: value, lo: 0.0};
+    }
+}
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+    fn neg(self) -> DoubleDouble {
+        return DoubleDouble{hi: -self.hi, lo: -self.lo};
+    }
+}
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+    fn add(self, rhs: DoubleDouble) -> DoubleDouble {
+        let s = Self::two_sum(self.hi, rhs.hi);
+        let t = Self::two_sum(self.lo, rhs.lo);
+        let s = Self::quick_two_sum(s.hi, s.lo + t.hi);
+        return Self::quick_two_sum(s.hi, s.lo + t.lo);
+    }
+}
+impl Add<f64> for DoubleDouble {
+    type Output = DoubleDouble;
+    fn add(self, rhs: f64) -> DoubleDouble {
+        return self + DoubleDouble::from(rhs);
+    }
+}
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+    fn sub(self, rhs: DoubleDouble) -> DoubleDouble {
+        return self + -rhs;
+    }
+}
+impl Sub<f64> for DoubleDouble {
+    type Output = DoubleDouble;
+    fn sub(self, rhs: f64) -> DoubleDouble {
+        return self + DoubleDouble::from(-rhs);
+    }
+}
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+    fn mul(self, rhs: DoubleDouble) -> DoubleDouble {
+        let p = Self::two_prod(self.hi, rhs.hi);
+        return Self::quick_two_sum(p.hi, p.lo + (self.hi * rhs.lo + self.lo * rhs.hi));
+    }
+}
+impl Mul<f64> for DoubleDouble {
+    type Output = DoubleDouble;
+    fn mul(self, rhs: f64) -> DoubleDouble {
+        let p = Self::two_prod(self.hi, rhs);
+        return Self::quick_two_sum(p.hi, p.lo + self.lo * rhs);
+    }
+}
+impl Div<f64> for DoubleDouble {
+    type Output = DoubleDouble;
+    fn div(self, rhs: f64) -> DoubleDouble {
+        let q1 = self.hi / rhs;
+        let remainder = self - Self::two_prod(q1, rhs);
+        return Self::quick_two_sum(q1, remainder.hi / rhs);
+    }
+}
+//Parses decimal numbers like "-0.743643887037158704752191506114774" without going through an f64,
+//so coordinates deeper than f64 can resolve can be given on the command line
+impl FromStr for DoubleDouble {
+    type Err = String;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid number: {}", string);
+        let (mantissa, mut exponent) = match string.find(['e', 'E']) {
+            Some(i) => (&string[..i], string[i+1..].parse::<i32>().map_err(|_| invalid())?),
+            None => (string, 0),
+        };
+        let (negative, digits) = match mantissa.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+
+        let mut value = DoubleDouble::ZERO;
+        let mut seen_point = false;
+        let mut seen_digit = false;
+        for character in digits.chars() {
+            match character {
+                '0'..='9' => {
+                    value = value * 10.0 + (character as u8 - b'0') as f64;
+                    seen_digit = true;
+                    if seen_point {exponent -= 1}
+                },
+                '.' if !seen_point => seen_point = true,
+                _ => return Err(invalid()),
+            }
+        }
+        if !seen_digit {return Err(invalid())}
+
+        //One power of ten at a time, as 10^-n is not exactly representable
+        for _ in 0..exponent.max(0) {value = value * 10.0}
+        for _ in exponent.min(0)..0 {value = value / 10.0}
+        if negative {value = -value}
+        return Ok(value);
+    }
+}
+
+
+//// Picking a renderer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Precision {
+    Single,
+    Double,
+    Perturbation,
+}
+impl Precision {
+    //The cheapest precision that can still resolve the view, unless one is forced.
+    //Double needs the shaderFloat64 device feature, perturbation is used instead where it is missing.
+    //Perturbation only exists for z² + c, other formulas get the most precise of the rest
+    pub fn choose(forced: Option<Precision>, zoom: f64, double_supported: bool, perturbation_supported: bool) -> Precision {
+        let precision = match forced {
+            Some(Precision::Double) if !double_supported => Precision::Perturbation,
+            Some(precision) => precision,
+            None if zoom < SINGLE_MAX_ZOOM => Precision::Single,
+            None if zoom < DOUBLE_MAX_ZOOM && double_supported => Precision::Double,
+            None => Precision::Perturbation,
+        };
+        if precision == Precision::Perturbation && !perturbation_supported {
+            return if double_supported {Precision::Double} else {Precision::Single}
+        }
+        return precision;
+    }
+}
+
+
+//// Reference orbit
+//Z_0 = 0, Z_1, ... of the point c, up to and including the first point outside the escape radius.
+//Rounded to f64 as only the offsets from it need to be precise, and those are relative to the view size
+pub fn reference_orbit(c: [DoubleDouble; 2], max_iterations: u32) -> Vec<[f64; 2]> {
+    let mut orbit = Vec::with_capacity(max_iterations as usize + 1);
+    let mut z = [DoubleDouble::ZERO; 2];
+    orbit.push([0.0, 0.0]);
+    for _ in 0..max_iterations {
+        let z_re_squared = z[0] * z[0];
+        let z_im_squared = z[1] * z[1];
+        z = [z_re_squared - z_im_squared + c[0], z[0] * z[1] * 2.0 + c[1]];
+        orbit.push([z[0].hi, z[1].hi]);
+        if z[0].hi * z[0].hi + z[1].hi * z[1].hi > ESCAPE_RADIUS_SQUARED {break}
+    }
+    return orbit;
+}
+
+//Smooth iteration count of the point dc away from the reference point, or None if it doesn't escape.
+//Same perturbation and rebasing as the perturbation shader, but in f64
+pub fn perturbed_smooth_iterations(orbit: &[[f64; 2]], dc: [f64; 2], max_iterations: u32) -> Option<f64> {
+    let last_ref = orbit.len() - 1;
+    let mut dz = [0.0, 0.0];
+    let mut ref_i = 0;
+    for i in 0..max_iterations {
+        let a = [2.0*orbit[ref_i][0] + dz[0], 2.0*orbit[ref_i][1] + dz[1]];
+        dz = [a[0]*dz[0] - a[1]*dz[1] + dc[0], a[0]*dz[1] + a[1]*dz[0] + dc[1]];
+        ref_i += 1;
+        let z = [orbit[ref_i][0] + dz[0], orbit[ref_i][1] + dz[1]];
+        let z_squared = z[0]*z[0] + z[1]*z[1];
+        if z_squared > ESCAPE_RADIUS_SQUARED {
+            return Some(smooth_iterations(i + 1, z_squared))
+        }
+        if z_squared < dz[0]*dz[0] + dz[1]*dz[1] || ref_i == last_ref {
+            dz = z;
+            ref_i = 0;
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(string: &str) -> DoubleDouble {
+        return string.parse().unwrap();
+    }
+
+    //|a - b|, rounded to an f64 once the difference is small enough for that
+    fn distance(a: DoubleDouble, b: DoubleDouble) -> f64 {
+        let difference = a - b;
+        return (difference.hi + difference.lo).abs();
+    }
+
+    #[test]
+    fn long_decimals_keep_the_digits_past_f64() {
+        let one_and_a_bit = parse("1.000000000000000000000000000001");
+        assert_eq!(one_and_a_bit.hi, 1.0);
+        assert!(((one_and_a_bit - 1.0).hi - 1e-30).abs() < 1e-31, "{:?}", one_and_a_bit);
+
+        let string = "-0.743643887037158704752191506114774";
+        let center = parse(string);
+        assert!((center.hi - string.parse::<f64>().unwrap()).abs() <= f64::EPSILON);
+        let tail = center - parse("-0.7436438870371587");
+        assert!((tail.hi - -4.752191506114774e-18).abs() < 1e-30);
+    }
+
+    #[test]
+    fn signs_and_exponents() {
+        assert_eq!(parse("2.5E3"), DoubleDouble::from(2500.0));
+        assert_eq!(parse("+42"), DoubleDouble::from(42.0));
+        assert_eq!(parse("-.5"), DoubleDouble::from(-0.5));
+        assert_eq!(parse("7."), DoubleDouble::from(7.0));
+        assert!(distance(parse("1.5e-20"), parse("0.000000000000000000015")) < 1e-50);
+        assert!(distance(parse("-123456789.123456789e-9"), parse("-0.123456789123456789")) < 1e-32);
+    }
+
+    #[test]
+    fn malformed_numbers_are_refused() {
+        for string in ["", "-", ".", "1.2.3", "1e", "1e5x", "abc", "--1", "1,5"] {
+            assert_eq!(string.parse::<DoubleDouble>(), Err(format!("invalid number: {}", string)));
+        }
+    }
+
+    #[test]
+    fn arithmetic_keeps_about_32_digits() {
+        let tiny = 1e-10;
+        let product = (DoubleDouble::from(1.0) + tiny) * (DoubleDouble::from(1.0) - tiny);
+        assert_eq!(product.hi, 1.0);
+        assert!(distance(product, DoubleDouble::from(1.0) - 1e-20) < 1e-31);
+
+        let third = DoubleDouble::from(1.0) / 3.0;
+        assert!(distance(third * 3.0, DoubleDouble::from(1.0)) < 1e-31);
+        assert!(distance(third * DoubleDouble::from(3.0), DoubleDouble::from(1.0)) < 1e-31);
+
+        let x = parse("0.123456789012345678901234567890");
+        let nudged = x + 1e-25;
+        assert!(((nudged - x).hi - 1e-25).abs() < 1e-32);
+        assert_eq!(-(-x), x);
+        assert_eq!(x - x, DoubleDouble::ZERO);
+    }
+
+    #[test]
+    fn cheapest_precision_resolving_the_zoom() {
+        assert_eq!(Precision::choose(None, 1.0, true, true), Precision::Single);
+        assert_eq!(Precision::choose(None, 1e6, true, true), Precision::Double);
+        assert_eq!(Precision::choose(None, 1e6, false, true), Precision::Perturbation);
+        assert_eq!(Precision::choose(None, 1e15, true, true), Precision::Perturbation);
+    }
+
+    #[test]
+    fn forced_precision_falls_back_where_unsupported() {
+        assert_eq!(Precision::choose(Some(Precision::Single), 1e15, true, true), Precision::Single);
+        assert_eq!(Precision::choose(Some(Precision::Double), 1.0, true, true), Precision::Double);
+        assert_eq!(Precision::choose(Some(Precision::Double), 1.0, false, true), Precision::Perturbation);
+        assert_eq!(Precision::choose(Some(Precision::Perturbation), 1.0, true, true), Precision::Perturbation);
+        //Formulas without perturbation get the most precise of the rest
+        assert_eq!(Precision::choose(None, 1e15, true, false), Precision::Double);
+        assert_eq!(Precision::choose(None, 1e15, false, false), Precision::Single);
+        assert_eq!(Precision::choose(Some(Precision::Double), 1.0, false, false), Precision::Single);
+        assert_eq!(Precision::choose(Some(Precision::Perturbation), 1.0, true, false), Precision::Double);
+    }
+}
@@ -0,0 +1,32 @@
+// Device features the renderer uses, the required and the optional ones in one place. Devices without a required
+// feature are passed over, optional ones are enabled on the device used if it has them and checked for before use.
+
+use erupt::vk;
+use vulkan_bootstrap::VulkanContext;
+
+use super::supersampling::{SupersampleMode, Supersampling};
+
+pub struct DeviceFeatures {
+    pub required: vk::PhysicalDeviceFeatures,
+    pub optional: vk::PhysicalDeviceFeatures,
+}
+
+//Features for drawing with supersampling, and counting pipeline statistics if asked to (see queries.rs)
+pub fn device_features(supersampling: Supersampling, pipeline_statistics: bool) -> DeviceFeatures {
+    let required = vk::PhysicalDeviceFeatures::default(); //The pipelines need none beyond core Vulkan
+    let mut optional = vk::PhysicalDeviceFeatures {
+        shader_float64: vk::TRUE, //Only used when zoomed in far enough, see supports_double
+        ..Default::default()
+    };
+    if pipeline_statistics { //Shader invocations per frame
+        optional.pipeline_statistics_query = vk::TRUE;
+    }
+    if supersampling.mode == SupersampleMode::Msaa {
+        optional.sample_rate_shading = vk::TRUE;
+    }
+    return DeviceFeatures{required, optional};
+}
+
+pub fn supports_double(context: &VulkanContext) -> bool {
+    return context.enabled_features.shader_float64 == vk::TRUE;
+}
@@ -0,0 +1,289 @@
+// Fractal formulas beyond z² + c. Each is compiled into its own fragment shader variant from the same GLSL sources,
+// selected with the FORMULA define (see build.rs), and the pipelines for them are created when first drawn.
+// The parameters the user picks, the Multibrot exponent and the Newton polynomial, are handed to the shaders in a uniform buffer.
+
+use std::str::FromStr;
+
+use super::deep_zoom::Precision;
+
+//Must match MAX_DEGREE in the mandelbrot fragment shaders
+pub const MAX_DEGREE: usize = 8;
+//Durand-Kerner iterations for the roots of the Newton polynomial, it converges long before for any sensible polynomial
+const ROOT_ITERATIONS: usize = 500;
+
+//Values must match the FORMULA_ defines in the mandelbrot fragment shaders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Formula {
+    /// z² + c
+    Mandelbrot = 0,
+    /// zⁿ + c, with n given by --power
+    Multibrot = 1,
+    /// (|re z| + i|im z|)² + c
+    BurningShip = 2,
+    /// conj(z)² + c
+    Tricorn = 3,
+    /// Newton's method on the polynomial given by --polynomial, colored by the root each point converges to
+    Newton = 4,
+}
+impl Formula {
+    const ALL: [Formula; 5] = [Formula::Mandelbrot, Formula::Multibrot, Formula::BurningShip, Formula::Tricorn, Formula::Newton];
+
+    pub fn next(self) -> Formula {
+        return Self::ALL[(self as usize + 1) % Self::ALL.len()];
+    }
+
+    //The reference orbit, and so perturbation and histogram coloring, is only computed for z² + c
+    pub fn is_quadratic(self) -> bool {
+        return self == Formula::Mandelbrot;
+    }
+}
+
+//Which fragment shader draws a pane
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderVariant {
+    pub formula: Formula,
+    pub precision: Precision,
+    pub julia: bool, //Julia sets are only drawn in single precision
+}
+impl ShaderVariant {
+    //GLSL file in glsl_shaders/ and defines the fragment shader is compiled from, must match build.rs
+    pub fn source(self) -> (&'static str, Vec<(&'static str, &'static str)>) {
+        let file = match self.precision {
+            Precision::Single => "mandelbrot.frag",
+            Precision::Double => "mandelbrot_f64.frag",
+            Precision::Perturbation => "mandelbrot_perturbation.frag",
+        };
+        return (file, self.defines());
+    }
+
+    //Defines selecting the variant in mandelbrot.frag, mandelbrot_f64.frag and mandelbrot.comp
+    pub fn defines(self) -> Vec<(&'static str, &'static str)> {
+        const FORMULA_VALUES: [&str; 5] = ["0", "1", "2", "3", "4"];
+        let mut defines = Vec::new();
+        if self.julia && self.formula != Formula::Newton {
+            defines.push(("JULIA", "1"));
+        }
+        if self.formula != Formula::Mandelbrot {
+            defines.push(("FORMULA", FORMULA_VALUES[self.formula as usize]));
+        }
+        return defines;
+    }
+
+    //Iteration pass of the compute renderer, which is single precision only
+    pub fn compute_shader(self) -> &'static [u8] {
+        return match (self.julia, self.formula) {
+            (_, Formula::Newton) => shader!("man_comp_newton.spv"),
+            (false, Formula::Mandelbrot) => shader!("man_comp.spv"),
+            (false, Formula::Multibrot) => shader!("man_comp_multibrot.spv"),
+            (false, Formula::BurningShip) => shader!("man_comp_burning_ship.spv"),
+            (false, Formula::Tricorn) => shader!("man_comp_tricorn.spv"),
+            (true, Formula::Mandelbrot) => shader!("julia_comp.spv"),
+            (true, Formula::Multibrot) => shader!("julia_comp_multibrot.spv"),
+            (true, Formula::BurningShip) => shader!("julia_comp_burning_ship.spv"),
+            (true, Formula::Tricorn) => shader!("julia_comp_tricorn.spv"),
+        }
+    }
+
+    pub fn fragment_shader(self) -> &'static [u8] {
+        return match (self.precision, self.julia, self.formula) {
+            (_, true, Formula::Mandelbrot) => shader!("julia_frag.spv"),
+            (_, true, Formula::Multibrot) => shader!("julia_frag_multibrot.spv"),
+            (_, true, Formula::BurningShip) => shader!("julia_frag_burning_ship.spv"),
+            (_, true, Formula::Tricorn) => shader!("julia_frag_tricorn.spv"),
+            //Newton does not use c, so its Julia sets are all the same
+            (Precision::Single, _, Formula::Newton) => shader!("man_frag_newton.spv"),
+            (Precision::Single, false, Formula::Mandelbrot) => shader!("man_frag.spv"),
+            (Precision::Single, false, Formula::Multibrot) => shader!("man_frag_multibrot.spv"),
+            (Precision::Single, false, Formula::BurningShip) => shader!("man_frag_burning_ship.spv"),
+            (Precision::Single, false, Formula::Tricorn) => shader!("man_frag_tricorn.spv"),
+            (Precision::Double, _, Formula::Newton) => shader!("man_frag_f64_newton.spv"),
+            (Precision::Double, false, Formula::Mandelbrot) => shader!("man_frag_f64.spv"),
+            (Precision::Double, false, Formula::Multibrot) => shader!("man_frag_f64_multibrot.spv"),
+            (Precision::Double, false, Formula::BurningShip) => shader!("man_frag_f64_burning_ship.spv"),
+            (Precision::Double, false, Formula::Tricorn) => shader!("man_frag_f64_tricorn.spv"),
+            (Precision::Perturbation, false, Formula::Mandelbrot) => shader!("man_frag_perturbation.spv"),
+            (Precision::Perturbation, _, formula) => panic!("No perturbation shader for {:?}!", formula),
+        }
+    }
+}
+
+
+//// Parameters
+//Polynomial for the Newton fractal, parsed from its real coefficients highest degree first, "1,0,0,-1" is z³ - 1
+#[derive(Clone, Debug)]
+pub struct Polynomial {
+    coefficients: Vec<f64>, //Lowest degree first
+}
+impl FromStr for Polynomial {
+    type Err = String;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut coefficients = string.split(',')
+            .map(|coefficient| coefficient.trim().parse::<f64>().map_err(|_| format!("invalid coefficient: {}", coefficient)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        coefficients.reverse();
+        while coefficients.last() == Some(&0.0) {coefficients.pop();}
+        if coefficients.len() < 2 || coefficients.len() > MAX_DEGREE + 1 {
+            return Err(format!("degree must be between 1 and {}", MAX_DEGREE))
+        }
+        return Ok(Polynomial{coefficients});
+    }
+}
+impl Polynomial {
+    fn degree(&self) -> usize {
+        return self.coefficients.len() - 1;
+    }
+
+    //All complex roots at once with the Durand-Kerner method
+    fn roots(&self) -> Vec<[f64; 2]> {
+        let leading = self.coefficients[self.degree()];
+        let monic: Vec<[f64; 2]> = self.coefficients.iter().map(|coefficient| [coefficient / leading, 0.0]).collect();
+        //Starting points spread on a spiral, none of them real or symmetric to each other
+        let mut roots: Vec<[f64; 2]> = (0..self.degree()).map(|k| complex_pow([0.4, 0.9], k as u32)).collect();
+        for _ in 0..ROOT_ITERATIONS {
+            for k in 0..roots.len() {
+                let mut denominator = [1.0, 0.0];
+                for j in 0..roots.len() {
+                    if j != k {
+                        denominator = complex_mul(denominator, [roots[k][0] - roots[j][0], roots[k][1] - roots[j][1]]);
+                    }
+                }
+                let step = complex_div(evaluate(&monic, roots[k]), denominator);
+                roots[k] = [roots[k][0] - step[0], roots[k][1] - step[1]];
+            }
+        }
+        return roots;
+    }
+}
+
+//Layout must match the FormulaParameters block in the mandelbrot fragment shaders (std140)
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FormulaParameters {
+    coefficients: [[f32; 4]; MAX_DEGREE + 1], //Complex in .xy, padded to vec4
+    roots: [[f32; 4]; MAX_DEGREE],
+    power: u32,
+    degree: u32,
+    _padding: [u32; 2],
+}
+impl FormulaParameters {
+    pub fn new(power: u32, polynomial: &Polynomial) -> FormulaParameters {
+        let mut parameters = FormulaParameters {
+            coefficients: [[0.0; 4]; MAX_DEGREE + 1],
+            roots: [[0.0; 4]; MAX_DEGREE],
+            power,
+            degree: polynomial.degree() as u32,
+            _padding: [0; 2],
+        };
+        for (k, coefficient) in polynomial.coefficients.iter().enumerate() {
+            parameters.coefficients[k][0] = *coefficient as f32;
+        }
+        for (k, root) in polynomial.roots().iter().enumerate() {
+            parameters.roots[k] = [root[0] as f32, root[1] as f32, 0.0, 0.0];
+        }
+        return parameters;
+    }
+}
+
+
+//// Complex helpers
+fn complex_mul(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    return [a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]];
+}
+fn complex_div(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    let norm = b[0]*b[0] + b[1]*b[1];
+    return [(a[0]*b[0] + a[1]*b[1]) / norm, (a[1]*b[0] - a[0]*b[1]) / norm];
+}
+fn complex_pow(z: [f64; 2], n: u32) -> [f64; 2] {
+    return (0..n).fold([1.0, 0.0], |power, _| complex_mul(power, z));
+}
+//Horner's scheme, coefficients lowest degree first
+fn evaluate(coefficients: &[[f64; 2]], z: [f64; 2]) -> [f64; 2] {
+    return coefficients.iter().rev().fold([0.0, 0.0], |sum, coefficient| {
+        let product = complex_mul(sum, z);
+        [product[0] + coefficient[0], product[1] + coefficient[1]]
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polynomial(string: &str) -> Polynomial {
+        return string.parse().unwrap();
+    }
+
+    //Every expected root is found exactly once, in any order
+    fn assert_roots(found: &[[f64; 2]], expected: &[[f64; 2]]) {
+        assert_eq!(found.len(), expected.len());
+        let mut unmatched = found.to_vec();
+        for root in expected {
+            let closest = (0..unmatched.len())
+                .min_by(|&a, &b| distance(unmatched[a], *root).total_cmp(&distance(unmatched[b], *root)))
+                .unwrap();
+            assert!(distance(unmatched[closest], *root) < 1e-9, "{:?} not among the roots {:?}", root, found);
+            unmatched.swap_remove(closest);
+        }
+    }
+
+    fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+        return (a[0] - b[0]).hypot(a[1] - b[1]);
+    }
+
+    #[test]
+    fn coefficients_are_given_highest_degree_first() {
+        let cubic = polynomial("1,0,0,-1");
+        assert_eq!(cubic.coefficients, vec![-1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(cubic.degree(), 3);
+        assert_eq!(polynomial(" 2 , -3.5 ").coefficients, vec![-3.5, 2.0]);
+        //Leading zeros don't count towards the degree
+        assert_eq!(polynomial("0,0,1,-1").degree(), 1);
+    }
+
+    #[test]
+    fn malformed_polynomials_are_refused() {
+        assert_eq!("1,x,2".parse::<Polynomial>().err(), Some("invalid coefficient: x".to_string()));
+        assert_eq!("1,,2".parse::<Polynomial>().err(), Some("invalid coefficient: ".to_string()));
+        let degree = format!("degree must be between 1 and {}", MAX_DEGREE);
+        assert_eq!("5".parse::<Polynomial>().err(), Some(degree.clone()));
+        assert_eq!("0,3".parse::<Polynomial>().err(), Some(degree.clone()));
+        assert_eq!(["1"; MAX_DEGREE + 2].join(",").parse::<Polynomial>().err(), Some(degree));
+        assert!(["1"; MAX_DEGREE + 1].join(",").parse::<Polynomial>().is_ok());
+    }
+
+    #[test]
+    fn roots_of_unity() {
+        let half_sqrt_3 = 3f64.sqrt() / 2.0;
+        assert_roots(&polynomial("1,0,0,-1").roots(), &[[1.0, 0.0], [-0.5, half_sqrt_3], [-0.5, -half_sqrt_3]]);
+    }
+
+    #[test]
+    fn roots_of_non_monic_polynomials() {
+        assert_roots(&polynomial("2,0,-8").roots(), &[[2.0, 0.0], [-2.0, 0.0]]);
+        assert_roots(&polynomial("1,0,1").roots(), &[[0.0, 1.0], [0.0, -1.0]]);
+        assert_roots(&polynomial("3,-6").roots(), &[[2.0, 0.0]]);
+    }
+
+    #[test]
+    fn parameters_hold_the_coefficients_and_roots() {
+        let parameters = FormulaParameters::new(5, &polynomial("1,0,0,-1"));
+        assert_eq!(parameters.power, 5);
+        assert_eq!(parameters.degree, 3);
+        assert_eq!(parameters.coefficients[0], [-1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(parameters.coefficients[3], [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(parameters.coefficients[4], [0.0; 4]);
+        let roots: Vec<[f64; 2]> = parameters.roots[..3].iter().map(|root| [root[0] as f64, root[1] as f64]).collect();
+        for root in roots {
+            assert!((distance(root, [0.0, 0.0]) - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(parameters.roots[3], [0.0; 4]);
+    }
+
+    #[test]
+    fn next_visits_every_formula() {
+        let mut formula = Formula::Mandelbrot;
+        for expected in Formula::ALL.iter().cycle().skip(1).take(Formula::ALL.len()) {
+            formula = formula.next();
+            assert_eq!(formula, *expected);
+        }
+    }
+}
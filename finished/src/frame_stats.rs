@@ -0,0 +1,192 @@
+// Frame timing: the time between frames, the CPU time spent on each, the GPU time of its render pass (see queries.rs)
+// and the latency from the start of the frame until it is queued for presentation. Averaged over the last frames
+// for the window title, and optionally written to a CSV file a frame per row to compare drivers and settings.
+// The GPU time of a frame is only known once its fence has signalled, so frames are completed when their slot comes round again.
+// Failing to write the CSV file is logged and ends the file there, it isn't worth stopping the viewer for.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use super::queries::PipelineStatistics;
+
+const STATS_WINDOW: usize = 120; //Frames the statistics in the title are taken over
+pub const TITLE_INTERVAL: Duration = Duration::from_millis(500); //Between updates of the title, any faster is unreadable
+
+//Timings of one frame
+#[derive(Clone, Copy, Debug)]
+struct FrameTiming {
+    frame_time: Option<Duration>, //Since the previous frame was presented, None for the first one after the loop was idle
+    cpu_time: Duration, //Spent updating, recording and submitting, without waiting for the GPU or swapchain
+    latency: Duration, //From the start of the frame until it was queued for presentation, waits included
+    gpu_time: Option<Duration>, //Of the render pass, None if the queue has no timestamps
+    statistics: Option<PipelineStatistics>, //None without pipeline statistics queries
+}
+
+pub struct FrameStats {
+    recent: VecDeque<FrameTiming>,
+    pending: Vec<Option<FrameTiming>>, //Per frame in flight, presented but waiting for the GPU time
+    last_present: Option<Instant>,
+    frame_number: u64,
+    csv: Option<BufWriter<File>>,
+}
+impl FrameStats {
+    //csv_file is created by the caller, so failing to create it is reported before anything else is done
+    pub fn new(frames_in_flight: usize, csv_file: Option<File>) -> FrameStats {
+        let mut stats = FrameStats{recent: VecDeque::with_capacity(STATS_WINDOW), pending: vec![None; frames_in_flight], last_present: None, frame_number: 0, csv: csv_file.map(BufWriter::new)};
+        stats.write_csv(|csv| writeln!(csv, "frame,frame_time_ms,cpu_time_ms,gpu_time_ms,latency_ms,vertex_invocations,clipping_primitives,fragment_invocations"));
+        return stats;
+    }
+
+    //Writes to the CSV file if there is one, giving up on it at the first failure
+    fn write_csv(&mut self, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) {
+        let Some(csv) = &mut self.csv else {return};
+        if let Err(err) = write(csv) {
+            log::warn!("Could not write the statistics file, no more frames will be written to it: {}", err);
+            self.csv = None;
+        }
+    }
+
+    //The frame in slot frame, started at start, was queued for presentation after cpu_time of work
+    pub fn presented(&mut self, frame: usize, start: Instant, cpu_time: Duration) {
+        let now = Instant::now();
+        let frame_time = self.last_present.map(|last_present| now - last_present);
+        self.last_present = Some(now);
+        self.pending[frame] = Some(FrameTiming{frame_time, cpu_time, latency: now - start, gpu_time: None, statistics: None});
+    }
+
+    //The GPU is done with the frame in slot frame, whose render pass took gpu_time
+    pub fn finished(&mut self, frame: usize, gpu_time: Option<Duration>, statistics: Option<PipelineStatistics>) {
+        let Some(mut timing) = self.pending[frame].take() else {return};
+        timing.gpu_time = gpu_time;
+        timing.statistics = statistics;
+        let milliseconds = |duration: Option<Duration>| duration.map(|duration| format!("{:.3}", duration.as_secs_f64() * 1000.0)).unwrap_or_default();
+        let statistics = match timing.statistics {
+            Some(statistics) => format!("{},{},{}", statistics.vertex_invocations, statistics.clipping_primitives, statistics.fragment_invocations),
+            None => ",,".to_string(),
+        };
+        let frame_number = self.frame_number;
+        self.write_csv(|csv| {
+            writeln!(csv, "{},{},{},{},{},{}", frame_number, milliseconds(timing.frame_time), milliseconds(Some(timing.cpu_time)), milliseconds(timing.gpu_time), milliseconds(Some(timing.latency)), statistics)
+        });
+        self.frame_number += 1;
+        if self.recent.len() == STATS_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(timing);
+    }
+
+    //Nothing is drawn until the next event, which shouldn't count as a slow frame
+    pub fn pause(&mut self) {
+        self.last_present = None;
+    }
+
+    //Averages of the recent frames for the window title, empty if there are none yet
+    pub fn summary(&self) -> String {
+        if self.recent.is_empty() {return String::new()}
+        let mean = |durations: Vec<Duration>| {
+            if durations.is_empty() {return None}
+            Some(durations.iter().sum::<Duration>().as_secs_f64() / durations.len() as f64)
+        };
+        let frame_time = mean(self.recent.iter().filter_map(|timing| timing.frame_time).collect());
+        let cpu_time = mean(self.recent.iter().map(|timing| timing.cpu_time).collect()).unwrap();
+        let gpu_time = mean(self.recent.iter().filter_map(|timing| timing.gpu_time).collect());
+        let latency = mean(self.recent.iter().map(|timing| timing.latency).collect()).unwrap();
+
+        let mut summary = match frame_time {
+            Some(frame_time) => format!("{:.1} fps, ", 1.0 / frame_time),
+            None => String::new(),
+        };
+        summary += &format!("cpu {:.2} ms", cpu_time * 1000.0);
+        if let Some(gpu_time) = gpu_time {
+            summary += &format!(", gpu {:.2} ms", gpu_time * 1000.0);
+        }
+        summary += &format!(", latency {:.1} ms", latency * 1000.0);
+        return summary;
+    }
+}
+impl Drop for FrameStats {
+    fn drop(&mut self) {
+        self.write_csv(|csv| csv.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn milliseconds(milliseconds: u64) -> Duration {
+        return Duration::from_millis(milliseconds);
+    }
+
+    #[test]
+    fn frames_complete_in_the_order_their_slots_finish() {
+        let path = std::env::temp_dir().join(format!("frame_stats_test_{}.csv", std::process::id()));
+        let mut stats = FrameStats::new(3, Some(File::create(&path).unwrap()));
+        let start = Instant::now() - milliseconds(40);
+        stats.presented(0, start, milliseconds(1));
+        stats.presented(1, start, milliseconds(2));
+        stats.presented(2, start, milliseconds(3));
+        assert_eq!(stats.summary(), "");
+
+        let statistics = PipelineStatistics{vertex_invocations: 4, clipping_primitives: 2, fragment_invocations: 1000};
+        stats.finished(1, Some(milliseconds(5)), Some(statistics));
+        stats.finished(0, None, None);
+        stats.finished(0, Some(milliseconds(9)), None); //Already completed, nothing new was presented in the slot
+        stats.finished(2, Some(milliseconds(7)), None);
+
+        let summary = stats.summary();
+        assert!(summary.contains(" fps, "), "{}", summary);
+        assert!(summary.contains("cpu 2.00 ms, gpu 6.00 ms, latency "), "{}", summary);
+        drop(stats);
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "frame,frame_time_ms,cpu_time_ms,gpu_time_ms,latency_ms,vertex_invocations,clipping_primitives,fragment_invocations");
+        assert_eq!(lines.len(), 4);
+        let rows: Vec<Vec<&str>> = lines[1..].iter().map(|line| line.split(',').collect()).collect();
+        for (frame, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), 8, "{:?}", row);
+            assert_eq!(row[0], frame.to_string());
+            assert!(row[4].parse::<f64>().unwrap() >= 40.0, "{:?}", row);
+        }
+        //Slot 1 finished first. It was presented after slot 0, so it has a frame time
+        assert!(!rows[0][1].is_empty());
+        assert_eq!(rows[0][2..4], ["2.000", "5.000"]);
+        assert_eq!(rows[0][5..], ["4", "2", "1000"]);
+        //Slot 0 was the first frame presented, and has neither GPU time nor statistics
+        assert_eq!(rows[1][1..4], ["", "1.000", ""]);
+        assert!(lines[2].ends_with(",,,"), "{}", lines[2]);
+        assert_eq!(rows[2][2..4], ["3.000", "7.000"]);
+        assert!(lines[3].ends_with(",,"), "{}", lines[3]);
+    }
+
+    #[test]
+    fn pausing_leaves_out_the_idle_time() {
+        let mut stats = FrameStats::new(1, None);
+        stats.presented(0, Instant::now(), milliseconds(1));
+        stats.finished(0, None, None);
+        stats.pause();
+        stats.presented(0, Instant::now(), milliseconds(1));
+        stats.finished(0, None, None);
+        assert_eq!(stats.summary().split(", ").next(), Some("cpu 1.00 ms"));
+
+        stats.presented(0, Instant::now(), milliseconds(1));
+        stats.finished(0, None, None);
+        assert!(stats.summary().contains(" fps, cpu 1.00 ms, latency "), "{}", stats.summary());
+    }
+
+    #[test]
+    fn only_the_recent_frames_are_averaged() {
+        let mut stats = FrameStats::new(2, None);
+        for frame in 0..STATS_WINDOW + 10 {
+            let cpu_time = if frame < 10 {milliseconds(1000)} else {milliseconds(3)};
+            stats.presented(frame % 2, Instant::now(), cpu_time);
+            stats.finished(frame % 2, Some(milliseconds(2)), None);
+        }
+        assert_eq!(stats.recent.len(), STATS_WINDOW);
+        assert!(stats.summary().contains("cpu 3.00 ms, gpu 2.00 ms"), "{}", stats.summary());
+    }
+}
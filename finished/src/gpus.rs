@@ -0,0 +1,90 @@
+// --list-gpus: every physical device and what it supports, as text or as JSON for bug reports.
+// Surface formats, present modes and present support need a window surface, so a hidden window is made when
+// there is a display to make it on. Without one, e.g. over ssh, those are left out.
+
+use erupt::vk;
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+use vulkan_bootstrap::VulkanAppError;
+use vulkan_bootstrap::report::{DeviceReport, report_devices};
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    Text,
+    Json,
+}
+
+//Devices lacking required_features are listed as unsuitable
+pub fn list_gpus(format: ListFormat, required_features: &vk::PhysicalDeviceFeatures) -> Result<(), VulkanAppError> {
+    //winit panics instead of returning an error when there is no display server, so check first
+    let has_display = ["DISPLAY", "WAYLAND_DISPLAY"].iter().any(|variable| std::env::var_os(variable).is_some()) || cfg!(not(unix));
+    let reports = if has_display {
+        let event_loop = EventLoop::new();
+        let window = WindowBuilder::new().with_visible(false).build(&event_loop).expect("Window build failed!");
+        report_devices(Some(&window), "Mandelbrot by Kristian Knudsen", required_features)?
+    } else {
+        report_devices(None, "Mandelbrot by Kristian Knudsen", required_features)?
+    };
+
+    match format {
+        ListFormat::Text => {
+            if reports.is_empty() {println!("No devices with Vulkan support")}
+            for report in &reports {
+                print_report(report, has_display);
+            }
+        },
+        ListFormat::Json => {
+            let devices: Vec<_> = reports.iter().map(report_json).collect();
+            println!("{}", serde_json::to_string_pretty(&devices).unwrap());
+        },
+    }
+    return Ok(());
+}
+
+fn print_report(report: &DeviceReport, has_surface: bool) {
+    let default = if report.default {", picked by default"} else {""};
+    let suitable = if report.suitable {""} else {", unsuitable"};
+    println!("GPU {}: {} ({:?}{}{})", report.index, report.name, report.device_type, default, suitable);
+    println!("  Vulkan {}, driver {}, vendor 0x{:04x}, device 0x{:04x}, score {}",
+        report.api_version_string(), report.driver_version_string(), report.vendor_id, report.device_id, report.score);
+    println!("  Queue families:");
+    for (index, family) in report.queue_families.iter().enumerate() {
+        let present = if family.present {", present"} else {""};
+        println!("    {}: {} x {:?}{}", index, family.count, family.flags, present);
+    }
+    if has_surface {
+        let formats: Vec<String> = report.surface_formats.iter().map(|format| format!("{:?} {:?}", format.format, format.color_space)).collect();
+        let present_modes: Vec<String> = report.present_modes.iter().map(|mode| format!("{:?}", mode)).collect();
+        println!("  Surface formats: {}", formats.join(", "));
+        println!("  Present modes: {}", present_modes.join(", "));
+    }
+    println!("  Extensions ({}): {}", report.extensions.len(), report.extensions.join(", "));
+}
+
+fn report_json(report: &DeviceReport) -> serde_json::Value {
+    return json!({
+        "index": report.index,
+        "name": report.name,
+        "type": format!("{:?}", report.device_type),
+        "vendor_id": report.vendor_id,
+        "device_id": report.device_id,
+        "api_version": report.api_version_string(),
+        "driver_version": report.driver_version_string(),
+        "driver_version_raw": report.driver_version,
+        "score": report.score,
+        "suitable": report.suitable,
+        "default": report.default,
+        "queue_families": report.queue_families.iter().map(|family| json!({
+            "flags": format!("{:?}", family.flags),
+            "count": family.count,
+            "present": family.present,
+        })).collect::<Vec<_>>(),
+        "surface_formats": report.surface_formats.iter().map(|format| json!({
+            "format": format!("{:?}", format.format),
+            "color_space": format!("{:?}", format.color_space),
+        })).collect::<Vec<_>>(),
+        "present_modes": report.present_modes.iter().map(|mode| format!("{:?}", mode)).collect::<Vec<_>>(),
+        "extensions": report.extensions,
+    });
+}
@@ -0,0 +1,64 @@
+// Shader hot reloading for development, enabled with --watch-shaders.
+// The GLSL sources are polled for changes, and the pipelines using a changed one rebuilt from shaders compiled at runtime,
+// see Pipelines::reload. Shaders that fail to compile leave the old pipelines in place.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+//Where the sources are in the repository, the default for --watch-shaders
+pub const GLSL_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../glsl_shaders");
+//Checking the modification times is cheap, but there is no need to do it every frame
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+impl ShaderWatcher {
+    pub fn new(directory: &Path) -> ShaderWatcher {
+        if !directory.is_dir() {
+            eprintln!("Shader directory {} not found, nothing will be reloaded", directory.display());
+        } else {
+            println!("Watching {} for shader changes", directory.display());
+        }
+        return ShaderWatcher {
+            directory: directory.to_path_buf(),
+            modified: modification_times(directory),
+            last_poll: Instant::now(),
+        };
+    }
+
+    //Paths of the sources changed or added since the last call
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {return Vec::new()}
+        self.last_poll = Instant::now();
+
+        let modified = modification_times(&self.directory);
+        let changed = modified.iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.modified = modified;
+        return changed;
+    }
+
+    //When changed will next look at the files, for waking up an otherwise idle event loop
+    pub fn next_poll(&self) -> Instant {
+        return self.last_poll + POLL_INTERVAL;
+    }
+}
+
+fn modification_times(directory: &Path) -> HashMap<PathBuf, SystemTime> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return HashMap::new(),
+    };
+    return entries.filter_map(|entry| {
+        let path = entry.ok()?.path();
+        let modified = fs::metadata(&path).ok()?.modified().ok()?;
+        Some((path, modified))
+    }).collect();
+}
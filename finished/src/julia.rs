@@ -0,0 +1,110 @@
+// Julia sets. Every point c of the complex plane has one, found by iterating z² + c from each pixel instead of from 0.
+// c is picked by clicking the Mandelbrot view, and the Julia set of it shown instead of or beside the Mandelbrot set.
+// Only single precision is implemented, so Julia sets pixelate at zooms the Mandelbrot view handles fine.
+
+use erupt::vk;
+
+//Shows the whole Julia set of any c in the Mandelbrot set, which lies within radius 2
+pub const JULIA_ZOOM: f64 = 0.5;
+//Pixels the cursor may move between press and release for it to count as a click rather than a drag
+pub const CLICK_DISTANCE: f64 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fractal {
+    Mandelbrot,
+    Julia,
+}
+
+//What the window shows, cycled with J
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// Only the Mandelbrot set
+    Mandelbrot,
+    /// Only the Julia set of the chosen point
+    Julia,
+    /// The Mandelbrot set on the left, the Julia set on the right
+    SideBySide,
+}
+impl Layout {
+    const ALL: [Layout; 3] = [Layout::Mandelbrot, Layout::Julia, Layout::SideBySide];
+
+    pub fn next(self) -> Layout {
+        return Self::ALL[(self as usize + 1) % Self::ALL.len()];
+    }
+
+    //The fractals shown and the part of the window each is drawn into
+    pub fn panes(self, extent: vk::Extent2D) -> Vec<(Fractal, vk::Rect2D)> {
+        let whole = vk::Rect2D{offset: vk::Offset2D{x: 0, y: 0}, extent};
+        return match self {
+            Layout::Mandelbrot => vec![(Fractal::Mandelbrot, whole)],
+            Layout::Julia => vec![(Fractal::Julia, whole)],
+            Layout::SideBySide => {
+                let left_width = extent.width / 2;
+                vec![
+                    (Fractal::Mandelbrot, vk::Rect2D{offset: vk::Offset2D{x: 0, y: 0}, extent: vk::Extent2D{width: left_width, height: extent.height}}),
+                    (Fractal::Julia, vk::Rect2D{offset: vk::Offset2D{x: left_width as i32, y: 0}, extent: vk::Extent2D{width: extent.width - left_width, height: extent.height}}),
+                ]
+            },
+        }
+    }
+
+    //The pane under the pixel (x, y) of the window, and the pixel relative to the pane
+    pub fn pane_at(self, x: f64, y: f64, extent: vk::Extent2D) -> (Fractal, vk::Extent2D, f64, f64) {
+        let panes = self.panes(extent);
+        let (fractal, area) = panes.iter()
+            .find(|(_, area)| x < (area.offset.x as u32 + area.extent.width) as f64)
+            .unwrap_or(&panes[panes.len() - 1]);
+        return (*fractal, area.extent, x - area.offset.x as f64, y - area.offset.y as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_panes_fill_the_window() {
+        let extent = vk::Extent2D{width: 801, height: 600};
+        for (layout, fractal) in [(Layout::Mandelbrot, Fractal::Mandelbrot), (Layout::Julia, Fractal::Julia)] {
+            let whole = vk::Rect2D{offset: vk::Offset2D{x: 0, y: 0}, extent};
+            assert_eq!(layout.panes(extent), vec![(fractal, whole)]);
+            assert_eq!(layout.pane_at(800.5, 10.0, extent), (fractal, extent, 800.5, 10.0));
+        }
+    }
+
+    #[test]
+    fn side_by_side_panes_tile_odd_and_even_widths() {
+        for width in [1, 2, 3, 4, 5, 800, 801] {
+            let extent = vk::Extent2D{width, height: 7};
+            let panes = Layout::SideBySide.panes(extent);
+            let [(left_fractal, left), (right_fractal, right)] = panes[..] else {panic!("Expected two panes, got {:?}", panes)};
+            assert_eq!((left_fractal, right_fractal), (Fractal::Mandelbrot, Fractal::Julia));
+            assert_eq!(left.offset, vk::Offset2D{x: 0, y: 0});
+            assert_eq!(right.offset, vk::Offset2D{x: left.extent.width as i32, y: 0});
+            assert_eq!(left.extent.width + right.extent.width, width);
+            assert!(right.extent.width - left.extent.width <= 1);
+            assert_eq!((left.extent.height, right.extent.height), (7, 7));
+        }
+    }
+
+    #[test]
+    fn boundary_pixels_pick_the_pane_they_are_in() {
+        let extent = vk::Extent2D{width: 801, height: 600};
+        let left_extent = vk::Extent2D{width: 400, height: 600};
+        let right_extent = vk::Extent2D{width: 401, height: 600};
+        assert_eq!(Layout::SideBySide.pane_at(0.0, 5.0, extent), (Fractal::Mandelbrot, left_extent, 0.0, 5.0));
+        assert_eq!(Layout::SideBySide.pane_at(399.5, 5.0, extent), (Fractal::Mandelbrot, left_extent, 399.5, 5.0));
+        assert_eq!(Layout::SideBySide.pane_at(400.0, 5.0, extent), (Fractal::Julia, right_extent, 0.0, 5.0));
+        assert_eq!(Layout::SideBySide.pane_at(800.5, 599.5, extent), (Fractal::Julia, right_extent, 400.5, 599.5));
+        //Past the right edge, like the cursor of a drag leaving the window
+        assert_eq!(Layout::SideBySide.pane_at(900.0, 5.0, extent), (Fractal::Julia, right_extent, 500.0, 5.0));
+
+        //Every pixel of a three pixel wide window
+        let extent = vk::Extent2D{width: 3, height: 1};
+        let picked: Vec<(Fractal, f64)> = (0..3).map(|x| {
+            let (fractal, _, pane_x, _) = Layout::SideBySide.pane_at(x as f64 + 0.5, 0.5, extent);
+            (fractal, pane_x)
+        }).collect();
+        assert_eq!(picked, vec![(Fractal::Mandelbrot, 0.5), (Fractal::Julia, 0.5), (Fractal::Julia, 1.5)]);
+    }
+}
@@ -1,352 +1,793 @@
-use winit::event::{Event, WindowEvent, VirtualKeyCode};
+use winit::event::{Event, WindowEvent, VirtualKeyCode, ElementState, MouseButton, MouseScrollDelta};
 use winit::window::{Window, WindowBuilder};
 use winit::event_loop::{EventLoop, ControlFlow};
 
-use erupt::{vk, {EntryLoader, InstanceLoader, DeviceLoader}, {ExtendableFrom, SmallVec}, utils::{surface}, cstr};
+use erupt::{vk, DeviceLoader, SmallVec};
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, ContextOptions, query_swap_chain_support, GRAPHICS_Q_IDX, PRESENT_Q_IDX};
+use vulkan_bootstrap::frames::FrameRing;
 
-use std::ffi::{CString, CStr};
-use std::os::raw::{c_char, c_void};
-use std::collections::HashSet;
+use clap::Parser;
+
+//SPIR-V compiled from glsl_shaders/ by build.rs. Defined before the modules so they can use it too
+macro_rules! shader {
+    ($file:literal) => {include_bytes!(concat!(env!("OUT_DIR"), "/", $file))};
+}
+
+mod cli;
+mod coloring;
+mod compute;
+mod deep_zoom;
+mod features;
+mod formula;
+mod frame_stats;
+mod gpus;
+mod hot_reload;
+mod julia;
+mod offscreen;
+mod palette;
+mod poster;
+mod queries;
+mod shader_data;
+mod supersampling;
+
+use coloring::Coloring;
+use compute::Renderer;
+use deep_zoom::{DoubleDouble, Precision, MAX_ZOOM};
+use features::supports_double;
+use formula::{Formula, FormulaParameters, ShaderVariant};
+use frame_stats::{FrameStats, TITLE_INTERVAL};
+use hot_reload::ShaderWatcher;
+use julia::{Fractal, Layout, JULIA_ZOOM, CLICK_DISTANCE};
+use palette::Palette;
+use queries::{Queries, QueryResults};
+use shader_data::ShaderData;
+use supersampling::{Supersampling, MultisampleImage};
+
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CString;
+use std::fs::File;
+use std::path::PathBuf;
+use std::os::raw::c_void;
 use std::mem::size_of;
 use std::time;
 
-const HEIGHT: u32 = 800;
-const WIDTH: u32 = 800;
 const APP_TITLE: &str = "Mandelbrot in Vulkan - Kristian Knudsen";
 
 
-// Shaders
-const VERT_SHADER: &[u8] = include_bytes!("man_vert.spv");
-const FRAG_SHADER: &[u8] = include_bytes!("man_frag.spv");
+// Shaders, the fragment shaders are picked per formula in formula.rs
+const VERT_SHADER: &[u8] = shader!("man_vert.spv");
+const VERT_SOURCE: &str = "mandelbrot.vert"; //In glsl_shaders/, for hot reloading
+const COLORIZE_SHADER: &[u8] = shader!("colorize_frag.spv"); //Compute renderer, see compute.rs
+const COLORIZE_SOURCE: &str = "colorize.frag";
+const COMPUTE_SOURCE: &str = "mandelbrot.comp";
+
+
+const RENDER_PASS_REGION: &str = "render pass"; //Timed every frame, see queries.rs
+const ITERATE_REGION: &str = "iterate"; //Compute renderer, in frames after the view changed
+const REFINE_REGION: &str = "refine"; //Progressive mode, in frames with tiles to refine
+//GPU time per frame spent iterating tiles in progressive mode, the rest is left for drawing
+const REFINE_TIME_PER_FRAME: time::Duration = time::Duration::from_millis(12);
+
+// Navigation
+const ZOOM_STEP: f64 = 1.25; //Zoom factor per scroll wheel notch or +/- press
+const PAN_STEP: f64 = 0.1; //Fraction of the window moved per arrow key press
+const PIXELS_PER_SCROLL_LINE: f64 = 50.0; //Touchpads scroll in pixels rather than notches
+
+
+//The part of the complex plane being looked at, independent of the size of the window
+#[derive(Clone, Copy, Debug)]
+struct View {
+    center: [DoubleDouble; 2], //More precise than any shader, see deep_zoom
+    zoom: f64, //At zoom 1 the view is 2 units tall
+    max_iterations: u32,
+    coloring: Coloring,
+    formula: Formula,
+}
+impl View {
+    //Histogram coloring needs the reference orbit, which only z² + c has
+    fn coloring(&self) -> Coloring {
+        if self.coloring == Coloring::Histogram && !self.formula.is_quadratic() {
+            return Coloring::Smooth
+        }
+        return self.coloring;
+    }
+
+    //Half the width and height of the view in the complex plane, wider than tall if the window is
+    fn scale(&self, extent: vk::Extent2D) -> [f64; 2] {
+        let half_height = 1.0 / self.zoom;
+        let aspect_ratio = extent.width as f64 / extent.height as f64;
+        return [half_height * aspect_ratio, half_height];
+    }
+
+    //Offset from the center of the point under a pixel, counted from the top left corner like winit does
+    fn pixel_offset(&self, x: f64, y: f64, extent: vk::Extent2D) -> [f64; 2] {
+        let scale = self.scale(extent);
+        let ndc_x = 2.0 * x / extent.width as f64 - 1.0;
+        let ndc_y = 2.0 * y / extent.height as f64 - 1.0;
+        return [ndc_x * scale[0], ndc_y * scale[1]];
+    }
+
+    //Moves the view such that the image follows the cursor when dragged by (dx, dy) pixels
+    fn pan_pixels(&mut self, dx: f64, dy: f64, extent: vk::Extent2D) {
+        let scale = self.scale(extent);
+        self.center[0] = self.center[0] - 2.0 * dx / extent.width as f64 * scale[0];
+        self.center[1] = self.center[1] - 2.0 * dy / extent.height as f64 * scale[1];
+    }
+
+    //Zooms by factor while keeping the point under pixel (x, y) in the same place on screen
+    fn zoom_at(&mut self, factor: f64, x: f64, y: f64, extent: vk::Extent2D) {
+        let offset = self.pixel_offset(x, y, extent);
+        let factor = (self.zoom * factor).min(MAX_ZOOM) / self.zoom;
+        self.center[0] = self.center[0] + offset[0] * (1.0 - 1.0 / factor);
+        self.center[1] = self.center[1] + offset[1] * (1.0 - 1.0 / factor);
+        self.zoom *= factor;
+    }
+}
+
+//Everything drawn in the window
+#[derive(Clone, Copy, Debug)]
+struct Scene {
+    view: View, //Of the Mandelbrot set
+    julia_view: View, //Of the Julia set, only its center and zoom are used as it is drawn like the Mandelbrot set
+    julia_c: [f64; 2],
+    layout: Layout,
+}
+impl Scene {
+    fn view_mut(&mut self, fractal: Fractal) -> &mut View {
+        return match fractal {
+            Fractal::Mandelbrot => &mut self.view,
+            Fractal::Julia => &mut self.julia_view,
+        }
+    }
+}
+
+//Layout must match the push constant block declared in the mandelbrot and julia shaders,
+//though shaders leave out the members at the end they don't use
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PushConstants {
+    center: [f32; 2], //Zero when the fragment shader adds the center itself at higher precision
+    scale: [f32; 2], //Half the width and height of the view in the complex plane
+    max_iterations: u32,
+    reference_length: u32, //Entries in the reference orbit, perturbation only
+    coloring: u32,
+    supersample: u32, //Points along each side of a pixel the fragment shader iterates, see supersampling.rs. Also puts dvec2 at a multiple of 16 bytes
+    center_f64: [f64; 2], //Double precision only
+    julia_c: [f32; 2], //Julia only
+    pane_offset: [u32; 2], //Compute renderer only, where the pane is in the sample buffer
+    pane_extent: [u32; 2], //Compute renderer only
+    image_width: u32, //Compute renderer only, samples per row
+    formula: u32, //Compute renderer only, colorize.frag is the same for every formula
+    region_offset: [u32; 2], //Compute renderer only, the part of the pane iterated, in pixels from its corner
+    region_extent: [u32; 2], //Compute renderer only
+    stride: u32, //Compute renderer only, above 1 for a coarse pass iterating one pixel per block of stride x stride
+    _padding_end: u32,
+}
+impl PushConstants {
+    fn new(view: &View, extent: vk::Extent2D, precision: Precision, reference_length: u32) -> PushConstants {
+        let scale = view.scale(extent);
+        let center = [view.center[0].hi, view.center[1].hi];
+        PushConstants {
+            center: if precision == Precision::Single {[center[0] as f32, center[1] as f32]} else {[0.0, 0.0]},
+            scale: [scale[0] as f32, scale[1] as f32],
+            max_iterations: view.max_iterations,
+            reference_length,
+            coloring: view.coloring() as u32,
+            supersample: 1,
+            center_f64: if precision == Precision::Double {center} else {[0.0, 0.0]},
+            julia_c: [0.0, 0.0],
+            pane_offset: [0, 0],
+            pane_extent: [extent.width, extent.height],
+            image_width: extent.width,
+            formula: view.formula as u32,
+            region_offset: [0, 0],
+            region_extent: [extent.width, extent.height],
+            stride: 1,
+            _padding_end: 0,
+        }
+    }
+
+    fn julia(scene: &Scene, extent: vk::Extent2D) -> PushConstants {
+        let mut view = scene.julia_view;
+        view.max_iterations = scene.view.max_iterations;
+        view.formula = scene.view.formula;
+        //The histogram is only estimated for the Mandelbrot view
+        view.coloring = if scene.view.coloring == Coloring::Histogram {Coloring::Smooth} else {scene.view.coloring};
+        let mut push_constants = PushConstants::new(&view, extent, Precision::Single, 0);
+        push_constants.julia_c = [scene.julia_c[0] as f32, scene.julia_c[1] as f32];
+        return push_constants;
+    }
+
+    //Push constants for drawing only area of the pane of pane_extent these draw, in the coordinates of the pane.
+    //The view is the same, so a pane drawn in parts, e.g. the tiles of a poster, matches it drawn at once
+    fn crop(self, pane_extent: vk::Extent2D, area: vk::Rect2D, precision: Precision) -> PushConstants {
+        let scale = [self.scale[0] as f64, self.scale[1] as f64];
+        let fraction = [
+            area.extent.width as f64 / pane_extent.width as f64,
+            area.extent.height as f64 / pane_extent.height as f64,
+        ];
+        //Of the middle of area from the middle of the pane, in the complex plane
+        let offset = [
+            ((2.0 * area.offset.x as f64 + area.extent.width as f64) / pane_extent.width as f64 - 1.0) * scale[0],
+            ((2.0 * area.offset.y as f64 + area.extent.height as f64) / pane_extent.height as f64 - 1.0) * scale[1],
+        ];
+        let mut cropped = self;
+        cropped.scale = [(scale[0] * fraction[0]) as f32, (scale[1] * fraction[1]) as f32];
+        //Perturbation keeps its reference point, the center then being the offset from it
+        if precision == Precision::Double {
+            cropped.center_f64 = [self.center_f64[0] + offset[0], self.center_f64[1] + offset[1]];
+        } else {
+            cropped.center = [(self.center[0] as f64 + offset[0]) as f32, (self.center[1] as f64 + offset[1]) as f32];
+        }
+        return cropped;
+    }
+}
+
+//Every stage reads the push constants, so every push must name all of them
+fn push_constant_stages() -> vk::ShaderStageFlags {
+    return vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE;
+}
+
+//A fractal drawn into part of the framebuffer
+#[derive(Clone, Copy, Debug)]
+struct Pane {
+    area: vk::Rect2D,
+    pipeline: vk::Pipeline,
+    push_constants: PushConstants,
+    iterate: Option<vk::Pipeline>, //Compute pipeline filling the samples the pipeline colors, compute renderer only
+}
+
+//GLSL file in glsl_shaders/, its defines and the SPIR-V built from them into the binary
+type ShaderSource = (&'static str, Vec<(&'static str, &'static str)>, &'static [u8]);
 
+//What a pipeline does, and so which shaders it is made of
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum PipelineKind {
+    Draw(ShaderVariant), //Iterates and colors in the fragment shader
+    Iterate(ShaderVariant), //Compute renderer, iterates into the sample buffer
+    Colorize, //Compute renderer, colors the sample buffer
+}
+impl PipelineKind {
+    //Of each shader stage, in pipeline order
+    fn sources(self) -> Vec<ShaderSource> {
+        return match self {
+            PipelineKind::Draw(variant) => {
+                let (frag_source, defines) = variant.source();
+                vec![(VERT_SOURCE, Vec::new(), VERT_SHADER), (frag_source, defines, variant.fragment_shader())]
+            },
+            PipelineKind::Iterate(variant) => vec![(COMPUTE_SOURCE, variant.defines(), variant.compute_shader())],
+            PipelineKind::Colorize => vec![(VERT_SOURCE, Vec::new(), VERT_SHADER), (COLORIZE_SOURCE, Vec::new(), COLORIZE_SHADER)],
+        }
+    }
+}
 
-const VALIDATION_LAYERS: [*const c_char; 1] = [cstr!("VK_LAYER_KHRONOS_validation")];
-#[cfg(debug_assertions)]
-const VALIDATION_ENABLED: bool = true;
-#[cfg(not(debug_assertions))]
-const VALIDATION_ENABLED: bool = false;
+//Pipelines, each created the first time it is drawn or dispatched with.
+//They don't depend on the swapchain, as the viewport is dynamic
+struct Pipelines {
+    renderpass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+    samples: vk::SampleCountFlagBits, //Per pixel of the renderpass's color attachment
+    cache: HashMap<PipelineKind, vk::Pipeline>,
+    //Sources changed while running by file name, compiled from these paths instead of using the shaders in the binary
+    reloaded: HashMap<String, PathBuf>,
+}
+impl Pipelines {
+    fn new(renderpass: vk::RenderPass, pipeline_layout: vk::PipelineLayout, samples: vk::SampleCountFlagBits) -> Pipelines {
+        return Pipelines{renderpass, pipeline_layout, samples, cache: HashMap::new(), reloaded: HashMap::new()};
+    }
 
+    fn get(&mut self, logical_device: &DeviceLoader, kind: PipelineKind) -> Result<vk::Pipeline, VulkanAppError> {
+        if let Some(pipeline) = self.cache.get(&kind) {return Ok(*pipeline)}
+        let code = match Self::shader_code(&self.reloaded, kind) {
+            Ok(code) => code,
+            Err(message) => {
+                eprintln!("{}", message);
+                eprintln!("Using the shaders built into the binary for {:?}", kind);
+                kind.sources().iter().map(|(_, _, built_in)| decode_shader(built_in)).collect::<Result<_, _>>()?
+            },
+        };
+        let pipeline = self.create(logical_device, kind, &code)?;
+        self.cache.insert(kind, pipeline);
+        return Ok(pipeline);
+    }
 
-const MAX_FRAMES_IN_FLIGHT: usize = 2;
+    //SPIR-V of each shader stage of kind, compiled from the sources in reloaded if they are there
+    fn shader_code(reloaded: &HashMap<String, PathBuf>, kind: PipelineKind) -> Result<Vec<Vec<u32>>, String> {
+        return kind.sources().iter()
+            .map(|(source, defines, built_in)| match reloaded.get(*source) {
+                Some(path) => shader_build::compile_glsl(path, defines),
+                None => decode_shader(built_in).map_err(|err| err.to_string()),
+            })
+            .collect();
+    }
 
+    fn create(&self, logical_device: &DeviceLoader, kind: PipelineKind, code: &[Vec<u32>]) -> Result<vk::Pipeline, VulkanAppError> {
+        return match kind {
+            PipelineKind::Iterate(_) => create_compute_pipeline(logical_device, self.pipeline_layout, &code[0]),
+            PipelineKind::Draw(_) | PipelineKind::Colorize => create_graphics_pipeline(logical_device, self.renderpass, self.pipeline_layout, self.samples, &code[0], &code[1]),
+        };
+    }
 
+    //Recompiles the shaders of the pipelines using any of the changed sources and replaces those pipelines, nothing else.
+    //Returns whether any pipeline was replaced, or the compiler errors, in which case all the old pipelines are kept
+    //and the sources are not taken as reloaded
+    fn reload(&mut self, logical_device: &DeviceLoader, changed: &[PathBuf]) -> Result<bool, String> {
+        let mut reloaded = self.reloaded.clone();
+        let mut changed_sources = Vec::new();
+        for path in changed {
+            if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
+                reloaded.insert(file_name.to_string(), path.clone());
+                changed_sources.push(file_name.to_string());
+            }
+        }
+        //Sources including a changed file are changed too, and compiled from next to it
+        for kind in self.cache.keys() {
+            for (source, _, _) in kind.sources() {
+                for path in changed {
+                    let source_path = path.with_file_name(source);
+                    let includes_changed = shader_build::preprocess(&source_path).is_ok_and(|(_, includes)| includes.contains(path));
+                    if includes_changed && !changed_sources.iter().any(|changed| changed == source) {
+                        reloaded.insert(source.to_string(), source_path);
+                        changed_sources.push(source.to_string());
+                    }
+                }
+            }
+        }
+        let is_changed = |source: &str| changed_sources.iter().any(|changed| changed == source);
+
+        let mut rebuilt = Vec::new();
+        for kind in self.cache.keys() {
+            if kind.sources().iter().any(|(source, _, _)| is_changed(source)) {
+                rebuilt.push((*kind, Self::shader_code(&reloaded, *kind)?));
+            }
+        }
+        if rebuilt.is_empty() {
+            self.reloaded = reloaded;
+            return Ok(false)
+        }
 
-unsafe extern "system" fn debug_callback(
-    _message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
-    _message_type: vk::DebugUtilsMessageTypeFlagsEXT,
-    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void
-) -> vk::Bool32 {
-    eprintln!("{}", CStr::from_ptr((*p_callback_data).p_message).to_string_lossy());
-    vk::FALSE
+        //Frames in flight may still be drawing with the old pipelines
+        unsafe {logical_device.device_wait_idle()}.map_err(|err| VulkanAppError::vulkan("wait for the device to be idle")(err).to_string())?;
+        //Every new pipeline before replacing any, so a failure leaves all the old ones in place
+        let mut pipelines = Vec::with_capacity(rebuilt.len());
+        for (kind, code) in rebuilt {
+            match self.create(logical_device, kind, &code) {
+                Ok(pipeline) => pipelines.push((kind, pipeline)),
+                Err(err) => {
+                    for (_, pipeline) in pipelines {
+                        unsafe {logical_device.destroy_pipeline(pipeline, None)};
+                    }
+                    return Err(err.to_string());
+                },
+            }
+        }
+
+        for (kind, pipeline) in pipelines {
+            if let Some(old_pipeline) = self.cache.insert(kind, pipeline) {
+                unsafe {logical_device.destroy_pipeline(old_pipeline, None)};
+            }
+        }
+        self.reloaded = reloaded;
+        return Ok(true);
+    }
+
+    fn destroy(&self, logical_device: &DeviceLoader) {
+        for pipeline in self.cache.values() {
+            unsafe {logical_device.destroy_pipeline(*pipeline, None)};
+        }
+    }
 }
 
-fn init_window() -> (Window, EventLoop<()>) {
+//Brings shader_data up to date with scene, and returns the panes drawing it with the Mandelbrot set in precision, jittered as supersampling says.
+//The compute renderer only handles single precision, other panes are drawn by the graphics pipelines regardless
+#[allow(clippy::too_many_arguments)]
+fn prepare_panes(
+    logical_device: &DeviceLoader,
+    shader_data: &mut ShaderData,
+    pipelines: &mut Pipelines,
+    scene: &Scene,
+    extent: vk::Extent2D,
+    precision: Precision,
+    renderer: Renderer,
+    supersampling: Supersampling
+) -> Result<Vec<Pane>, VulkanAppError> {
+    return scene.layout.panes(extent).into_iter().map(|(fractal, area)| {
+        let formula = scene.view.formula;
+        let (variant, mut push_constants) = match fractal {
+            Fractal::Mandelbrot => {
+                let reference_length = shader_data.update(&scene.view, area.extent, precision);
+                (ShaderVariant{formula, precision, julia: false}, PushConstants::new(&scene.view, area.extent, precision, reference_length))
+            },
+            Fractal::Julia => (ShaderVariant{formula, precision: Precision::Single, julia: true}, PushConstants::julia(scene, area.extent)),
+        };
+        push_constants.pane_offset = [area.offset.x as u32, area.offset.y as u32];
+        push_constants.image_width = extent.width;
+        push_constants.supersample = supersampling.jitter();
+        if renderer == Renderer::Compute && variant.precision == Precision::Single {
+            let iterate = pipelines.get(logical_device, PipelineKind::Iterate(variant))?;
+            return Ok(Pane{area, pipeline: pipelines.get(logical_device, PipelineKind::Colorize)?, push_constants, iterate: Some(iterate)});
+        }
+        Ok(Pane{area, pipeline: pipelines.get(logical_device, PipelineKind::Draw(variant))?, push_constants, iterate: None})
+    }).collect();
+}
+
+//Zoom animation, scales the view from 1 at theta = 0 down to about 1/100 at theta = 1 and back out at theta = 2
+fn animation_scale(theta: f64) -> f64 {
+    return (0.1*(theta-1.0)*(theta-1.0) + 0.001) / 0.101;
+}
+
+
+fn init_window(width: u32, height: u32) -> (Window, EventLoop<()>) {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_inner_size( winit::dpi::PhysicalSize::new(WIDTH, HEIGHT))
+        .with_inner_size( winit::dpi::PhysicalSize::new(width, height))
         .with_title(APP_TITLE)
-        .with_resizable(false)
         .build(&event_loop).expect("Window build failed!");
     (window, event_loop)
 }
 
 struct VulkanApp { //Members dropped in declared order. So they must be placed in opposite order of references
-    image_available_sems: SmallVec<vk::Semaphore>,
-    render_finished_sems: SmallVec<vk::Semaphore>,
-    in_flight_fences: SmallVec<vk::Fence>,
-    images_in_flight: SmallVec<vk::Fence>,
-    command_buffers: SmallVec<vk::CommandBuffer>,
+    frames: FrameRing, //Semaphores and fences of the frames in flight, the per-frame objects below are indexed by its slot
+    images_in_flight: SmallVec<vk::Fence>, //Fence of the frame last drawing into each swapchain image
+    queries: Queries, //Timing and statistics of the frames in flight
+    command_buffers: SmallVec<vk::CommandBuffer>, //One per frame in flight, re-recorded each time the frame is drawn
     command_pool: vk::CommandPool,
     framebuffers: Vec<vk::Framebuffer>,
+    multisample_image: Option<MultisampleImage>, //MSAA only, drawn into and resolved into the swapchain image
+    supersampling: Supersampling,
     renderpass: vk::RenderPass,
     graphics_pipeline_layout: vk::PipelineLayout,
-    graphics_pipeline: vk::Pipeline,
+    graphics_pipelines: Pipelines,
+    shader_data: ShaderData,
+    panes: Vec<Pane>, //What the command buffers draw
+    renderer: Renderer,
+    //Compute passes the samples were last iterated with, coloring left out as the samples don't depend on it
+    iterated: Vec<compute::Pass>,
+    progressive: bool,
+    //Shifts and dispatches waiting to be recorded into the next frame ahead of its render pass, in order
+    iterating: Vec<(Vec<vk::BufferCopyBuilder<'static>>, Vec<compute::Pass>)>,
+    refining: VecDeque<compute::Pass>, //Tiles of the panes left to iterate at full resolution, progressive only
+    refine_tiles: usize, //Tiles recorded per frame, adjusted to take about REFINE_TIME_PER_FRAME
+    refined: Vec<usize>, //Tiles recorded into each frame in flight, to tell the time per tile from its queries
+    precision: Precision,
+    forced_precision: Option<Precision>,
     image_views: Vec<vk::ImageView>,
     swapchain: vk::SwapchainKHR,
     swapchain_extent: vk::Extent2D,
-    graphics_queue: vk::Queue,
-    present_queue: vk::Queue,
-    device: Box<DeviceLoader>,
-    surface: vk::SurfaceKHR,
-    messenger: vk::DebugUtilsMessengerEXT,
-    instance: Box<InstanceLoader>,
-    _entry: Box<EntryLoader>,
+    present_mode: vk::PresentModeKHR,
+    context: VulkanContext,
 }
 impl Drop for VulkanApp {
     fn drop(&mut self) {
+        //images_in_flight references the same fences, so destroying the frames cleans it up too
+        self.frames.destroy(&self.context.device);
+        self.cleanup_swapchain();
+        self.queries.destroy(&self.context.device);
+        unsafe {
+            self.context.device.destroy_command_pool(self.command_pool, None);
+            self.graphics_pipelines.destroy(&self.context.device);
+            self.context.device.destroy_pipeline_layout(self.graphics_pipeline_layout, None);
+            self.shader_data.destroy(&self.context.device);
+            self.context.device.destroy_render_pass(self.renderpass, None);
+            self.context.device.destroy_swapchain_khr(self.swapchain, None);
+        }
+        println!("VulkanApp dropped succesfully");
+    }
+}
+impl VulkanApp {
+    //Destroys everything that depends on the swapchain extent, but not the swapchain itself
+    //(it is handed to the new swapchain as old_swapchain when recreating)
+    fn cleanup_swapchain(&mut self) {
         unsafe {
-            for i in 0..MAX_FRAMES_IN_FLIGHT {
-                self.device.destroy_semaphore(self.image_available_sems[i], None);
-                self.device.destroy_semaphore(self.render_finished_sems[i], None);
-                //images_in_flight actually references the same structs, so in_flight_fences being destroyed cleans it up too
-                self.device.destroy_fence(self.in_flight_fences[i], None);
-            }
-            self.device.destroy_command_pool(self.command_pool, None);
             for buffer in &mut self.framebuffers {
-                self.device.destroy_framebuffer(*buffer, None);
+                self.context.device.destroy_framebuffer(*buffer, None);
             }
-            self.device.destroy_pipeline(self.graphics_pipeline, None);
-            self.device.destroy_pipeline_layout(self.graphics_pipeline_layout, None);
-            self.device.destroy_render_pass(self.renderpass, None);
             for view in &mut self.image_views {
-                self.device.destroy_image_view(*view, None);
+                self.context.device.destroy_image_view(*view, None);
             }
-            self.device.destroy_device(None);
-            if !self.messenger.is_null() {
-                self.instance.destroy_debug_utils_messenger_ext(self.messenger, None)
-            }
-            self.instance.destroy_surface_khr(self.surface, None);
-            self.instance.destroy_instance(None);
         }
-        println!("VulkanApp dropped succesfully");
+        if let Some(image) = self.multisample_image.take() {
+            image.destroy(&self.context.device);
+        }
+        self.framebuffers.clear();
+        self.image_views.clear();
     }
-}
-fn init_vulkan(window: &Window) -> VulkanApp {
-    let entry = Box::new(EntryLoader::new().unwrap());
 
-    fn check_validation_layer_support(entry: &EntryLoader) -> bool{
-        let available_layers = unsafe {entry.enumerate_instance_layer_properties(None).unwrap()};
-        for layer in &VALIDATION_LAYERS {
-            let mut found = false;
-            for layer_properties in &available_layers {
-                let layer_name_ptr = &layer_properties.layer_name[0] as *const i8;
-                unsafe {
-                    //println!("{:?}", CStr::from_ptr(layer_name_ptr));
-                    if CStr::from_ptr(layer_name_ptr) == CStr::from_ptr(*layer) {
-                        found = true; break
-                    }
-                }
+    //Rebuilds the swapchain and all objects depending on its extent, e.g. after the window was resized
+    //or the driver reports the swapchain out of date. Must not be called while the window is minimized.
+    fn recreate_swapchain(&mut self, window: &Window, scene: &Scene) -> Result<(), VulkanAppError> {
+        unsafe {self.context.device.device_wait_idle()}.map_err(VulkanAppError::vulkan("wait for the device"))?;
+        self.cleanup_swapchain();
+
+        let old_swapchain = self.swapchain;
+        let (swapchain, image_format, swapchain_extent) = create_swapchain(&self.context, window, self.present_mode, old_swapchain)?;
+        unsafe {self.context.device.destroy_swapchain_khr(old_swapchain, None)};
+        self.swapchain = swapchain;
+        self.swapchain_extent = swapchain_extent;
+
+        self.image_views = create_image_views(&self.context.device, swapchain, image_format)?;
+        self.multisample_image = MultisampleImage::new(&self.context, image_format, swapchain_extent, self.supersampling)?;
+        self.framebuffers = create_framebuffers(&self.context.device, &self.image_views, self.multisample_image.as_ref(), self.renderpass, swapchain_extent)?;
+        self.resize_samples()?;
+        self.update_panes(scene)?;
+
+        //The number of images may have changed, and none of the new ones are in flight
+        self.images_in_flight = SmallVec::from_elem(vk::Fence::null(), self.image_views.len());
+        return Ok(());
+    }
+
+    //Sets the panes drawn from now on to those of scene, with the cheapest precision that resolves its Mandelbrot view.
+    //Command buffers already in flight are left alone, the next one recorded iterates what changed and draws the new panes
+    fn update_panes(&mut self, scene: &Scene) -> Result<(), VulkanAppError> {
+        let precision = Precision::choose(self.forced_precision, scene.view.zoom, supports_double(&self.context), scene.view.formula.is_quadratic());
+        if precision != self.precision {
+            println!("Rendering with {:?} precision", precision);
+            self.precision = precision;
+        }
+        let panes = prepare_panes(&self.context.device, &mut self.shader_data, &mut self.graphics_pipelines, scene, self.swapchain_extent, precision, self.renderer, self.supersampling)?;
+
+        //Iterate again only if the samples would change, not for a new coloring, and only what changed
+        let passes = compute_passes(&panes);
+        if passes != self.iterated {
+            //Samples still being refined are partly coarse, which is not worth shifting
+            let previous = if self.refining.is_empty() {&self.iterated[..]} else {&[]};
+            let (shifts, mut dispatches) = compute::plan(&self.shader_data, previous, &passes);
+            self.refining.clear();
+            if self.progressive { //Only a coarse pass now, the tiles follow over the next frames
+                self.refining = dispatches.iter().flat_map(compute::tiles).collect();
+                dispatches = dispatches.iter().map(compute::coarse).collect();
             }
-            if !found {return false}
+            self.iterating.push((shifts, dispatches));
+            self.iterated = passes;
         }
-        return true
+        self.panes = panes;
+        return Ok(());
     }
 
-    if VALIDATION_ENABLED && !check_validation_layer_support(&entry) {
-        panic!("Validation layer requested but not available!");
-    }
+    //Records the command buffer of frame to draw the panes into swapchain image image_index.
+    //The frame must not be in flight, its fence having been waited for
+    fn record_frame(&mut self, frame: usize, image_index: usize) -> Result<vk::CommandBuffer, VulkanAppError> {
+        let logical_device = &self.context.device;
+        let command_buffer = self.command_buffers[frame];
+        let command_buffer_begin_info = vk::CommandBufferBeginInfoBuilder::new()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            logical_device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty()).map_err(VulkanAppError::vulkan("reset command buffer"))?;
+            logical_device.begin_command_buffer(command_buffer, &command_buffer_begin_info).map_err(VulkanAppError::vulkan("begin command buffer"))?;
+        }
+        self.queries.begin_frame(logical_device, command_buffer, frame);
+        let descriptor_set = self.shader_data.prepare_frame(frame);
+
+        //Compute renderer: the samples are iterated ahead of the render pass coloring them, see compute::record
+        if !self.iterating.is_empty() {
+            let region = self.queries.begin_region(logical_device, command_buffer, frame, ITERATE_REGION);
+            for (shifts, dispatches) in self.iterating.drain(..) {
+                compute::record(logical_device, command_buffer, self.graphics_pipeline_layout, &self.shader_data, descriptor_set, &shifts, &dispatches);
+            }
+            self.queries.end_region(logical_device, command_buffer, region);
+            self.refined[frame] = 0;
+        } else { //Progressive mode: the coarse pass gets a frame of its own, then each frame refines a few tiles
+            let tiles: Vec<_> = self.refining.drain(..self.refine_tiles.min(self.refining.len())).collect();
+            if !tiles.is_empty() {
+                let region = self.queries.begin_region(logical_device, command_buffer, frame, REFINE_REGION);
+                compute::record(logical_device, command_buffer, self.graphics_pipeline_layout, &self.shader_data, descriptor_set, &[], &tiles);
+                self.queries.end_region(logical_device, command_buffer, region);
+            }
+            self.refined[frame] = tiles.len();
+        }
 
-    //// Application info
-    let app_name = CString::new("Mandelbrot by Kristian Knudsen").unwrap();
-    let engine_name = CString::new("No Engine").unwrap();
+        let region = self.queries.begin_region(logical_device, command_buffer, frame, RENDER_PASS_REGION);
+        self.queries.begin_statistics(logical_device, command_buffer, frame);
+        record_render_pass(
+            logical_device,
+            command_buffer,
+            self.framebuffers[image_index],
+            self.swapchain_extent,
+            self.renderpass,
+            self.graphics_pipeline_layout,
+            descriptor_set,
+            &self.panes
+        );
+        self.queries.end_statistics(logical_device, command_buffer, frame);
+        self.queries.end_region(logical_device, command_buffer, region);
+        unsafe {logical_device.end_command_buffer(command_buffer)}.map_err(VulkanAppError::vulkan("record command buffer"))?;
+        return Ok(command_buffer);
+    }
 
-    let app_info = vk::ApplicationInfoBuilder::new()
-        .application_name(&app_name)
-        .application_version(vk::make_api_version(0,1,0,0))
-        .engine_name(&engine_name)
-        .engine_version(vk::API_VERSION_1_0)
-        .api_version(vk::API_VERSION_1_0);
+    //Query results of the frame last submitted in slot frame, which must be done. None if they were read already.
+    //The time the tiles refined in the frame took sets how many the next frames get
+    fn query_results(&mut self, frame: usize) -> Option<QueryResults> {
+        let results = self.queries.read(&self.context.device, frame)?;
+        if let (tiles @ 1.., Some(time)) = (self.refined[frame], results.region(REFINE_REGION)) {
+            let tile_time = time.as_secs_f64() / tiles as f64;
+            let fitting = (REFINE_TIME_PER_FRAME.as_secs_f64() / tile_time.max(f64::EPSILON)) as usize;
+            self.refine_tiles = fitting.clamp(1, 2 * self.refine_tiles); //Growing gradually, a few cheap tiles say little about the rest
+        }
+        return Some(results);
+    }
 
-    let mut instance_extensions = surface::enumerate_required_extensions(window).unwrap();
-    if VALIDATION_ENABLED {
-        instance_extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION_NAME);
+    //Gives the compute renderer a sample per pixel of the swapchain. The device must be idle
+    fn resize_samples(&mut self) -> Result<(), VulkanAppError> {
+        if self.renderer != Renderer::Compute {return Ok(())}
+        let pixel_count = self.swapchain_extent.width as usize * self.swapchain_extent.height as usize;
+        self.shader_data.resize_samples(&self.context, pixel_count, true)?;
+        self.iterated.clear(); //The samples are gone
+        self.iterating.clear();
+        self.refining.clear();
+        return Ok(());
     }
 
-    //// Instance info & debug messenger
-    let mut messenger_info = init_debug_messenger_info();
-    let mut instance_info = vk::InstanceCreateInfoBuilder::new()
-        .application_info(&app_info)
-        .enabled_extension_names(&instance_extensions);
-    if VALIDATION_ENABLED {
-        instance_info = instance_info
-            .enabled_layer_names(&VALIDATION_LAYERS)
-            .extend_from(&mut messenger_info);
+    //Progressive mode: whether tiles are left to refine, in which case frames should be drawn to iterate and show them
+    fn is_refining(&self) -> bool {
+        return !self.refining.is_empty();
     }
-    
-    //// Instance created
-    let instance = Box::new(unsafe {InstanceLoader::new(&entry, &instance_info)}.expect("Failed to create Vulkan instance!"));
-    // Messenger attached
-    let messenger = if VALIDATION_ENABLED {
-        unsafe {instance.create_debug_utils_messenger_ext(&messenger_info, None)}.unwrap()
-    } else {
-        vk::DebugUtilsMessengerEXT::default()
-    };
+}
 
-    //// Window surface creation
-    let surface = unsafe { surface::create_surface(&instance, &window, None) }.unwrap();
-
-    //// Physical device and queues
-    const GRAPHICS_Q_IDX: usize = 0;
-    const PRESENT_Q_IDX: usize = 1;
-    const DEVICE_EXTS: [*const c_char; 1] = [vk::KHR_SWAPCHAIN_EXTENSION_NAME];
-
-    // Swapchain queries
-    fn query_swap_chain_support(device: &vk::PhysicalDevice, surface: &vk::SurfaceKHR, instance: &InstanceLoader)
-    -> (vk::SurfaceCapabilitiesKHR, Vec<vk::SurfaceFormatKHR>, Vec<vk::PresentModeKHR>) {
-            let surface_capabilities = unsafe {instance.get_physical_device_surface_capabilities_khr(*device, *surface)}.unwrap();
-            let formats = unsafe {instance.get_physical_device_surface_formats_khr(*device, *surface, None)}.unwrap();
-            let present_modes = unsafe {instance.get_physical_device_surface_present_modes_khr(*device, *surface, None)}.unwrap();
-            (surface_capabilities, formats.to_vec(), present_modes.to_vec())
-    }
-
-    let (physical_device, queue_family_indices) = {
-        fn find_queue_families(device: &vk::PhysicalDevice, surface: &vk::SurfaceKHR, instance: &InstanceLoader) -> Option<[u32; 2]> {
-            let queue_family_properties = unsafe {instance.get_physical_device_queue_family_properties(*device, None)};
-            let mut indices = [0; 2];
-            let mut found_queues = [false; 2];
-            'outer:
-            for (i, queue_family) in queue_family_properties.iter().enumerate() {
-                if !found_queues[GRAPHICS_Q_IDX] && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                    indices[GRAPHICS_Q_IDX] = i as u32; //Graphics queue found, look for present queue (probably the same)
-                    found_queues[GRAPHICS_Q_IDX] = true;
-                }
-                if !found_queues[PRESENT_Q_IDX] && unsafe {instance.get_physical_device_surface_support_khr(*device, i as u32, *surface)}.unwrap() {
-                    indices[PRESENT_Q_IDX] = i as u32; //Graphics queue found, look for present queue (probably the same)
-                    found_queues[PRESENT_Q_IDX] = true;
-                }
-                for queue_found in found_queues {
-                    if !queue_found {break 'outer}
-                }
-                return Some(indices) //Only reached if the above for loop does not break
-            }
-            None
-        }
-        
-        fn check_device_extension_support(device: &vk::PhysicalDevice, instance: &InstanceLoader) -> bool {
-            let device_extension_properties = unsafe {instance.enumerate_device_extension_properties(*device, None, None)}.unwrap();
-            let available_extension_names: Vec<&str> = device_extension_properties
-                .iter()
-                .map(|ext| unsafe {CStr::from_ptr(ext.extension_name.as_ptr())}.to_str().unwrap() ).collect();
-            for extension in DEVICE_EXTS {
-                let ext_name = unsafe {CStr::from_ptr(extension)}.to_str().unwrap();
-                if !available_extension_names.contains(&ext_name) {
-                    return false
-                }
-            }
-            return true
-        }
+//The iteration passes of the compute renderer for panes, comparable to tell whether the samples are still current
+fn compute_passes(panes: &[Pane]) -> Vec<compute::Pass> {
+    return panes.iter().filter_map(|pane| {
+        let mut push_constants = pane.push_constants;
+        push_constants.coloring = 0;
+        Some((pane.iterate?, push_constants))
+    }).collect();
+}
 
-        //Checking device suitability
-        let devices = unsafe {instance.enumerate_physical_devices(None)}.unwrap();
-        if devices.len() == 0 {panic!("No devices with Vulkan support!")}
-        fn is_device_suitable(device: &vk::PhysicalDevice, surface: &vk::SurfaceKHR, instance: &InstanceLoader) -> bool {
-            let device_properties = unsafe {instance.get_physical_device_properties(*device)};
-            let device_features = unsafe {instance.get_physical_device_features(*device)};
-            println!("Device name: {}", unsafe {CStr::from_ptr(device_properties.device_name.as_ptr())}.to_string_lossy());
-
-            if !check_device_extension_support(device, instance) {return false} //Must have extension to query swap chain
-            let (_, formats, present_modes) = query_swap_chain_support(device, surface, instance);
-
-            return device_features.geometry_shader == vk::TRUE
-                && if let Some(_) = find_queue_families(device, surface, instance) {true} else {false}
-                && !formats.is_empty() && !present_modes.is_empty()
-                
-        }
-        fn rate_device_suitability(device_properties: &vk::PhysicalDeviceProperties, device_features: &vk::PhysicalDeviceFeatures) -> u32 {
-            let mut score = 0;
-            if device_properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {score += 1000}
-            score += device_properties.limits.max_image_dimension2_d;
-            if device_features.geometry_shader == vk::FALSE {return 0}
-            return score
-        }
-    
-        //Picking device
-        let physical_device = devices.into_iter().max_by_key(
-            |device| {
-                let device_properties = unsafe {instance.get_physical_device_properties(*device)};
-                let device_features = unsafe {instance.get_physical_device_features(*device)};
-                rate_device_suitability(&device_properties, &device_features)
-            }
-        ).expect("No devices could be found!");
-        if !is_device_suitable(&physical_device, &surface, &instance) {panic!("No suitable GPU found!")}
-        let queue_family_indices = find_queue_families(&physical_device, &surface, &instance).unwrap();
-        
-        (physical_device, queue_family_indices)
+#[allow(clippy::too_many_arguments)]
+fn init_vulkan(
+    window: &Window,
+    context_options: &ContextOptions,
+    present_mode: vk::PresentModeKHR,
+    forced_precision: Option<Precision>,
+    renderer: Renderer,
+    progressive: bool,
+    supersampling: Supersampling,
+    frames_in_flight: usize,
+    scene: &Scene
+) -> Result<VulkanApp, VulkanAppError> {
+    let context = VulkanContext::with_options(Some(window), "Mandelbrot by Kristian Knudsen", context_options)?;
+    let logical_device = &context.device;
+    let renderer = renderer.available(&context);
+    let supersampling = supersampling.available(&context, renderer);
+
+    //// Creating swapchain
+    let (swapchain, image_format, swapchain_extent) = create_swapchain(&context, window, present_mode, vk::SwapchainKHR::null())?;
+
+    //// Image views
+    let image_views = create_image_views(logical_device, swapchain, image_format)?;
+
+    //// Storage buffers for the perturbation renderer and histogram coloring
+    let shader_data = ShaderData::new(&context, scene.view.max_iterations, frames_in_flight)?;
+
+    //// Graphics pipelines
+    let renderpass = create_renderpass(logical_device, image_format, vk::ImageLayout::PRESENT_SRC_KHR, supersampling.samples())?;
+    let graphics_pipeline_layout = create_pipeline_layout(logical_device, shader_data.descriptor_set_layout)?;
+    let graphics_pipelines = Pipelines::new(renderpass, graphics_pipeline_layout, supersampling.samples());
+
+    //// Framebuffers, drawn into through a multisampled image with MSAA
+    let multisample_image = MultisampleImage::new(&context, image_format, swapchain_extent, supersampling)?;
+    let swapchain_framebuffers = create_framebuffers(logical_device, &image_views, multisample_image.as_ref(), renderpass, swapchain_extent)?;
+
+    //// Command pool and buffers, the buffers reset one at a time to be recorded anew every frame
+    let command_pool_info = vk::CommandPoolCreateInfoBuilder::new()
+        .queue_family_index(context.queue_family_indices[GRAPHICS_Q_IDX])
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+    let command_pool = unsafe {logical_device.create_command_pool(&command_pool_info, None)}.map_err(VulkanAppError::vulkan("create command pool"))?;
+    let command_buffer_allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(frames_in_flight as u32);
+    let command_buffers = unsafe {logical_device.allocate_command_buffers(&command_buffer_allocate_info)}.map_err(VulkanAppError::vulkan("allocate command buffers"))?;
+
+    //// Create semaphores for in-render-pass synchronization
+    let frames = FrameRing::new(logical_device, frames_in_flight)?;
+    let images_in_flight = SmallVec::from_elem(vk::Fence::null(), image_views.len());
+    let queries = Queries::new(&context, frames_in_flight)?;
+
+    let mut vulkan_app = VulkanApp {
+        context,
+        swapchain,
+        swapchain_extent,
+        present_mode,
+        image_views,
+        forced_precision,
+        precision: Precision::Single,
+        shader_data,
+        panes: Vec::new(),
+        renderer,
+        iterated: Vec::new(),
+        progressive,
+        iterating: Vec::new(),
+        refining: VecDeque::new(),
+        refine_tiles: 1,
+        refined: vec![0; frames_in_flight],
+        graphics_pipelines,
+        graphics_pipeline_layout,
+        renderpass,
+        supersampling,
+        multisample_image,
+        framebuffers: swapchain_framebuffers,
+        command_pool,
+        command_buffers,
+        frames,
+        images_in_flight,
+        queries,
     };
+    //// Panes, prepared once the app exists as the precision is picked along the way
+    vulkan_app.resize_samples()?;
+    vulkan_app.update_panes(scene)?;
+    return Ok(vulkan_app);
+}
 
-    //// Logical device
-    let unique_queue_family_indices: Vec<u32> = HashSet::from(queue_family_indices.clone()).into_iter().collect();
-    let device_queue_infos: &[vk::DeviceQueueCreateInfoBuilder] = &unique_queue_family_indices.into_iter().map(|index| {
-        vk::DeviceQueueCreateInfoBuilder::new()
-        .queue_family_index(index)
-        .queue_priorities(&[1.0])
-    }).collect::<Vec<vk::DeviceQueueCreateInfoBuilder>>().into_boxed_slice();
-    
-    let device_features = vk::PhysicalDeviceFeatures::default();
-    let mut device_create_info = vk::DeviceCreateInfoBuilder::new()
-        .queue_create_infos(device_queue_infos)
-        .enabled_features(&device_features)
-        .enabled_extension_names(&DEVICE_EXTS);
-    if VALIDATION_ENABLED {
-        device_create_info = device_create_info.enabled_layer_names(&VALIDATION_LAYERS);
-    }
-    let logical_device = Box::new(unsafe {DeviceLoader::new(&instance, physical_device, &device_create_info)}.expect("Failed to create logical device!"));
-
-    //// Queue handles
-    let graphics_queue = unsafe {logical_device.get_device_queue(queue_family_indices[GRAPHICS_Q_IDX], 0)};
-    let present_queue = unsafe {logical_device.get_device_queue(queue_family_indices[PRESENT_Q_IDX], 0)};
-
-
-    //// Picking swapchain settings
-    fn choose_swap_surface_format(formats: &Vec<vk::SurfaceFormatKHR>) -> vk::SurfaceFormatKHR {
-        for available_format in formats {
-            if available_format.format == vk::Format::R8G8B8A8_SRGB && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR {
-                return *available_format
-            }
+//// Picking swapchain settings
+fn choose_swap_surface_format(formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+    for available_format in formats {
+        if available_format.format == vk::Format::R8G8B8A8_SRGB && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR_KHR {
+            return *available_format
         }
-        return formats[0];
     }
-    fn choose_swap_present_mode(present_modes: &Vec<vk::PresentModeKHR>) -> vk::PresentModeKHR {
-        for available_mode in present_modes {
-            if *available_mode == vk::PresentModeKHR::MAILBOX_KHR {
-                return *available_mode
-            }
+    return formats[0];
+}
+fn choose_swap_present_mode(present_modes: &[vk::PresentModeKHR], preferred_mode: vk::PresentModeKHR) -> vk::PresentModeKHR {
+    for available_mode in present_modes {
+        if *available_mode == preferred_mode {
+            return *available_mode
         }
-        return vk::PresentModeKHR::FIFO_KHR;
     }
-    fn choose_swap_extent(capabilities: &vk::SurfaceCapabilitiesKHR, window: &Window) -> vk::Extent2D {
-        //If width/height of current extent is u32::MAX, the window manager allows selecting an extent different from the window resolution
-        if capabilities.current_extent.width != u32::MAX { //Extent is specified already, must use it
-            return capabilities.current_extent
-        } else {
-            let window_size = window.inner_size();
-            let mut actual_extent = vk::Extent2D{width: window_size.width, height: window_size.height};
-            actual_extent.width = actual_extent.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width);
-            actual_extent.height = actual_extent.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height);
-            return actual_extent;
-        }
+    return vk::PresentModeKHR::FIFO_KHR; //Always supported
+}
+fn choose_swap_extent(capabilities: &vk::SurfaceCapabilitiesKHR, window: &Window) -> vk::Extent2D {
+    //If width/height of current extent is u32::MAX, the window manager allows selecting an extent different from the window resolution
+    if capabilities.current_extent.width != u32::MAX { //Extent is specified already, must use it
+        return capabilities.current_extent
+    } else {
+        let window_size = window.inner_size();
+        let mut actual_extent = vk::Extent2D{width: window_size.width, height: window_size.height};
+        actual_extent.width = actual_extent.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width);
+        actual_extent.height = actual_extent.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height);
+        return actual_extent;
     }
+}
 
-    //// Creating swapchain
-    let (swapchain, image_format, swapchain_extent) = {
-        let (surface_capabilities, formats, present_modes) = query_swap_chain_support(&physical_device, &surface, &instance);
-        let surface_format = choose_swap_surface_format(&formats);
-        let present_mode = choose_swap_present_mode(&present_modes);
-        let swap_extent = choose_swap_extent(&surface_capabilities, &window);
-        let image_count = {
-            let mut count = surface_capabilities.min_image_count + 1;
-            if surface_capabilities.min_image_count > 0 && count > surface_capabilities.max_image_count {count = surface_capabilities.max_image_count}
-            count
-        };
-        let mut swapchain_info = vk::SwapchainCreateInfoKHRBuilder::new()
-            .surface(surface)
-            .min_image_count(image_count)
-            .image_format(surface_format.format)
-            .image_color_space(surface_format.color_space)
-            .image_extent(swap_extent)
-            .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .composite_alpha(vk::CompositeAlphaFlagBitsKHR::OPAQUE_KHR)
-            .pre_transform(surface_capabilities.current_transform)
-            .present_mode(present_mode)
-            .clipped(true);
-        if queue_family_indices[GRAPHICS_Q_IDX] != queue_family_indices[PRESENT_Q_IDX] {
-            swapchain_info = swapchain_info.image_sharing_mode(vk::SharingMode::CONCURRENT).queue_family_indices(&queue_family_indices);
-        } else {
-            swapchain_info = swapchain_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE);
-        }
-        let swapchain = unsafe {logical_device.create_swapchain_khr(&swapchain_info, None)}.expect("Could not create swapchain!");
-
-        (swapchain, surface_format.format, swap_extent)
+fn create_swapchain(context: &VulkanContext, window: &Window, preferred_present_mode: vk::PresentModeKHR, old_swapchain: vk::SwapchainKHR) -> Result<(vk::SwapchainKHR, vk::Format, vk::Extent2D), VulkanAppError> {
+    let queue_family_indices = context.queue_family_indices;
+    let (surface_capabilities, formats, present_modes) = query_swap_chain_support(&context.physical_device, &context.surface, &context.instance);
+    let surface_format = choose_swap_surface_format(&formats);
+    let present_mode = choose_swap_present_mode(&present_modes, preferred_present_mode);
+    let swap_extent = choose_swap_extent(&surface_capabilities, window);
+    let image_count = {
+        let mut count = surface_capabilities.min_image_count + 1;
+        if surface_capabilities.min_image_count > 0 && count > surface_capabilities.max_image_count {count = surface_capabilities.max_image_count}
+        count
     };
-    let swapchain_images = unsafe {logical_device.get_swapchain_images_khr(swapchain, None)}.unwrap();
+    let mut swapchain_info = vk::SwapchainCreateInfoKHRBuilder::new()
+        .surface(context.surface)
+        .min_image_count(image_count)
+        .image_format(surface_format.format)
+        .image_color_space(surface_format.color_space)
+        .image_extent(swap_extent)
+        .image_array_layers(1)
+        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .composite_alpha(vk::CompositeAlphaFlagBitsKHR::OPAQUE_KHR)
+        .pre_transform(surface_capabilities.current_transform)
+        .present_mode(present_mode)
+        .clipped(true)
+        .old_swapchain(old_swapchain);
+    if queue_family_indices[GRAPHICS_Q_IDX] != queue_family_indices[PRESENT_Q_IDX] {
+        swapchain_info = swapchain_info.image_sharing_mode(vk::SharingMode::CONCURRENT).queue_family_indices(&queue_family_indices);
+    } else {
+        swapchain_info = swapchain_info.image_sharing_mode(vk::SharingMode::EXCLUSIVE);
+    }
+    let swapchain = unsafe {context.device.create_swapchain_khr(&swapchain_info, None)}.map_err(VulkanAppError::Swapchain)?;
 
-    //// Image views
+    Ok((swapchain, surface_format.format, swap_extent))
+}
+
+fn create_image_views(logical_device: &DeviceLoader, swapchain: vk::SwapchainKHR, image_format: vk::Format) -> Result<Vec<vk::ImageView>, VulkanAppError> {
+    let swapchain_images = unsafe {logical_device.get_swapchain_images_khr(swapchain, None)}.map_err(VulkanAppError::Swapchain)?;
     let mut image_views = Vec::new();
-    for i in 0..swapchain_images.len() {
+    for image in swapchain_images {
         let image_view_info = vk::ImageViewCreateInfoBuilder::new()
-            .image(swapchain_images[i])
+            .image(image)
             .view_type(vk::ImageViewType::_2D)
             .format(image_format)
             .components(vk::ComponentMapping{
@@ -362,148 +803,215 @@ fn init_vulkan(window: &Window) -> VulkanApp {
                 base_array_layer: 0,
                 layer_count: 1,
             });
-        let image_view = unsafe {logical_device.create_image_view(&image_view_info, None)}.unwrap();
+        let image_view = unsafe {logical_device.create_image_view(&image_view_info, None)}.map_err(VulkanAppError::vulkan("create image view"))?;
         image_views.push(image_view);
     }
+    Ok(image_views)
+}
 
-    //// Push constants
-    let push_constants = [1.0];
-
-    //// Graphics pipeline
-    let (graphics_pipeline, graphics_pipeline_layout, renderpass) = {
-        // Render pass
-        let color_attachments = [vk::AttachmentDescriptionBuilder::new()
+//With more than one sample per pixel, draws into a multisampled attachment and resolves it into the second, see create_framebuffers
+fn create_renderpass(logical_device: &DeviceLoader, image_format: vk::Format, final_layout: vk::ImageLayout, samples: vk::SampleCountFlagBits) -> Result<vk::RenderPass, VulkanAppError> {
+    let multisampled = samples != vk::SampleCountFlagBits::_1;
+    // Render pass
+    let mut color_attachments = vec![vk::AttachmentDescriptionBuilder::new()
+        .format(image_format)
+        .samples(samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(if multisampled {vk::AttachmentStoreOp::DONT_CARE} else {vk::AttachmentStoreOp::STORE}) //Only the resolved image is kept
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(if multisampled {vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL} else {final_layout})];
+    if multisampled {
+        color_attachments.push(vk::AttachmentDescriptionBuilder::new()
             .format(image_format)
             .samples(vk::SampleCountFlagBits::_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE) //Every pixel is overwritten by the resolve
             .store_op(vk::AttachmentStoreOp::STORE)
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)];
-        // Subpass
-        let dependencies = [vk::SubpassDependencyBuilder::new()
-            .src_subpass(vk::SUBPASS_EXTERNAL)
-            .dst_subpass(0)
+            .final_layout(final_layout));
+    }
+    // Subpass
+    let mut dependencies = vec![vk::SubpassDependencyBuilder::new()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)];
+    if final_layout == vk::ImageLayout::TRANSFER_SRC_OPTIMAL { //Image is copied out after the pass, which must wait for the writes
+        dependencies.push(vk::SubpassDependencyBuilder::new()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
             .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .src_access_mask(vk::AccessFlags::empty())
-            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)];
-        let color_attachment_refs = [vk::AttachmentReferenceBuilder::new()
-            .attachment(0) //First attachment in array -> color_attachment
-            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
-        let subpasses = [vk::SubpassDescriptionBuilder::new()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&color_attachment_refs)];
-        
-        let renderpass_info = vk::RenderPassCreateInfoBuilder::new()
-            .attachments(&color_attachments)
-            .subpasses(&subpasses)
-            .dependencies(&dependencies);
-        let renderpass = unsafe {logical_device.create_render_pass(&renderpass_info, None)}.expect("Failed to create renderpass!");
-
-
-        let entry_point = CString::new("main").unwrap();
-        // Shader modules
-        let vert_decoded = erupt::utils::decode_spv(VERT_SHADER).unwrap();
-        let vert_shader_module_info = vk::ShaderModuleCreateInfoBuilder::new().code(&vert_decoded);
-        let vert_shader_module = unsafe {logical_device.create_shader_module(&vert_shader_module_info, None)}.unwrap();
-        let vert_stage_info = vk::PipelineShaderStageCreateInfoBuilder::new()
-            .stage(vk::ShaderStageFlagBits::VERTEX)
-            .module(vert_shader_module)
-            .name(&entry_point);
-
-        let frag_decoded = erupt::utils::decode_spv(FRAG_SHADER).unwrap();
-        let frag_shader_module_info = vk::ShaderModuleCreateInfoBuilder::new().code(&frag_decoded);
-        let frag_shader_module = unsafe {logical_device.create_shader_module(&frag_shader_module_info, None)}.unwrap();
-        let frag_stage_info = vk::PipelineShaderStageCreateInfoBuilder::new()
-            .stage(vk::ShaderStageFlagBits::FRAGMENT)
-            .module(frag_shader_module)
-            .name(&entry_point);
-        
-        let shader_stages = [vert_stage_info, frag_stage_info];
-
-        // Vertex input settings (since vertices are hard-coded in the shader for now, ít is specified to take no input)
-        let pipeline_vertex_input_state_info = vk::PipelineVertexInputStateCreateInfoBuilder::new();
-        // Input assembly settings
-        let pipeline_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
-            .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
-            .primitive_restart_enable(false);
-        // Viewport settings
-        let viewports = [vk::ViewportBuilder::new()
-            .x(0.0)
-            .y(0.0)
-            .width(swapchain_extent.width as f32)
-            .height(swapchain_extent.height as f32)
-            .min_depth(0.0)
-            .max_depth(1.0)];
-        let scissor_rects = [vk::Rect2DBuilder::new()
-            .offset(vk::Offset2D{x: 0, y: 0})
-            .extent(swapchain_extent)];
-        let pipeline_viewport_state_info = vk::PipelineViewportStateCreateInfoBuilder::new()
-            .viewports(&viewports)
-            .scissors(&scissor_rects);
-        // Rasterizer settings
-        let pipeline_rasterization_state_info = vk::PipelineRasterizationStateCreateInfoBuilder::new()
-            .depth_clamp_enable(false)
-            .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(vk::CullModeFlags::BACK)
-            .front_face(vk::FrontFace::CLOCKWISE)
-            .depth_bias_enable(false);
-        // Multisampling settings
-        let pipeline_multisample_state_info = vk::PipelineMultisampleStateCreateInfoBuilder::new()
-            .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlagBits::_1);
-        // Color blending settings
-        let pipeline_color_blend_attachment_states = [vk::PipelineColorBlendAttachmentStateBuilder::new()
-            .color_write_mask(
-                vk::ColorComponentFlags::R |
-                vk::ColorComponentFlags::G |
-                vk::ColorComponentFlags::B |
-                vk::ColorComponentFlags::A)
-            .blend_enable(false)];
-        let pipeline_color_blend_state_info = vk::PipelineColorBlendStateCreateInfoBuilder::new()
-            .logic_op_enable(false)
-            .attachments(&pipeline_color_blend_attachment_states);
-        
-        // Pipeline layout
-        let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
-            .stage_flags(vk::ShaderStageFlags::VERTEX)
-            .offset(0)
-            .size((push_constants.len()*size_of::<f32>()) as u32)];
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
-            .push_constant_ranges(&push_constant_ranges);
-        let pipeline_layout = unsafe {logical_device.create_pipeline_layout(&pipeline_layout_info, None)}.unwrap();
-        
-        let graphics_pipeline_infos = [vk::GraphicsPipelineCreateInfoBuilder::new()
-            .stages(&shader_stages)
-            .vertex_input_state(&pipeline_vertex_input_state_info)
-            .input_assembly_state(&pipeline_input_assembly_state_info)
-            .viewport_state(&pipeline_viewport_state_info)
-            .rasterization_state(&pipeline_rasterization_state_info)
-            .multisample_state(&pipeline_multisample_state_info)
-            .color_blend_state(&pipeline_color_blend_state_info)
-            .layout(pipeline_layout)
-            .render_pass(renderpass)
-            .subpass(0)];
-        let graphics_pipeline = unsafe {logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &graphics_pipeline_infos, None)}.unwrap()[0];
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ));
+    }
+    let color_attachment_refs = [vk::AttachmentReferenceBuilder::new()
+        .attachment(0) //First attachment in array -> color_attachment
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let resolve_attachment_refs = [vk::AttachmentReferenceBuilder::new()
+        .attachment(1)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let mut subpass = vk::SubpassDescriptionBuilder::new()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs);
+    if multisampled {
+        subpass = subpass.resolve_attachments(&resolve_attachment_refs);
+    }
+    let subpasses = [subpass];
+    
+    let renderpass_info = vk::RenderPassCreateInfoBuilder::new()
+        .attachments(&color_attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies);
+    unsafe {logical_device.create_render_pass(&renderpass_info, None)}.map_err(VulkanAppError::vulkan("create render pass"))
+}
 
-        //Once the graphics pipeline has been created, the SPIR-V bytecode is compiled into the pipeline itself
-        //The shader modules can therefore be destroyed already
-        unsafe {
-            logical_device.destroy_shader_module(vert_shader_module, None);
-            logical_device.destroy_shader_module(frag_shader_module, None);
-        }
+fn create_pipeline_layout(logical_device: &DeviceLoader, descriptor_set_layout: vk::DescriptorSetLayout) -> Result<vk::PipelineLayout, VulkanAppError> {
+    let push_constant_ranges = [vk::PushConstantRangeBuilder::new()
+        .stage_flags(push_constant_stages())
+        .offset(0)
+        .size(size_of::<PushConstants>() as u32)];
+    let set_layouts = [descriptor_set_layout];
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfoBuilder::new()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    unsafe {logical_device.create_pipeline_layout(&pipeline_layout_info, None)}.map_err(VulkanAppError::Pipeline)
+}
 
-        (graphics_pipeline, pipeline_layout, renderpass)
-    };
+fn decode_shader(spirv: &[u8]) -> Result<Vec<u32>, VulkanAppError> {
+    return erupt::utils::decode_spv(spirv).map_err(|err| VulkanAppError::ShaderDecode(err.to_string()));
+}
 
-    //// Framebuffers
-    let mut swapchain_framebuffers = Vec::new();
-    for i in 0..image_views.len() {
-        let attachments = [image_views[i]];
+fn create_graphics_pipeline(
+    logical_device: &DeviceLoader,
+    renderpass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+    samples: vk::SampleCountFlagBits,
+    vert_code: &[u32],
+    frag_code: &[u32]
+) -> Result<vk::Pipeline, VulkanAppError> {
+    let entry_point = CString::new("main").unwrap();
+    // Shader modules
+    let vert_shader_module_info = vk::ShaderModuleCreateInfoBuilder::new().code(vert_code);
+    let vert_shader_module = unsafe {logical_device.create_shader_module(&vert_shader_module_info, None)}.map_err(VulkanAppError::vulkan("create vertex shader module"))?;
+    let vert_stage_info = vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::VERTEX)
+        .module(vert_shader_module)
+        .name(&entry_point);
+
+    let frag_shader_module_info = vk::ShaderModuleCreateInfoBuilder::new().code(frag_code);
+    let frag_shader_module = unsafe {logical_device.create_shader_module(&frag_shader_module_info, None)};
+    let frag_shader_module = frag_shader_module.map_err(|err| {
+        unsafe {logical_device.destroy_shader_module(vert_shader_module, None)};
+        VulkanAppError::vulkan("create fragment shader module")(err)
+    })?;
+    let frag_stage_info = vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::FRAGMENT)
+        .module(frag_shader_module)
+        .name(&entry_point);
+    
+    let shader_stages = [vert_stage_info, frag_stage_info];
+
+    // Vertex input settings (since vertices are hard-coded in the shader for now, ít is specified to take no input)
+    let pipeline_vertex_input_state_info = vk::PipelineVertexInputStateCreateInfoBuilder::new();
+    // Input assembly settings
+    let pipeline_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfoBuilder::new()
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+        .primitive_restart_enable(false);
+    // Viewport settings, set per pane when recording
+    let pipeline_viewport_state_info = vk::PipelineViewportStateCreateInfoBuilder::new()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let pipeline_dynamic_state_info = vk::PipelineDynamicStateCreateInfoBuilder::new()
+        .dynamic_states(&dynamic_states);
+    // Rasterizer settings
+    let pipeline_rasterization_state_info = vk::PipelineRasterizationStateCreateInfoBuilder::new()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::BACK)
+        .front_face(vk::FrontFace::CLOCKWISE)
+        .depth_bias_enable(false);
+    // Multisampling settings. With MSAA every sample is shaded, as the fractal aliases inside the quad and not along its edges
+    let pipeline_multisample_state_info = vk::PipelineMultisampleStateCreateInfoBuilder::new()
+        .sample_shading_enable(samples != vk::SampleCountFlagBits::_1)
+        .min_sample_shading(1.0)
+        .rasterization_samples(samples);
+    // Color blending settings
+    let pipeline_color_blend_attachment_states = [vk::PipelineColorBlendAttachmentStateBuilder::new()
+        .color_write_mask(
+            vk::ColorComponentFlags::R |
+            vk::ColorComponentFlags::G |
+            vk::ColorComponentFlags::B |
+            vk::ColorComponentFlags::A)
+        .blend_enable(false)];
+    let pipeline_color_blend_state_info = vk::PipelineColorBlendStateCreateInfoBuilder::new()
+        .logic_op_enable(false)
+        .attachments(&pipeline_color_blend_attachment_states);
+    
+    let graphics_pipeline_infos = [vk::GraphicsPipelineCreateInfoBuilder::new()
+        .stages(&shader_stages)
+        .vertex_input_state(&pipeline_vertex_input_state_info)
+        .input_assembly_state(&pipeline_input_assembly_state_info)
+        .viewport_state(&pipeline_viewport_state_info)
+        .rasterization_state(&pipeline_rasterization_state_info)
+        .multisample_state(&pipeline_multisample_state_info)
+        .color_blend_state(&pipeline_color_blend_state_info)
+        .dynamic_state(&pipeline_dynamic_state_info)
+        .layout(pipeline_layout)
+        .render_pass(renderpass)
+        .subpass(0)];
+    let graphics_pipelines = unsafe {logical_device.create_graphics_pipelines(vk::PipelineCache::null(), &graphics_pipeline_infos, None)};
+
+    //Once the graphics pipeline has been created, the SPIR-V bytecode is compiled into the pipeline itself
+    //The shader modules can therefore be destroyed already, or if creation failed, they are of no use
+    unsafe {
+        logical_device.destroy_shader_module(vert_shader_module, None);
+        logical_device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    graphics_pipelines.result().map(|pipelines| pipelines[0]).map_err(VulkanAppError::Pipeline)
+}
+
+fn create_compute_pipeline(logical_device: &DeviceLoader, pipeline_layout: vk::PipelineLayout, comp_code: &[u32]) -> Result<vk::Pipeline, VulkanAppError> {
+    let entry_point = CString::new("main").unwrap();
+    let comp_shader_module_info = vk::ShaderModuleCreateInfoBuilder::new().code(comp_code);
+    let comp_shader_module = unsafe {logical_device.create_shader_module(&comp_shader_module_info, None)}.map_err(VulkanAppError::vulkan("create compute shader module"))?;
+    let comp_stage_info = vk::PipelineShaderStageCreateInfoBuilder::new()
+        .stage(vk::ShaderStageFlagBits::COMPUTE)
+        .module(comp_shader_module)
+        .name(&entry_point);
+
+    let compute_pipeline_infos = [vk::ComputePipelineCreateInfoBuilder::new()
+        .stage(*comp_stage_info)
+        .layout(pipeline_layout)];
+    let compute_pipelines = unsafe {logical_device.create_compute_pipelines(vk::PipelineCache::null(), &compute_pipeline_infos, None)};
+    unsafe {logical_device.destroy_shader_module(comp_shader_module, None)};
+
+    compute_pipelines.result().map(|pipelines| pipelines[0]).map_err(VulkanAppError::Pipeline)
+}
+
+//multisample_image must be given if the renderpass is multisampled, the image views are then resolved into
+fn create_framebuffers(
+    logical_device: &DeviceLoader,
+    image_views: &[vk::ImageView],
+    multisample_image: Option<&MultisampleImage>,
+    renderpass: vk::RenderPass,
+    swapchain_extent: vk::Extent2D
+) -> Result<Vec<vk::Framebuffer>, VulkanAppError> {
+    let mut framebuffers = Vec::new();
+    for image_view in image_views {
+        let attachments = match multisample_image {
+            Some(multisample_image) => vec![multisample_image.view, *image_view],
+            None => vec![*image_view],
+        };
 
         let framebuffer_info = vk::FramebufferCreateInfoBuilder::new()
             .render_pass(renderpass)
@@ -512,217 +1020,374 @@ fn init_vulkan(window: &Window) -> VulkanApp {
             .height(swapchain_extent.height)
             .layers(1);
 
-        let framebuffer = unsafe {logical_device.create_framebuffer(&framebuffer_info, None)}.expect("Could not create framebuffer!");
-        swapchain_framebuffers.push(framebuffer);
+        let framebuffer = unsafe {logical_device.create_framebuffer(&framebuffer_info, None)}.map_err(VulkanAppError::vulkan("create framebuffer"))?;
+        framebuffers.push(framebuffer);
     }
+    Ok(framebuffers)
+}
 
-    //// Command pool and buffers
-    let command_pool_info = vk::CommandPoolCreateInfoBuilder::new()
-        .queue_family_index(queue_family_indices[GRAPHICS_Q_IDX]);
-    let command_pool = unsafe {logical_device.create_command_pool(&command_pool_info, None)}.expect("Could not create command pool!");
-
-    let command_buffers = allocate_and_record_command_buffers(
-        swapchain_images.len() as u32,
-        command_pool,
-        &logical_device,
-        swapchain_extent,
-        &swapchain_framebuffers,
-        renderpass,
-        graphics_pipeline,
-        graphics_pipeline_layout,
-        &push_constants
-    );
-
-    //// Create semaphores for in-render-pass synchronization
-    let mut image_available_sems = SmallVec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-    let mut render_finished_sems = SmallVec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-    let mut in_flight_fences = SmallVec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-    let mut images_in_flight = SmallVec::with_capacity(swapchain_images.len());
-    unsafe {
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            image_available_sems.push(logical_device.create_semaphore(&vk::SemaphoreCreateInfoBuilder::new(), None).unwrap());
-            render_finished_sems.push(logical_device.create_semaphore(&vk::SemaphoreCreateInfoBuilder::new(), None).unwrap());
-            in_flight_fences.push(logical_device.create_fence(&vk::FenceCreateInfoBuilder::new().flags(vk::FenceCreateFlags::SIGNALED), None).unwrap());
-        }
-        for _ in 0..swapchain_images.len() {
-            images_in_flight.push(vk::Fence::null());
+//Records the full render pass, drawing every pane, into a command buffer that is already recording
+#[allow(clippy::too_many_arguments)]
+fn record_render_pass(
+    logical_device: &DeviceLoader,
+    command_buffer: vk::CommandBuffer,
+    framebuffer: vk::Framebuffer,
+    extent: vk::Extent2D,
+    renderpass: vk::RenderPass,
+    graphics_pipeline_layout: vk::PipelineLayout,
+    descriptor_set: vk::DescriptorSet,
+    panes: &[Pane]
+) {
+    //Start render pass
+    let render_area = vk::Rect2DBuilder::new()
+        .offset(vk::Offset2D{x: 0, y: 0})
+        .extent(extent);
+    let mut clear_color = [vk::ClearValue::default()]; clear_color[0].color.float32 = [0.0, 0.0, 0.0, 1.0];
+    let renderpass_begin_info = vk::RenderPassBeginInfoBuilder::new()
+        .render_pass(renderpass)
+        .framebuffer(framebuffer)
+        .render_area(*render_area)
+        .clear_values(&clear_color);
+    unsafe {logical_device.cmd_begin_render_pass(command_buffer, &renderpass_begin_info, vk::SubpassContents::INLINE)};
+
+    //Drawing commands, one full screen quad per pane, limited to it by the viewport
+    unsafe {logical_device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, graphics_pipeline_layout, 0, &[descriptor_set], &[])};
+    for pane in panes {
+        let viewports = [vk::ViewportBuilder::new()
+            .x(pane.area.offset.x as f32)
+            .y(pane.area.offset.y as f32)
+            .width(pane.area.extent.width as f32)
+            .height(pane.area.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)];
+        let scissor_rects = [pane.area.into_builder()];
+        unsafe {
+            logical_device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pane.pipeline);
+            logical_device.cmd_set_viewport(command_buffer, 0, &viewports);
+            logical_device.cmd_set_scissor(command_buffer, 0, &scissor_rects);
+            logical_device.cmd_push_constants(
+                command_buffer,
+                graphics_pipeline_layout,
+                push_constant_stages(),
+                0,
+                size_of::<PushConstants>() as u32,
+                &pane.push_constants as *const PushConstants as *const c_void
+            );
+            logical_device.cmd_draw(command_buffer, 4, 1, 0, 0);
+            //In order: vertexCount, instanceCount, firstVertex, firstInstance
         }
     }
 
-    VulkanApp {
-        _entry: entry,
-        instance,
-        device: logical_device,
-        messenger,
-        surface,
-        graphics_queue,
-        present_queue,
-        swapchain,
-        swapchain_extent,
-        image_views,
-        graphics_pipeline,
-        graphics_pipeline_layout,
-        renderpass,
-        framebuffers: swapchain_framebuffers,
-        command_pool,
-        command_buffers,
-        image_available_sems,
-        render_finished_sems,
-        in_flight_fences,
-        images_in_flight,
+    //End the render pass
+    unsafe {logical_device.cmd_end_render_pass(command_buffer)};
+}
+
+//Statistics of the last frames, and whether the shaders being watched failed to compile
+fn window_title(stats: &str, shader_failed: bool) -> String {
+    let mut title = APP_TITLE.to_string();
+    if !stats.is_empty() {
+        title += &format!(" - {}", stats);
+    }
+    if shader_failed {
+        title += " - shader compilation failed, see console";
     }
+    return title;
 }
 
-fn init_debug_messenger_info() -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'static> {
-    let messenger_info = vk::DebugUtilsMessengerCreateInfoEXTBuilder::new()
-    .message_severity(
-        //vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE_EXT |
-        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT |
-        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT
-    )
-    .message_type(
-        vk::DebugUtilsMessageTypeFlagsEXT::GENERAL_EXT |
-        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT |
-        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT
-    )
-    .pfn_user_callback(Some(debug_callback));
-    messenger_info
+//Prints what went wrong and exits. Whatever Vulkan objects were created are left for the driver to clean up with the process
+fn exit_with_error(error: VulkanAppError) -> ! {
+    eprintln!("Error: {}", error);
+    if let VulkanAppError::DeviceIndexOutOfRange{..} | VulkanAppError::DeviceNotFound(_) | VulkanAppError::UnsuitableDevice(_) = error {
+        eprintln!("Pick another device with --gpu <INDEX|NAME>, --list-gpus shows them all");
+    }
+    std::process::exit(error.exit_code());
 }
 
-fn allocate_and_record_command_buffers(
-    amount: u32,
-    command_pool: vk::CommandPool,
-    logical_device: &DeviceLoader,
-    swapchain_extent: vk::Extent2D,
-    swapchain_framebuffers: &Vec<vk::Framebuffer>,
-    renderpass: vk::RenderPass,
-    graphics_pipeline: vk::Pipeline,
-    graphics_pipeline_layout: vk::PipelineLayout,
-    push_constants: &[f32; 1]
-) -> SmallVec<vk::CommandBuffer> {
+fn main() {
+    let args = cli::Args::parse();
+    //Validation messages are logged under vulkan::, at every level since --vulkan-log already picked the severities
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn,vulkan=trace")).init();
+    if let Some(format) = args.list_gpus {
+        gpus::list_gpus(format, &args.device_features().required).unwrap_or_else(|err| exit_with_error(err));
+        return
+    }
+    let view = View {
+        center: [args.center_re, args.center_im],
+        zoom: args.zoom.min(MAX_ZOOM),
+        max_iterations: args.iterations,
+        coloring: args.coloring,
+        formula: args.formula,
+    };
+    let mut scene = Scene {
+        view,
+        julia_view: View {center: [DoubleDouble::ZERO; 2], zoom: JULIA_ZOOM, ..view},
+        julia_c: [args.julia_re, args.julia_im],
+        layout: args.layout,
+    };
 
-    let command_buffer_allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
-        .command_pool(command_pool)
-        .level(vk::CommandBufferLevel::PRIMARY)
-        .command_buffer_count(amount);
-    let command_buffers = unsafe {logical_device.allocate_command_buffers(&command_buffer_allocate_info)}.expect("Could not create command buffers!");
-
-    for i in 0..command_buffers.len() {
-        //Begin recording command buffer
-        let command_buffer_begin_info = vk::CommandBufferBeginInfoBuilder::new();
-        unsafe {logical_device.begin_command_buffer(command_buffers[i], &command_buffer_begin_info)}.expect("Could not begin command buffer recording!");
-
-        //Start render pass
-        let render_area = vk::Rect2DBuilder::new()
-            .offset(vk::Offset2D{x: 0, y: 0})
-            .extent(swapchain_extent);
-        let mut clear_color = [vk::ClearValue::default()]; clear_color[0].color.float32 = [0.0, 0.0, 0.0, 1.0];
-        let renderpass_begin_info = vk::RenderPassBeginInfoBuilder::new()
-            .render_pass(renderpass)
-            .framebuffer(swapchain_framebuffers[i])
-            .render_area(*render_area)
-            .clear_values(&clear_color);
-        unsafe {logical_device.cmd_begin_render_pass(command_buffers[i], &renderpass_begin_info, vk::SubpassContents::INLINE)};
+    //The built-in palette first, then the ones given on the command line
+    let mut palettes = vec![Palette::built_in()];
+    palettes.extend(palette::load_palettes(&args.palette));
+    let mut palette_index = if palettes.len() > 1 {1} else {0}; //Start with the first one loaded, if any
 
-        //Drawing commands
-        unsafe {
-            logical_device.cmd_bind_pipeline(command_buffers[i], vk::PipelineBindPoint::GRAPHICS, graphics_pipeline);
-            logical_device.cmd_push_constants(command_buffers[i], graphics_pipeline_layout, vk::ShaderStageFlags::VERTEX,0, (push_constants.len()*size_of::<f32>()) as u32, push_constants.as_ptr() as *const c_void);
-            logical_device.cmd_draw(command_buffers[i], 4, 1, 0, 0);
-            //In order: vertexCount, instanceCount, firstVertex, firstInstance
-        }
+    let formula_parameters = FormulaParameters::new(args.power, &args.polynomial);
 
-        //End the render pass and end recording
-        unsafe {
-            logical_device.cmd_end_render_pass(command_buffers[i]);    
-            logical_device.end_command_buffer(command_buffers[i]).expect("Failed recording command buffer!");
-        }
+    if let Some(cli::Command::RenderPoster{output, width, height, tile_size}) = &args.command {
+        poster::render_poster(output, *width, *height, *tile_size, &scene, args.precision, args.supersampling(), &palettes[palette_index], &formula_parameters, &args.context_options())
+            .unwrap_or_else(|err| exit_with_error(err));
+        return
     }
-    return command_buffers;
-}
 
-fn main() {
-    let (window, event_loop) = init_window();
-    let mut vulkan_app = init_vulkan(&window);
-    let mut current_frame = 0;
+    //Headless mode: render a single frame to a PNG file without opening a window
+    if let Some(output_path) = &args.output {
+        offscreen::render_to_png(output_path, args.width, args.height, &scene, args.precision, args.renderer(), args.supersampling(), &palettes[palette_index], &formula_parameters, &args.context_options())
+            .unwrap_or_else(|err| exit_with_error(err));
+        return
+    }
+
+    //Before opening the window, so a bad path is reported like any other error
+    let stats_csv = args.stats_csv.as_deref().map(|path| File::create(path).map_err(VulkanAppError::io(path))).transpose()
+        .unwrap_or_else(|err| exit_with_error(err));
+    let (window, event_loop) = init_window(args.width, args.height);
+    let mut vulkan_app = init_vulkan(&window, &args.context_options(), args.present_mode.to_vk(), args.precision, args.renderer(), args.progressive, args.supersampling(), args.frames_in_flight as usize, &scene)
+        .unwrap_or_else(|err| exit_with_error(err));
+    if args.precision == Some(Precision::Double) && !supports_double(&vulkan_app.context) {
+        println!("Device does not support double precision shaders, using perturbation instead");
+    }
+    vulkan_app.shader_data.set_palette(&palettes[palette_index]);
+    vulkan_app.shader_data.set_formula_parameters(&formula_parameters);
     let mut timer = time::Instant::now();
-    let speed = 0.1;
-    let mut push_constants = [0.0];
+    let speed = args.speed;
+    let mut theta: f64 = 0.0;
+    let mut base_zoom = scene.view.zoom; //Zoom at theta = 0, the animation zooms relative to this
     let mut zooming = true;
+    let mut view_changed = false;
+    let mut redraw = true; //Whether the last frame presented is out of date for reasons other than the view
+    let mut framebuffer_resized = false;
+    let mut cursor_position = winit::dpi::PhysicalPosition::new(0.0, 0.0);
+    let mut dragging = None; //The fractal being dragged, even if the cursor leaves its pane
+    let mut press_position = cursor_position;
+    let mut shader_watcher = args.watch_shaders.as_deref().map(ShaderWatcher::new);
+    let mut shader_failed = false;
+    let mut frame_stats = FrameStats::new(vulkan_app.frames.len(), stats_csv);
+    let mut title_timer = time::Instant::now(); //Since the title last showed new statistics
 
     //The event loop hijacks the main thread, so once it closes the entire program exits.
     //All cleanup operations should be handled either before the main loop, inside the mainloop,
     //or in the drop function of any data moved into the closure
     event_loop.run(move |event,_,control_flow| {
-        *control_flow = ControlFlow::Poll;
         match event {
             Event::WindowEvent{event, ..} => match event {
                 WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 },
-                WindowEvent::KeyboardInput{input,..} => {
+                WindowEvent::Resized(_) => {
+                    framebuffer_resized = true;
+                },
+                WindowEvent::KeyboardInput{input,..} if input.state == ElementState::Pressed => {
+                    //Navigation keys move the view of the pane under the cursor
+                    let (fractal, extent, _, _) = scene.layout.pane_at(cursor_position.x, cursor_position.y, vulkan_app.swapchain_extent);
+                    let view = scene.view_mut(fractal);
+                    let mut navigated = true;
                     match input.virtual_keycode {
                         Some(VirtualKeyCode::Space) => {
-                            if input.state == winit::event::ElementState::Pressed {
-                                zooming = !zooming;
-                            }
+                            zooming = !zooming;
+                            base_zoom = scene.view.zoom * animation_scale(theta); //Continue the animation from where the view is now
+                            navigated = false;
+                        },
+                        Some(VirtualKeyCode::C) => {
+                            scene.view.coloring = scene.view.coloring.next();
+                            println!("Coloring: {:?}", scene.view.coloring);
+                            view_changed = true;
+                            navigated = false;
+                        },
+                        Some(VirtualKeyCode::F) => {
+                            scene.view.formula = scene.view.formula.next();
+                            println!("Formula: {:?}", scene.view.formula);
+                            view_changed = true;
+                            navigated = false;
+                        },
+                        Some(VirtualKeyCode::J) => {
+                            scene.layout = scene.layout.next();
+                            println!("Layout: {:?}", scene.layout);
+                            view_changed = true;
+                            navigated = false;
+                        },
+                        Some(VirtualKeyCode::P) => {
+                            palette_index = (palette_index + 1) % palettes.len();
+                            println!("Palette: {}", palettes[palette_index].name);
+                            vulkan_app.shader_data.set_palette(&palettes[palette_index]);
+                            redraw = true;
+                            navigated = false;
                         },
                         Some(VirtualKeyCode::Escape) => {
                             *control_flow = ControlFlow::Exit;
+                            navigated = false;
+                        },
+                        Some(VirtualKeyCode::Left) => view.pan_pixels(PAN_STEP * extent.width as f64, 0.0, extent),
+                        Some(VirtualKeyCode::Right) => view.pan_pixels(-PAN_STEP * extent.width as f64, 0.0, extent),
+                        Some(VirtualKeyCode::Up) => view.pan_pixels(0.0, PAN_STEP * extent.height as f64, extent),
+                        Some(VirtualKeyCode::Down) => view.pan_pixels(0.0, -PAN_STEP * extent.height as f64, extent),
+                        Some(VirtualKeyCode::Plus | VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd) => {
+                            view.zoom_at(ZOOM_STEP, extent.width as f64 / 2.0, extent.height as f64 / 2.0, extent)
                         },
-                        _ => (),
+                        Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) => {
+                            view.zoom_at(1.0 / ZOOM_STEP, extent.width as f64 / 2.0, extent.height as f64 / 2.0, extent)
+                        },
+                        _ => navigated = false,
+                    }
+                    if navigated { //Taking over the Mandelbrot view stops the animation
+                        if fractal == Fractal::Mandelbrot {zooming = false}
+                        view_changed = true;
                     }
                 },
+                WindowEvent::MouseInput{button: MouseButton::Left, state, ..} => {
+                    let (fractal, extent, x, y) = scene.layout.pane_at(cursor_position.x, cursor_position.y, vulkan_app.swapchain_extent);
+                    if state == ElementState::Pressed {
+                        dragging = Some(fractal);
+                        press_position = cursor_position;
+                    } else {
+                        dragging = None;
+                        //Clicking the Mandelbrot set picks the point the Julia set is drawn for
+                        let click_distance = (cursor_position.x - press_position.x).hypot(cursor_position.y - press_position.y);
+                        if fractal == Fractal::Mandelbrot && click_distance < CLICK_DISTANCE {
+                            let offset = scene.view.pixel_offset(x, y, extent);
+                            scene.julia_c = [(scene.view.center[0] + offset[0]).hi, (scene.view.center[1] + offset[1]).hi];
+                            println!("Julia set of c = {} + {}i", scene.julia_c[0], scene.julia_c[1]);
+                            view_changed = true;
+                        }
+                    }
+                },
+                WindowEvent::CursorMoved{position, ..} => {
+                    if let Some(fractal) = dragging {
+                        let (_, extent, _, _) = scene.layout.pane_at(press_position.x, press_position.y, vulkan_app.swapchain_extent);
+                        scene.view_mut(fractal).pan_pixels(position.x - cursor_position.x, position.y - cursor_position.y, extent);
+                        if fractal == Fractal::Mandelbrot {zooming = false}
+                        view_changed = true;
+                    }
+                    cursor_position = position;
+                },
+                WindowEvent::MouseWheel{delta, ..} => {
+                    let notches = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y as f64,
+                        MouseScrollDelta::PixelDelta(position) => position.y / PIXELS_PER_SCROLL_LINE,
+                    };
+                    let (fractal, extent, x, y) = scene.layout.pane_at(cursor_position.x, cursor_position.y, vulkan_app.swapchain_extent);
+                    scene.view_mut(fractal).zoom_at(ZOOM_STEP.powf(notches), x, y, extent);
+                    if fractal == Fractal::Mandelbrot {zooming = false}
+                    view_changed = true;
+                },
                 _ => (),
             },
             Event::MainEventsCleared => { //Main body
                 //If drawing continously, put rendering code here directly
 
-                let wait_fences = [vulkan_app.in_flight_fences[current_frame]];
-                unsafe {vulkan_app.device.wait_for_fences(&wait_fences, true, u64::MAX)}.unwrap();
+                // A minimized window has a zero-sized surface, which no swapchain can be created for. Pause until it is restored
+                let window_size = window.inner_size();
+                if window_size.width == 0 || window_size.height == 0 {
+                    *control_flow = ControlFlow::Wait;
+                    timer = time::Instant::now(); //Don't let the zoom animation jump ahead while paused
+                    return
+                }
+
+                //Development mode: rebuild the pipelines whose shaders were edited, keeping the old ones if they don't compile
+                if let Some(watcher) = &mut shader_watcher {
+                    let changed = watcher.changed();
+                    if !changed.is_empty() {
+                        match vulkan_app.graphics_pipelines.reload(&vulkan_app.context.device, &changed) {
+                            Ok(true) => {
+                                println!("Reloaded shaders");
+                                shader_failed = false;
+                                window.set_title(&window_title(&frame_stats.summary(), shader_failed));
+                                view_changed = true;
+                            },
+                            Ok(false) => (),
+                            Err(message) => {
+                                eprintln!("{}", message);
+                                eprintln!("Shader compilation failed, keeping the old shaders");
+                                shader_failed = true;
+                                window.set_title(&window_title(&frame_stats.summary(), shader_failed));
+                            },
+                        }
+                    }
+                }
+
+                if zooming {
+                    let time_delta = timer.elapsed();
+                    theta = (theta + time_delta.as_secs_f64()*speed) % 2.0;
+                    scene.view.zoom = (base_zoom / animation_scale(theta)).min(MAX_ZOOM);
+                    view_changed = true;
+                }
+
+                //Progressive mode: refine what was drawn coarsely, a few tiles per frame, unless it is about to be redone
+                if !view_changed && vulkan_app.is_refining() {
+                    redraw = true;
+                }
+
+                //The last frame is still on screen and still right, so sleep until an event changes something
+                if !view_changed && !redraw && !framebuffer_resized {
+                    *control_flow = match &shader_watcher {
+                        Some(watcher) => ControlFlow::WaitUntil(watcher.next_poll()),
+                        None => ControlFlow::Wait,
+                    };
+                    timer = time::Instant::now();
+                    frame_stats.pause();
+                    return
+                }
+                redraw = false;
+                *control_flow = ControlFlow::Poll; //Until nothing changes anymore
+                let frame_start = time::Instant::now();
+
+                let current_frame = vulkan_app.frames.index();
+                let sync = vulkan_app.frames.current();
+                let wait_fences = [sync.in_flight];
+                unsafe {vulkan_app.context.device.wait_for_fences(&wait_fences, true, u64::MAX)}
+                    .map_err(VulkanAppError::vulkan("wait for frame fence")).unwrap_or_else(|err| exit_with_error(err));
+                //The frame last drawn in this slot is done, so its queries can be read without waiting
+                if let Some(results) = vulkan_app.query_results(current_frame) {
+                    frame_stats.finished(current_frame, results.region(RENDER_PASS_REGION), results.statistics);
+                }
 
                 // Acquire index of image from the swapchain, signal semaphore once finished
-                let image_index = unsafe {
-                    vulkan_app.device.acquire_next_image_khr(
+                let acquire_result = unsafe {
+                    vulkan_app.context.device.acquire_next_image_khr(
                         vulkan_app.swapchain,
                         u64::MAX,
-                        vulkan_app.image_available_sems[current_frame],
+                        sync.image_available,
                         vk::Fence::null()
-                    ).unwrap()
+                    )
+                };
+                let image_index = match acquire_result.raw {
+                    vk::Result::SUCCESS | vk::Result::SUBOPTIMAL_KHR => acquire_result.unwrap(),
+                    vk::Result::ERROR_OUT_OF_DATE_KHR => { //Swapchain can no longer be presented to, rebuild it and try again next frame
+                        vulkan_app.recreate_swapchain(&window, &scene).unwrap_or_else(|err| exit_with_error(err));
+                        redraw = true;
+                        return
+                    },
+                    err => exit_with_error(VulkanAppError::vulkan("acquire swapchain image")(err)),
                 };
 
                 // Is the requested image already in-flight? Then wait for it to finish
                 if !vulkan_app.images_in_flight[image_index as usize].is_null() {
                     let wait_fences = [vulkan_app.images_in_flight[image_index as usize]];
-                    unsafe {vulkan_app.device.wait_for_fences(&wait_fences, true, u64::MAX)}.unwrap();
+                    unsafe {vulkan_app.context.device.wait_for_fences(&wait_fences, true, u64::MAX)}
+                        .map_err(VulkanAppError::vulkan("wait for swapchain image fence")).unwrap_or_else(|err| exit_with_error(err));
                 }
                 // The image is now being used by this frame
-                vulkan_app.images_in_flight[image_index as usize] = vulkan_app.in_flight_fences[current_frame];
+                vulkan_app.images_in_flight[image_index as usize] = sync.in_flight;
+                let cpu_start = time::Instant::now(); //Done waiting for the GPU and swapchain
 
-                //Reallocate to get the new push constants in, lazy mans method
-                if zooming {
-                    let time_delta = timer.elapsed();
-                    push_constants[0] = (push_constants[0] + time_delta.as_secs_f32()*speed) % 2.0;
-
-                    let amount = vulkan_app.command_buffers.len();
-                    unsafe {vulkan_app.device.free_command_buffers(vulkan_app.command_pool, &vulkan_app.command_buffers)};
-                    vulkan_app.command_buffers = allocate_and_record_command_buffers(
-                        amount as u32,
-                        vulkan_app.command_pool,
-                        &vulkan_app.device,
-                        vulkan_app.swapchain_extent,
-                        &vulkan_app.framebuffers,
-                        vulkan_app.renderpass,
-                        vulkan_app.graphics_pipeline,
-                        vulkan_app.graphics_pipeline_layout,
-                        &push_constants
-                    );    
+                //Only this frame's command buffer is recorded, the others may still be in flight
+                if view_changed {
+                    view_changed = false;
+                    vulkan_app.update_panes(&scene).unwrap_or_else(|err| exit_with_error(err));
                 }
+                let command_buffer = vulkan_app.record_frame(current_frame, image_index as usize).unwrap_or_else(|err| exit_with_error(err));
 
-                let wait_sems = [vulkan_app.image_available_sems[current_frame]];
+                let wait_sems = [sync.image_available];
                 let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-                let signal_sems = [vulkan_app.render_finished_sems[current_frame]];
-                let cmd_buffers = [vulkan_app.command_buffers[image_index as usize]];
+                let signal_sems = [sync.render_finished];
+                let cmd_buffers = [command_buffer];
                 let submits = [vk::SubmitInfoBuilder::new()
                     .wait_semaphores(&wait_sems)
                     .wait_dst_stage_mask(&wait_stages)
@@ -730,9 +1395,13 @@ fn main() {
                     .signal_semaphores(&signal_sems)];
                 //println!("updated constant to {}", vulkan_app.push_constants[0]);
                 unsafe {
-                    vulkan_app.device.reset_fences(&wait_fences).unwrap();
-                    vulkan_app.device.queue_submit(vulkan_app.graphics_queue, &submits, vulkan_app.in_flight_fences[current_frame]).expect("Queue submission failed!");
+                    vulkan_app.context.device.reset_fences(&wait_fences)
+                        .map_err(VulkanAppError::vulkan("reset frame fence")).unwrap_or_else(|err| exit_with_error(err));
+                    vulkan_app.context.device.queue_submit(vulkan_app.context.graphics_queue, &submits, sync.in_flight)
+                        .map_err(VulkanAppError::vulkan("submit frame")).unwrap_or_else(|err| exit_with_error(err));
                 }
+                vulkan_app.context.check_validation().unwrap_or_else(|err| exit_with_error(err));
+                let cpu_time = cpu_start.elapsed();
 
                 // Present rendered image to the swap chain such that it will show up on screen
                 let swapchains = [vulkan_app.swapchain];
@@ -741,24 +1410,116 @@ fn main() {
                     .wait_semaphores(&signal_sems)
                     .swapchains(&swapchains)
                     .image_indices(&image_indices);
-                unsafe {vulkan_app.device.queue_present_khr(vulkan_app.present_queue, &present_info)}.expect("Presenting to queue failed!");
+                let present_result = unsafe {vulkan_app.context.device.queue_present_khr(vulkan_app.context.present_queue, &present_info)};
+                vulkan_app.context.check_validation().unwrap_or_else(|err| exit_with_error(err));
+                frame_stats.presented(current_frame, frame_start, cpu_time);
+                if title_timer.elapsed() >= TITLE_INTERVAL {
+                    window.set_title(&window_title(&frame_stats.summary(), shader_failed));
+                    title_timer = time::Instant::now();
+                }
+                match present_result.raw {
+                    vk::Result::SUCCESS if !framebuffer_resized => (),
+                    vk::Result::SUCCESS | vk::Result::SUBOPTIMAL_KHR | vk::Result::ERROR_OUT_OF_DATE_KHR => {
+                        framebuffer_resized = false;
+                        vulkan_app.recreate_swapchain(&window, &scene).unwrap_or_else(|err| exit_with_error(err));
+                        redraw = true; //The new images are blank
+                    },
+                    err => exit_with_error(VulkanAppError::vulkan("present swapchain image")(err)),
+                }
 
                 timer = time::Instant::now(); //Reset timer after frame is presented
 
-                current_frame = current_frame % MAX_FRAMES_IN_FLIGHT;
+                //The next frame waits for the oldest one in flight, using its semaphores and command buffer once it is done
+                vulkan_app.frames.advance();
 
                 //window.request_redraw() //Call if state changed and a redraw is necessary
             },
-            Event::RedrawRequested(_) => { //Conditionally redraw (OS might request this too)
-
+            Event::RedrawRequested(_) => { //The OS asks for this when the window was uncovered, for example
+                redraw = true;
             },
             Event::LoopDestroyed => {
                 println!("Exiting event loop, should drop application");
                 unsafe {
-                    vulkan_app.device.device_wait_idle().unwrap(); //App referred to in closure, it is dropped once the scope closes
+                    vulkan_app.context.device.device_wait_idle().unwrap(); //App referred to in closure, it is dropped once the scope closes
                 }
             },
             _ => ()
         }
     });
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view() -> View {
+        return View {
+            center: [DoubleDouble::from(-0.75), DoubleDouble::from(0.1)],
+            zoom: 3.0,
+            max_iterations: 100,
+            coloring: Coloring::Smooth,
+            formula: Formula::Mandelbrot,
+        };
+    }
+
+    //The point of the complex plane under pixel (x, y)
+    fn point(view: &View, x: f64, y: f64, extent: vk::Extent2D) -> [f64; 2] {
+        let offset = view.pixel_offset(x, y, extent);
+        return [(view.center[0] + offset[0]).hi, (view.center[1] + offset[1]).hi];
+    }
+
+    fn assert_close(a: [f64; 2], b: [f64; 2]) {
+        assert!((a[0] - b[0]).abs() < 1e-12 && (a[1] - b[1]).abs() < 1e-12, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn pixel_offsets_span_the_view() {
+        let extent = vk::Extent2D{width: 800, height: 400};
+        let view = view();
+        let scale = view.scale(extent);
+        assert_eq!(view.pixel_offset(400.0, 200.0, extent), [0.0, 0.0]);
+        assert_eq!(view.pixel_offset(0.0, 0.0, extent), [-scale[0], -scale[1]]);
+        assert_eq!(view.pixel_offset(800.0, 400.0, extent), scale);
+        assert_eq!(scale[0], 2.0 * scale[1]);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let extents = [vk::Extent2D{width: 800, height: 600}, vk::Extent2D{width: 300, height: 900}, vk::Extent2D{width: 512, height: 512}];
+        for extent in extents {
+            for factor in [2.0, 1.1, 0.5, 0.9] {
+                for (x, y) in [(0.0, 0.0), (123.0, 45.5), (extent.width as f64, extent.height as f64 / 3.0)] {
+                    let mut view = view();
+                    let before = point(&view, x, y, extent);
+                    view.zoom_at(factor, x, y, extent);
+                    assert_eq!(view.zoom, 3.0 * factor);
+                    assert_close(point(&view, x, y, extent), before);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zooming_past_the_limit_keeps_the_point_under_the_cursor() {
+        let extent = vk::Extent2D{width: 640, height: 480};
+        let mut view = view();
+        view.zoom = MAX_ZOOM / 2.0;
+        let before = point(&view, 10.0, 400.0, extent);
+        view.zoom_at(8.0, 10.0, 400.0, extent);
+        assert_eq!(view.zoom, MAX_ZOOM);
+        let after = point(&view, 10.0, 400.0, extent);
+        assert!((after[0] - before[0]).abs() < 1e-2 / MAX_ZOOM && (after[1] - before[1]).abs() < 1e-2 / MAX_ZOOM);
+    }
+
+    #[test]
+    fn panning_drags_the_point_with_the_cursor() {
+        for extent in [vk::Extent2D{width: 800, height: 600}, vk::Extent2D{width: 301, height: 999}] {
+            for (dx, dy) in [(10.0, 0.0), (0.0, -25.0), (-3.5, 7.25), (400.0, 300.0)] {
+                let mut view = view();
+                let grabbed = point(&view, 100.0, 200.0, extent);
+                view.pan_pixels(dx, dy, extent);
+                assert_eq!(view.zoom, 3.0);
+                assert_close(point(&view, 100.0 + dx, 200.0 + dy, extent), grabbed);
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// Headless rendering: the same Mandelbrot pipelines, rendered into an image we own instead of a swapchain,
+// then copied to host memory and written to disk as PNG. Needs no window or display, so it runs on
+// servers and software drivers such as lavapipe.
+
+use erupt::{vk, DeviceLoader};
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, ContextOptions, find_memory_type, GRAPHICS_Q_IDX};
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use super::{Scene, Pane, Pipelines, prepare_panes, compute_passes, create_renderpass, create_pipeline_layout, create_framebuffers, record_render_pass};
+use super::compute::{self, Renderer};
+use super::deep_zoom::Precision;
+use super::features::supports_double;
+use super::formula::FormulaParameters;
+use super::palette::Palette;
+use super::shader_data::ShaderData;
+use super::supersampling::{Supersampling, MultisampleImage};
+
+//Matches the format picked for the swapchain, so the PNG looks like the window
+pub const IMAGE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+pub const BYTES_PER_PIXEL: u64 = 4;
+
+#[allow(clippy::too_many_arguments)]
+pub fn render_to_png(
+    output_path: &Path,
+    width: u32,
+    height: u32,
+    scene: &Scene,
+    forced_precision: Option<Precision>,
+    renderer: Renderer,
+    supersampling: Supersampling,
+    palette: &Palette,
+    formula_parameters: &FormulaParameters,
+    context_options: &ContextOptions
+) -> Result<(), VulkanAppError> {
+    let context = VulkanContext::with_options(None, "Mandelbrot by Kristian Knudsen", context_options)?;
+    let logical_device = &context.device;
+    let extent = vk::Extent2D{width, height};
+    let renderer = renderer.available(&context);
+    let supersampling = supersampling.available(&context, renderer);
+
+    //// Pipelines, same as on screen but ending in a layout we can copy from
+    let mut shader_data = ShaderData::new(&context, scene.view.max_iterations, 1)?;
+    //Whatever is created from here on is destroyed below whether or not rendering succeeds, null handles are skipped
+    let mut renderpass = vk::RenderPass::null();
+    let mut pipeline_layout = vk::PipelineLayout::null();
+    let mut graphics_pipelines = None;
+    let mut target = None;
+    let mut command_pool = vk::CommandPool::null();
+    let pixels = (|| -> Result<Vec<u8>, VulkanAppError> {
+        shader_data.set_palette(palette);
+        shader_data.set_formula_parameters(formula_parameters);
+        if renderer == Renderer::Compute {
+            shader_data.resize_samples(&context, width as usize * height as usize, false)?;
+        }
+        renderpass = create_renderpass(logical_device, IMAGE_FORMAT, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, supersampling.samples())?;
+        pipeline_layout = create_pipeline_layout(logical_device, shader_data.descriptor_set_layout)?;
+        let graphics_pipelines = graphics_pipelines.insert(Pipelines::new(renderpass, pipeline_layout, supersampling.samples()));
+        let target = target.insert(Target::new(&context, renderpass, extent, supersampling)?);
+
+        //// Precision, and the reference orbit and histogram if they are needed
+        let precision = Precision::choose(forced_precision, scene.view.zoom, supports_double(&context), scene.view.formula.is_quadratic());
+        let panes = prepare_panes(logical_device, &mut shader_data, graphics_pipelines, scene, extent, precision, renderer, supersampling)?;
+        let descriptor_set = shader_data.prepare_frame(0);
+
+        let command_pool_info = vk::CommandPoolCreateInfoBuilder::new()
+            .queue_family_index(context.queue_family_indices[GRAPHICS_Q_IDX]);
+        command_pool = unsafe {logical_device.create_command_pool(&command_pool_info, None)}.map_err(VulkanAppError::vulkan("create command pool"))?;
+
+        //// Iterations of the compute renderer, finished before the render pass colors them
+        let passes = compute_passes(&panes);
+        if !passes.is_empty() {
+            compute::iterate(logical_device, context.graphics_queue, command_pool, pipeline_layout, &shader_data, descriptor_set, &[], &passes)?;
+        }
+
+        let pixels = target.render(&context, command_pool, pipeline_layout, descriptor_set, &panes, extent)?;
+        context.check_validation()?;
+        return Ok(pixels);
+    })();
+
+    unsafe {
+        //The compute work is not waited for if rendering failed. Should this fail too the device is lost, and there is nothing to wait for
+        let _ = logical_device.device_wait_idle();
+        logical_device.destroy_command_pool(command_pool, None);
+        if let Some(target) = &target {
+            target.destroy(logical_device);
+        }
+        if let Some(graphics_pipelines) = &graphics_pipelines {
+            graphics_pipelines.destroy(logical_device);
+        }
+        logical_device.destroy_pipeline_layout(pipeline_layout, None);
+        logical_device.destroy_render_pass(renderpass, None);
+    }
+    shader_data.destroy(logical_device);
+
+    let pixels = pixels?;
+    write_png(output_path, width, height, &pixels)?;
+    println!("Wrote {}x{} image to {}", width, height, output_path.display());
+    return Ok(());
+}
+
+//An image to render into instead of a swapchain image, and a host visible buffer to read it back through
+pub struct Target {
+    extent: vk::Extent2D,
+    image: vk::Image,
+    image_memory: vk::DeviceMemory,
+    image_view: vk::ImageView,
+    multisample_image: Option<MultisampleImage>, //MSAA only, resolved into image
+    framebuffer: vk::Framebuffer,
+    renderpass: vk::RenderPass,
+    buffer: vk::Buffer,
+    buffer_memory: vk::DeviceMemory,
+}
+impl Target {
+    //renderpass must end in TRANSFER_SRC_OPTIMAL and have the samples of supersampling, see create_renderpass
+    pub fn new(context: &VulkanContext, renderpass: vk::RenderPass, extent: vk::Extent2D, supersampling: Supersampling) -> Result<Target, VulkanAppError> {
+        //Filled in one object at a time, so that on failure destroy frees those created so far and skips the null handles of the rest
+        let mut target = Target{
+            extent,
+            image: vk::Image::null(),
+            image_memory: vk::DeviceMemory::null(),
+            image_view: vk::ImageView::null(),
+            multisample_image: None,
+            framebuffer: vk::Framebuffer::null(),
+            renderpass,
+            buffer: vk::Buffer::null(),
+            buffer_memory: vk::DeviceMemory::null(),
+        };
+        if let Err(err) = target.create(context, supersampling) {
+            target.destroy(&context.device);
+            return Err(err);
+        }
+        return Ok(target);
+    }
+
+    fn create(&mut self, context: &VulkanContext, supersampling: Supersampling) -> Result<(), VulkanAppError> {
+        let logical_device = &context.device;
+        let extent = self.extent;
+
+        //// Render target, device local
+        let image_info = vk::ImageCreateInfoBuilder::new()
+            .image_type(vk::ImageType::_2D)
+            .format(IMAGE_FORMAT)
+            .extent(vk::Extent3D{width: extent.width, height: extent.height, depth: 1})
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlagBits::_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        self.image = unsafe {logical_device.create_image(&image_info, None)}.map_err(VulkanAppError::vulkan("create offscreen image"))?;
+        self.image_memory = allocate(context, unsafe {logical_device.get_image_memory_requirements(self.image)}, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        unsafe {logical_device.bind_image_memory(self.image, self.image_memory, 0)}.map_err(VulkanAppError::vulkan("bind image memory"))?;
+
+        let image_view_info = vk::ImageViewCreateInfoBuilder::new()
+            .image(self.image)
+            .view_type(vk::ImageViewType::_2D)
+            .format(IMAGE_FORMAT)
+            .subresource_range(vk::ImageSubresourceRange{
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        self.image_view = unsafe {logical_device.create_image_view(&image_view_info, None)}.map_err(VulkanAppError::vulkan("create image view"))?;
+        self.multisample_image = MultisampleImage::new(context, IMAGE_FORMAT, extent, supersampling)?;
+        self.framebuffer = create_framebuffers(logical_device, &[self.image_view], self.multisample_image.as_ref(), self.renderpass, extent)?[0];
+
+        //// Readback buffer, host visible
+        let buffer_size = extent.width as u64 * extent.height as u64 * BYTES_PER_PIXEL;
+        let buffer_info = vk::BufferCreateInfoBuilder::new()
+            .size(buffer_size)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        self.buffer = unsafe {logical_device.create_buffer(&buffer_info, None)}.map_err(VulkanAppError::vulkan("create readback buffer"))?;
+        self.buffer_memory = allocate(context, unsafe {logical_device.get_buffer_memory_requirements(self.buffer)}, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)?;
+        unsafe {logical_device.bind_buffer_memory(self.buffer, self.buffer_memory, 0)}.map_err(VulkanAppError::vulkan("bind buffer memory"))?;
+        return Ok(());
+    }
+
+    //Draws panes into the top left extent of the image, at most the size of the target, waits for it
+    //and returns those pixels as tightly packed RGBA rows
+    pub fn render(
+        &self,
+        context: &VulkanContext,
+        command_pool: vk::CommandPool,
+        pipeline_layout: vk::PipelineLayout,
+        descriptor_set: vk::DescriptorSet,
+        panes: &[Pane],
+        extent: vk::Extent2D
+    ) -> Result<Vec<u8>, VulkanAppError> {
+        let logical_device = &context.device;
+        let extent = vk::Extent2D{width: extent.width.min(self.extent.width), height: extent.height.min(self.extent.height)};
+
+        //// Recording
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer = unsafe {logical_device.allocate_command_buffers(&command_buffer_allocate_info)}.map_err(VulkanAppError::vulkan("allocate command buffers"))?[0];
+
+        let command_buffer_begin_info = vk::CommandBufferBeginInfoBuilder::new()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {logical_device.begin_command_buffer(command_buffer, &command_buffer_begin_info)}.map_err(VulkanAppError::vulkan("begin command buffer"))?;
+        record_render_pass(logical_device, command_buffer, self.framebuffer, extent, self.renderpass, pipeline_layout, descriptor_set, panes);
+
+        //Rows are tightly packed in the buffer (buffer_row_length 0), which is what the image encoders expect
+        let region = vk::BufferImageCopyBuilder::new()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(vk::ImageSubresourceLayers{
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D{x: 0, y: 0, z: 0})
+            .image_extent(vk::Extent3D{width: extent.width, height: extent.height, depth: 1});
+        //Make the copied data visible to the host once the fence signals
+        let buffer_barrier = vk::BufferMemoryBarrierBuilder::new()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+        unsafe {
+            logical_device.cmd_copy_image_to_buffer(command_buffer, self.image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, self.buffer, &[region]);
+            logical_device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::HOST, vk::DependencyFlags::empty(), &[], &[buffer_barrier], &[]);
+        }
+        unsafe {logical_device.end_command_buffer(command_buffer)}.map_err(VulkanAppError::vulkan("record command buffer"))?;
+
+        //// Submit and wait. The command buffer is freed with command_pool should anything fail before it is freed here
+        let fence = unsafe {logical_device.create_fence(&vk::FenceCreateInfoBuilder::new(), None)}.map_err(VulkanAppError::vulkan("create fence"))?;
+        let cmd_buffers = [command_buffer];
+        let submits = [vk::SubmitInfoBuilder::new().command_buffers(&cmd_buffers)];
+        let pixels = unsafe {logical_device.queue_submit(context.graphics_queue, &submits, fence)}.map_err(VulkanAppError::vulkan("submit to the queue"))
+            .and_then(|()| unsafe {logical_device.wait_for_fences(&[fence], true, u64::MAX)}.map_err(VulkanAppError::vulkan("wait for the render")))
+            .and_then(|()| self.read_back(context, extent));
+        unsafe {
+            logical_device.destroy_fence(fence, None);
+            logical_device.free_command_buffers(command_pool, &cmd_buffers);
+        }
+        return pixels;
+    }
+
+    //The top left extent of the image, copied into the buffer by a render that is done
+    fn read_back(&self, context: &VulkanContext, extent: vk::Extent2D) -> Result<Vec<u8>, VulkanAppError> {
+        let logical_device = &context.device;
+        let size = extent.width as u64 * extent.height as u64 * BYTES_PER_PIXEL;
+        let mut pixels = vec![0u8; size as usize];
+        unsafe {
+            let mapped = logical_device.map_memory(self.buffer_memory, 0, size, vk::MemoryMapFlags::empty()).map_err(VulkanAppError::vulkan("map memory"))?;
+            std::ptr::copy_nonoverlapping(mapped as *const u8, pixels.as_mut_ptr(), pixels.len());
+            logical_device.unmap_memory(self.buffer_memory);
+        }
+        return Ok(pixels);
+    }
+
+    pub fn destroy(&self, logical_device: &DeviceLoader) {
+        unsafe {
+            logical_device.destroy_framebuffer(self.framebuffer, None);
+            if let Some(multisample_image) = &self.multisample_image {
+                multisample_image.destroy(logical_device);
+            }
+            logical_device.destroy_buffer(self.buffer, None);
+            logical_device.free_memory(self.buffer_memory, None);
+            logical_device.destroy_image_view(self.image_view, None);
+            logical_device.destroy_image(self.image, None);
+            logical_device.free_memory(self.image_memory, None);
+        }
+    }
+}
+
+//Memory of properties fitting memory_requirements, for the caller to bind its image or buffer to
+pub fn allocate(context: &VulkanContext, memory_requirements: vk::MemoryRequirements, properties: vk::MemoryPropertyFlags) -> Result<vk::DeviceMemory, VulkanAppError> {
+    let mem_type = find_memory_type(&context.instance, context.physical_device, memory_requirements.memory_type_bits, properties)
+        .ok_or(VulkanAppError::NoMemoryType(properties))?;
+    let alloc_info = vk::MemoryAllocateInfoBuilder::new()
+        .allocation_size(memory_requirements.size)
+        .memory_type_index(mem_type);
+    unsafe {context.device.allocate_memory(&alloc_info, None)}.map_err(VulkanAppError::vulkan("allocate memory"))
+}
+
+fn write_png(output_path: &Path, width: u32, height: u32, rgba_pixels: &[u8]) -> Result<(), VulkanAppError> {
+    let mut writer = png_encoder(output_path, width, height)?.write_header().map_err(VulkanAppError::io(output_path))?;
+    writer.write_image_data(rgba_pixels).map_err(VulkanAppError::io(output_path))?;
+    return writer.finish().map_err(VulkanAppError::io(output_path));
+}
+
+//Encoder for 8 bit sRGB RGBA, what the targets read back
+pub fn png_encoder(output_path: &Path, width: u32, height: u32) -> Result<png::Encoder<'static, BufWriter<File>>, VulkanAppError> {
+    let file = File::create(output_path).map_err(VulkanAppError::io(output_path))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    return Ok(encoder);
+}
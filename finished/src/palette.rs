@@ -0,0 +1,374 @@
+// Color palettes, loaded from the gradient files artists already have:
+//   .map   Fractint maps, one "r g b" line (0-255) per color, anything after the third number is a comment
+//   .ggr   GIMP gradients, segments with blending and coloring modes
+//   .json  a list of stops, [{"position": 0.0, "color": "#000764"}, ...]
+//   .csv   one stop per line, "position,#rrggbb" or "position,r,g,b" (0-255), an optional header line
+// Every palette is resampled to PALETTE_SIZE colors in linear RGB, the layout the shaders read from their uniform buffer.
+
+use serde::Deserialize;
+
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//Must match PALETTE_SIZE in the mandelbrot fragment shaders
+pub const PALETTE_SIZE: usize = 256;
+
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<[f32; 4]>, //PALETTE_SIZE linear RGB colors, padded to vec4 as std140 wants
+}
+impl Palette {
+    //The black, blue, white gradient the viewer always had. Already linear, so no sRGB decoding
+    pub fn built_in() -> Palette {
+        let stops = [
+            (0.0, [0.0, 0.0, 0.0]),
+            (0.5, [0.0, 0.0, 1.0]),
+            (1.0, [0.8, 0.8, 1.0]),
+        ];
+        return Palette::from_stops("built-in".to_string(), &stops);
+    }
+
+    pub fn load(path: &Path) -> Result<Palette, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let name = path.file_stem().map_or_else(|| path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        return match extension.as_deref() {
+            Some("map") => parse_map(name, &contents),
+            Some("ggr") => parse_ggr(name, &contents),
+            Some("json") => parse_json(name, &contents),
+            Some("csv") => parse_csv(name, &contents),
+            _ => Err("unknown palette format, expected .map, .ggr, .json or .csv".to_string()),
+        }
+    }
+
+    //Linear interpolation between stops of (position, linear RGB), which must be sorted by position
+    fn from_stops(name: String, stops: &[(f32, [f32; 3])]) -> Palette {
+        let colors = (0..PALETTE_SIZE).map(|i| {
+            let position = i as f32 / (PALETTE_SIZE - 1) as f32;
+            let after = stops.iter().position(|stop| stop.0 >= position).unwrap_or(stops.len() - 1);
+            let before = after.saturating_sub(1);
+            let (p0, c0) = stops[before];
+            let (p1, c1) = stops[after];
+            let t = if p1 > p0 {((position - p0) / (p1 - p0)).clamp(0.0, 1.0)} else {1.0};
+            [lerp(c0[0], c1[0], t), lerp(c0[1], c1[1], t), lerp(c0[2], c1[2], t), 1.0]
+        }).collect();
+        return Palette{name, colors};
+    }
+}
+
+//Loads every path, expanding directories to the palette files in them. Files that fail to load are reported and skipped
+pub fn load_palettes(paths: &[PathBuf]) -> Vec<Palette> {
+    let mut files = Vec::new();
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                let mut dir_files: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_file()).collect();
+                dir_files.sort();
+                files.extend(dir_files);
+            },
+            Err(_) => files.push(path.clone()),
+        }
+    }
+
+    let mut palettes = Vec::new();
+    for file in files {
+        match Palette::load(&file) {
+            Ok(palette) => palettes.push(palette),
+            Err(err) => eprintln!("Could not load palette {}: {}", file.display(), err),
+        }
+    }
+    return palettes;
+}
+
+
+//// Formats
+fn parse_map(name: String, contents: &str) -> Result<Palette, String> {
+    let mut colors = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        let numbers: Vec<&str> = line.split_whitespace().take(3).collect();
+        if numbers.is_empty() {continue}
+        let rgb = parse_rgb(&numbers).ok_or_else(|| format!("line {}: expected three numbers 0-255", line_number + 1))?;
+        colors.push(rgb);
+    }
+    if colors.is_empty() {return Err("no colors".to_string())}
+
+    //Spread evenly, usually 256 colors so one per entry
+    let last = (colors.len() - 1).max(1) as f32;
+    let stops: Vec<(f32, [f32; 3])> = colors.into_iter().enumerate().map(|(i, color)| (i as f32 / last, color)).collect();
+    return Ok(Palette::from_stops(name, &stops));
+}
+
+fn parse_ggr(name: String, contents: &str) -> Result<Palette, String> {
+    let mut lines = contents.lines();
+    if lines.next().map(str::trim) != Some("GIMP Gradient") {
+        return Err("missing \"GIMP Gradient\" header".to_string())
+    }
+    let mut name = name;
+    let mut line = lines.next().ok_or("unexpected end of file")?;
+    if let Some(gradient_name) = line.strip_prefix("Name:") {
+        name = gradient_name.trim().to_string();
+        line = lines.next().ok_or("unexpected end of file")?;
+    }
+    let segment_count: usize = line.trim().parse().map_err(|_| "expected number of segments")?;
+
+    let mut segments = Vec::with_capacity(segment_count);
+    for i in 0..segment_count {
+        let line = lines.next().ok_or_else(|| format!("expected {} segments, found {}", segment_count, i))?;
+        let values: Vec<f32> = line.split_whitespace().map(str::parse).collect::<Result<_, _>>().map_err(|_| format!("segment {}: not a number", i + 1))?;
+        if values.len() < 13 {
+            return Err(format!("segment {}: expected at least 13 values", i + 1))
+        }
+        segments.push(GgrSegment {
+            left: values[0],
+            middle: values[1],
+            right: values[2],
+            left_color: [values[3], values[4], values[5]],
+            right_color: [values[7], values[8], values[9]],
+            blending: values[11] as u32,
+            coloring: values[12] as u32,
+        });
+    }
+    if segments.is_empty() {return Err("no segments".to_string())}
+
+    //Sample the gradient at every palette entry, the segments already define the curve between them
+    let colors = (0..PALETTE_SIZE).map(|i| {
+        let position = i as f32 / (PALETTE_SIZE - 1) as f32;
+        let segment = segments.iter().find(|segment| position <= segment.right).unwrap_or(&segments[segments.len() - 1]);
+        let color = segment.color_at(position);
+        [srgb_to_linear(color[0]), srgb_to_linear(color[1]), srgb_to_linear(color[2]), 1.0]
+    }).collect();
+    return Ok(Palette{name, colors});
+}
+
+#[derive(Deserialize)]
+struct JsonStop {
+    position: f32,
+    color: String,
+}
+fn parse_json(name: String, contents: &str) -> Result<Palette, String> {
+    let json_stops: Vec<JsonStop> = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+    let mut stops = Vec::with_capacity(json_stops.len());
+    for stop in json_stops {
+        let color = parse_hex(&stop.color).ok_or_else(|| format!("invalid color \"{}\", expected #rrggbb", stop.color))?;
+        stops.push((stop.position, color));
+    }
+    return stops_to_palette(name, stops);
+}
+
+fn parse_csv(name: String, contents: &str) -> Result<Palette, String> {
+    let mut stops = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if line.trim().is_empty() || line.starts_with('#') {continue}
+        let position = match fields[0].parse::<f32>() {
+            Ok(position) => position,
+            Err(_) if line_number == 0 => continue, //Header
+            Err(_) => return Err(format!("line {}: invalid position", line_number + 1)),
+        };
+        let color = match fields.len() {
+            2 => parse_hex(fields[1]),
+            4 => parse_rgb(&fields[1..]),
+            _ => None,
+        }.ok_or_else(|| format!("line {}: expected position,#rrggbb or position,r,g,b", line_number + 1))?;
+        stops.push((position, color));
+    }
+    return stops_to_palette(name, stops);
+}
+
+fn stops_to_palette(name: String, mut stops: Vec<(f32, [f32; 3])>) -> Result<Palette, String> {
+    if stops.is_empty() {return Err("no color stops".to_string())}
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    return Ok(Palette::from_stops(name, &stops));
+}
+
+
+//// GIMP gradient segments, following gimpgradient.c
+struct GgrSegment {
+    left: f32,
+    middle: f32,
+    right: f32,
+    left_color: [f32; 3], //sRGB 0-1, the alpha is ignored as the set is drawn opaque
+    right_color: [f32; 3],
+    blending: u32, //0 linear, 1 curved, 2 sine, 3 sphere increasing, 4 sphere decreasing, 5 step
+    coloring: u32, //0 RGB, 1 HSV counter-clockwise, 2 HSV clockwise
+}
+impl GgrSegment {
+    fn color_at(&self, position: f32) -> [f32; 3] {
+        let length = self.right - self.left;
+        let (middle, position) = if length < f32::EPSILON {
+            (0.5, 0.5)
+        } else {
+            ((self.middle - self.left) / length, (position - self.left) / length)
+        };
+        let linear = || {
+            if position <= middle {
+                if middle < f32::EPSILON {0.0} else {0.5 * position / middle}
+            } else if middle > 1.0 - f32::EPSILON {
+                1.0
+            } else {
+                0.5 + 0.5 * (position - middle) / (1.0 - middle)
+            }
+        };
+        let factor = match self.blending {
+            1 => position.powf(0.5f32.ln() / middle.max(f32::EPSILON).ln()),
+            2 => ((-PI / 2.0 + PI * linear()).sin() + 1.0) / 2.0,
+            3 => (1.0 - (linear() - 1.0).powi(2)).sqrt(),
+            4 => 1.0 - (1.0 - linear().powi(2)).sqrt(),
+            5 => if position >= middle {1.0} else {0.0},
+            _ => linear(),
+        };
+
+        if self.coloring == 0 {
+            return [
+                lerp(self.left_color[0], self.right_color[0], factor),
+                lerp(self.left_color[1], self.right_color[1], factor),
+                lerp(self.left_color[2], self.right_color[2], factor),
+            ]
+        }
+        let left = rgb_to_hsv(self.left_color);
+        let right = rgb_to_hsv(self.right_color);
+        let hue = if self.coloring == 1 { //Counter-clockwise, increasing hue
+            let distance = if left[0] < right[0] {right[0] - left[0]} else {1.0 - (left[0] - right[0])};
+            (left[0] + distance * factor).rem_euclid(1.0)
+        } else {
+            let distance = if right[0] < left[0] {left[0] - right[0]} else {1.0 - (right[0] - left[0])};
+            (left[0] - distance * factor).rem_euclid(1.0)
+        };
+        return hsv_to_rgb([hue, lerp(left[1], right[1], factor), lerp(left[2], right[2], factor)]);
+    }
+}
+
+
+//// Color helpers
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    return a + (b - a) * t;
+}
+
+//Palette files hold sRGB colors, but the shader output is encoded to sRGB by the swapchain format
+fn srgb_to_linear(value: f32) -> f32 {
+    return if value <= 0.04045 {value / 12.92} else {((value + 0.055) / 1.055).powf(2.4)};
+}
+
+//Three numbers 0-255 to linear RGB
+fn parse_rgb(fields: &[&str]) -> Option<[f32; 3]> {
+    if fields.len() != 3 {return None}
+    let mut rgb = [0.0; 3];
+    for (channel, field) in rgb.iter_mut().zip(fields) {
+        *channel = srgb_to_linear(field.parse::<u8>().ok()? as f32 / 255.0);
+    }
+    return Some(rgb);
+}
+
+//"#rrggbb" to linear RGB
+fn parse_hex(color: &str) -> Option<[f32; 3]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.is_ascii() {return None}
+    let mut rgb = [0.0; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        *channel = srgb_to_linear(u8::from_str_radix(&hex[2*i..2*i + 2], 16).ok()? as f32 / 255.0);
+    }
+    return Some(rgb);
+}
+
+fn rgb_to_hsv(rgb: [f32; 3]) -> [f32; 3] {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    let min = rgb[0].min(rgb[1]).min(rgb[2]);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == rgb[0] {
+        ((rgb[1] - rgb[2]) / delta / 6.0).rem_euclid(1.0)
+    } else if max == rgb[1] {
+        ((rgb[2] - rgb[0]) / delta + 2.0) / 6.0
+    } else {
+        ((rgb[0] - rgb[1]) / delta + 4.0) / 6.0
+    };
+    let saturation = if max == 0.0 {0.0} else {delta / max};
+    return [hue, saturation, max];
+}
+
+fn hsv_to_rgb(hsv: [f32; 3]) -> [f32; 3] {
+    let [hue, saturation, value] = hsv;
+    let sector = hue * 6.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    return [r + m, g + m, b + m];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEGMENT: &str = "0.0 0.5 1.0 0.0 0.0 0.0 1.0 1.0 1.0 1.0 1.0 0 0";
+
+    fn error(result: Result<Palette, String>) -> String {
+        return result.err().expect("Malformed palette was accepted!");
+    }
+
+    #[test]
+    fn map_reads_three_numbers_per_line() {
+        let palette = parse_map("test".to_string(), "0 0 0 black\n\n255 255 255 white\n").unwrap();
+        assert_eq!(palette.colors.len(), PALETTE_SIZE);
+        assert_eq!(palette.colors[0], [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(palette.colors[PALETTE_SIZE - 1], [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn malformed_map_lines_are_refused() {
+        assert_eq!(error(parse_map("test".to_string(), "0 0 0\n255 0\n")), "line 2: expected three numbers 0-255");
+        assert_eq!(error(parse_map("test".to_string(), "0 0 256\n")), "line 1: expected three numbers 0-255");
+        assert_eq!(error(parse_map("test".to_string(), "red green blue\n")), "line 1: expected three numbers 0-255");
+        assert_eq!(error(parse_map("test".to_string(), "0 -1 0\n")), "line 1: expected three numbers 0-255");
+        assert_eq!(error(parse_map("test".to_string(), "\n  \n")), "no colors");
+    }
+
+    #[test]
+    fn ggr_reads_name_and_segments() {
+        let palette = parse_ggr("file".to_string(), &format!("GIMP Gradient\nName: Gray\n1\n{}\n", SEGMENT)).unwrap();
+        assert_eq!(palette.name, "Gray");
+        assert_eq!(palette.colors.len(), PALETTE_SIZE);
+        assert_eq!(palette.colors[PALETTE_SIZE - 1], [1.0, 1.0, 1.0, 1.0]);
+        let unnamed = parse_ggr("file".to_string(), &format!("GIMP Gradient\n1\n{}\n", SEGMENT)).unwrap();
+        assert_eq!(unnamed.name, "file");
+    }
+
+    #[test]
+    fn malformed_ggr_lines_are_refused() {
+        assert_eq!(error(parse_ggr("test".to_string(), &format!("1\n{}\n", SEGMENT))), "missing \"GIMP Gradient\" header");
+        assert_eq!(error(parse_ggr("test".to_string(), "GIMP Gradient\n")), "unexpected end of file");
+        assert_eq!(error(parse_ggr("test".to_string(), "GIMP Gradient\nName: Empty\n")), "unexpected end of file");
+        assert_eq!(error(parse_ggr("test".to_string(), &format!("GIMP Gradient\none\n{}\n", SEGMENT))), "expected number of segments");
+        assert_eq!(error(parse_ggr("test".to_string(), &format!("GIMP Gradient\n2\n{}\n", SEGMENT))), "expected 2 segments, found 1");
+        assert_eq!(error(parse_ggr("test".to_string(), "GIMP Gradient\n1\n0.0 0.5 1.0 0.0 0.0 0.0 1.0\n")), "segment 1: expected at least 13 values");
+        assert_eq!(error(parse_ggr("test".to_string(), "GIMP Gradient\n1\n0.0 0.5 1.0 black 0.0 0.0 1.0 1.0 1.0 1.0 1.0 0 0\n")), "segment 1: not a number");
+        assert_eq!(error(parse_ggr("test".to_string(), "GIMP Gradient\n0\n")), "no segments");
+    }
+
+    #[test]
+    fn csv_reads_hex_and_rgb_stops_after_a_header() {
+        let palette = parse_csv("test".to_string(), "position,color\n# comment\n1.0,255,255,255\n0.0,#000000\n").unwrap();
+        assert_eq!(palette.colors[0], [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(palette.colors[PALETTE_SIZE - 1], [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn malformed_csv_lines_are_refused() {
+        assert_eq!(error(parse_csv("test".to_string(), "0.0,#000000\nhalf,#ffffff\n")), "line 2: invalid position");
+        assert_eq!(error(parse_csv("test".to_string(), "0.0,#00000\n")), "line 1: expected position,#rrggbb or position,r,g,b");
+        assert_eq!(error(parse_csv("test".to_string(), "0.0,#gg0000\n")), "line 1: expected position,#rrggbb or position,r,g,b");
+        assert_eq!(error(parse_csv("test".to_string(), "0.0,255,255\n")), "line 1: expected position,#rrggbb or position,r,g,b");
+        assert_eq!(error(parse_csv("test".to_string(), "0.0,255,255,300\n")), "line 1: expected position,#rrggbb or position,r,g,b");
+        assert_eq!(error(parse_csv("test".to_string(), "0.0\n")), "line 1: expected position,#rrggbb or position,r,g,b");
+        assert_eq!(error(parse_csv("test".to_string(), "position,color\n")), "no color stops");
+    }
+}
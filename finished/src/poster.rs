@@ -0,0 +1,442 @@
+// render-poster: images far larger than the window, or than the device can render at once.
+// The poster is split into tiles, each drawn into the same offscreen target with push constants cropping the view of the
+// whole poster to the tile, then read back. Finished rows are written out a band of tiles at a time,
+// so memory use grows with the width and tile size but not with the height.
+
+use erupt::vk;
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, ContextOptions, GRAPHICS_Q_IDX};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{Scene, Pane, Pipelines, prepare_panes, create_renderpass, create_pipeline_layout};
+use super::compute::Renderer;
+use super::deep_zoom::Precision;
+use super::features::supports_double;
+use super::formula::FormulaParameters;
+use super::julia::Fractal;
+use super::offscreen::{Target, IMAGE_FORMAT, BYTES_PER_PIXEL, png_encoder};
+use super::palette::Palette;
+use super::shader_data::ShaderData;
+use super::supersampling::Supersampling;
+
+//Rows per strip of TIFF files, readers load a strip at a time
+const TIFF_ROWS_PER_STRIP: u32 = 64;
+
+#[allow(clippy::too_many_arguments)]
+pub fn render_poster(
+    output_path: &Path,
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    scene: &Scene,
+    forced_precision: Option<Precision>,
+    supersampling: Supersampling,
+    palette: &Palette,
+    formula_parameters: &FormulaParameters,
+    context_options: &ContextOptions
+) -> Result<(), VulkanAppError> {
+    let context = VulkanContext::with_options(None, "Mandelbrot by Kristian Knudsen", context_options)?;
+    //Before rendering anything, in case the file can't be written
+    let mut writer = ImageWriter::new(output_path, width, height)?;
+    let logical_device = &context.device;
+    let extent = vk::Extent2D{width, height};
+    let supersampling = supersampling.available(&context, Renderer::Graphics);
+
+    //// Pipelines and the target the tiles are drawn into one after another
+    let mut shader_data = ShaderData::new(&context, scene.view.max_iterations, 1)?;
+    shader_data.set_palette(palette);
+    shader_data.set_formula_parameters(formula_parameters);
+    let renderpass = create_renderpass(logical_device, IMAGE_FORMAT, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, supersampling.samples())?;
+    let pipeline_layout = create_pipeline_layout(logical_device, shader_data.descriptor_set_layout)?;
+    let mut graphics_pipelines = Pipelines::new(renderpass, pipeline_layout, supersampling.samples());
+    let target = Target::new(&context, renderpass, vk::Extent2D{width: tile_size.min(width), height: tile_size.min(height)}, supersampling)?;
+
+    //// Panes of the whole poster. The reference orbit and histogram are for all of it, so the tiles match up
+    let precision = Precision::choose(forced_precision, scene.view.zoom, supports_double(&context), scene.view.formula.is_quadratic());
+    let panes = prepare_panes(logical_device, &mut shader_data, &mut graphics_pipelines, scene, extent, precision, Renderer::Graphics, supersampling)?;
+    let descriptor_set = shader_data.prepare_frame(0);
+    let precisions: Vec<Precision> = scene.layout.panes(extent).iter().map(|(fractal, _)| {
+        if *fractal == Fractal::Mandelbrot {precision} else {Precision::Single}
+    }).collect();
+
+    let command_pool_info = vk::CommandPoolCreateInfoBuilder::new()
+        .queue_family_index(context.queue_family_indices[GRAPHICS_Q_IDX]);
+    let command_pool = unsafe {logical_device.create_command_pool(&command_pool_info, None)}.map_err(VulkanAppError::vulkan("create command pool"))?;
+
+    //// Tiles, a band across the poster at a time
+    let row_size = width as usize * BYTES_PER_PIXEL as usize;
+    let tile_count = width.div_ceil(tile_size) * height.div_ceil(tile_size);
+    let mut tiles_done = 0;
+    for band_y in (0..height).step_by(tile_size as usize) {
+        let band_height = tile_size.min(height - band_y);
+        let mut band = vec![0u8; row_size * band_height as usize];
+        for tile_x in (0..width).step_by(tile_size as usize) {
+            let tile = vk::Rect2D{
+                offset: vk::Offset2D{x: tile_x as i32, y: band_y as i32},
+                extent: vk::Extent2D{width: tile_size.min(width - tile_x), height: band_height},
+            };
+            let tile_panes = crop_panes(&panes, &precisions, tile);
+            let pixels = target.render(&context, command_pool, pipeline_layout, descriptor_set, &tile_panes, tile.extent)?;
+            context.check_validation()?;
+
+            stitch(&mut band, width, tile_x, tile.extent.width, &pixels);
+            tiles_done += 1;
+        }
+        writer.write_rows(&band).map_err(VulkanAppError::io(output_path))?;
+        println!("Rendered {}/{} tiles", tiles_done, tile_count);
+    }
+    writer.finish().map_err(VulkanAppError::io(output_path))?;
+
+    unsafe {
+        logical_device.destroy_command_pool(command_pool, None);
+        target.destroy(logical_device);
+        graphics_pipelines.destroy(logical_device);
+        logical_device.destroy_pipeline_layout(pipeline_layout, None);
+        logical_device.destroy_render_pass(renderpass, None);
+    }
+    shader_data.destroy(logical_device);
+
+    println!("Wrote {}x{} image to {}", width, height, output_path.display());
+    return Ok(());
+}
+
+//The parts of panes under tile, positioned in the tile. precisions are those of the panes
+fn crop_panes(panes: &[Pane], precisions: &[Precision], tile: vk::Rect2D) -> Vec<Pane> {
+    return panes.iter().zip(precisions).filter_map(|(pane, precision)| {
+        let left = pane.area.offset.x.max(tile.offset.x);
+        let top = pane.area.offset.y.max(tile.offset.y);
+        let right = (pane.area.offset.x + pane.area.extent.width as i32).min(tile.offset.x + tile.extent.width as i32);
+        let bottom = (pane.area.offset.y + pane.area.extent.height as i32).min(tile.offset.y + tile.extent.height as i32);
+        if left >= right || top >= bottom {return None}
+
+        let extent = vk::Extent2D{width: (right - left) as u32, height: (bottom - top) as u32};
+        let in_pane = vk::Rect2D{offset: vk::Offset2D{x: left - pane.area.offset.x, y: top - pane.area.offset.y}, extent};
+        Some(Pane{
+            area: vk::Rect2D{offset: vk::Offset2D{x: left - tile.offset.x, y: top - tile.offset.y}, extent},
+            pipeline: pane.pipeline,
+            push_constants: pane.push_constants.crop(pane.area.extent, in_pane, *precision),
+            iterate: None,
+        })
+    }).collect();
+}
+
+//Copies the pixels of a tile tile_width wide into the rows of band, a band of the image width wide, at column tile_x
+fn stitch(band: &mut [u8], width: u32, tile_x: u32, tile_width: u32, pixels: &[u8]) {
+    let row_size = width as usize * BYTES_PER_PIXEL as usize;
+    let tile_row_size = tile_width as usize * BYTES_PER_PIXEL as usize;
+    let tile_start = tile_x as usize * BYTES_PER_PIXEL as usize;
+    for (row, tile_row) in pixels.chunks_exact(tile_row_size).enumerate() {
+        let start = row * row_size + tile_start;
+        band[start..start + tile_row_size].copy_from_slice(tile_row);
+    }
+}
+
+//Writes an image from the top down, some rows of RGBA pixels at a time
+enum ImageWriter {
+    Png(Box<png::StreamWriter<'static, BufWriter<File>>>),
+    Tiff(TiffWriter),
+}
+impl ImageWriter {
+    //TIFF for .tif and .tiff paths, PNG for anything else
+    fn new(output_path: &Path, width: u32, height: u32) -> Result<ImageWriter, VulkanAppError> {
+        let extension = output_path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase());
+        if let Some("tif" | "tiff") = extension.as_deref() {
+            return TiffWriter::new(output_path, width, height).map(ImageWriter::Tiff).map_err(VulkanAppError::io(output_path));
+        }
+        let writer = png_encoder(output_path, width, height)?.write_header().map_err(VulkanAppError::io(output_path))?;
+        return Ok(ImageWriter::Png(Box::new(writer.into_stream_writer().map_err(VulkanAppError::io(output_path))?)));
+    }
+
+    fn write_rows(&mut self, rgba_pixels: &[u8]) -> io::Result<()> {
+        match self {
+            ImageWriter::Png(writer) => writer.write_all(rgba_pixels),
+            ImageWriter::Tiff(writer) => writer.write_rows(rgba_pixels),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            ImageWriter::Png(writer) => Ok(writer.finish()?),
+            ImageWriter::Tiff(writer) => writer.finish(),
+        }
+    }
+}
+
+//Baseline TIFF, uncompressed 8 bit RGB. The pixels are written as they come, right after the header,
+//and the directory describing them after the last row. Offsets are 32 bit, so files are limited to 4 GiB
+struct TiffWriter {
+    file: BufWriter<File>,
+    width: u32,
+    height: u32,
+}
+impl TiffWriter {
+    fn new(output_path: &Path, width: u32, height: u32) -> io::Result<TiffWriter> {
+        let data_size = width as u64 * height as u64 * 3;
+        //Header, pixels padded to a word, and the directory with the values finish puts after it
+        let file_size = 8 + data_size + 1 + 150 + 14 + height.div_ceil(TIFF_ROWS_PER_STRIP) as u64 * 8;
+        if file_size > u32::MAX as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}x{} is too large for a TIFF file, write a PNG instead", width, height)));
+        }
+        let mut file = BufWriter::new(File::create(output_path)?);
+        //Little endian, the magic number and where the directory will be
+        let directory_offset = 8 + data_size as u32 + (data_size % 2) as u32;
+        let mut header = b"II".to_vec();
+        header.extend(42u16.to_le_bytes());
+        header.extend(directory_offset.to_le_bytes());
+        file.write_all(&header)?;
+        return Ok(TiffWriter{file, width, height});
+    }
+
+    fn write_rows(&mut self, rgba_pixels: &[u8]) -> io::Result<()> {
+        let rgb_pixels: Vec<u8> = rgba_pixels.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect();
+        return self.file.write_all(&rgb_pixels);
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        const SHORT: u16 = 3;
+        const LONG: u16 = 4;
+        const RATIONAL: u16 = 5;
+        const ENTRY_COUNT: u32 = 12;
+
+        let row_size = self.width * 3;
+        let data_size = row_size * self.height;
+        let directory_offset = 8 + data_size + data_size % 2;
+        let strip_count = self.height.div_ceil(TIFF_ROWS_PER_STRIP);
+        let strip_offsets: Vec<u32> = (0..strip_count).map(|strip| 8 + strip * TIFF_ROWS_PER_STRIP * row_size).collect();
+        let strip_sizes: Vec<u32> = (0..strip_count).map(|strip| (TIFF_ROWS_PER_STRIP).min(self.height - strip * TIFF_ROWS_PER_STRIP) * row_size).collect();
+
+        //Values that don't fit in the 4 bytes of an entry go after the directory
+        let mut values = Vec::new();
+        let values_offset = directory_offset + 2 + ENTRY_COUNT * 12 + 4;
+        let mut out_of_line = |bytes: Vec<u8>| {
+            let offset = values_offset + values.len() as u32;
+            values.extend(bytes);
+            return offset;
+        };
+        let bits_per_sample = out_of_line([8u16; 3].iter().flat_map(|bits| bits.to_le_bytes()).collect());
+        let resolution = out_of_line([72u32, 1].iter().flat_map(|part| part.to_le_bytes()).collect()); //72 dpi, nominally
+        let (strip_offsets, strip_sizes) = if strip_count == 1 {
+            (strip_offsets[0], strip_sizes[0])
+        } else {
+            (
+                out_of_line(strip_offsets.iter().flat_map(|offset| offset.to_le_bytes()).collect()),
+                out_of_line(strip_sizes.iter().flat_map(|size| size.to_le_bytes()).collect()),
+            )
+        };
+
+        //Tag, type, count and the value or where it is, in increasing tag order
+        let entries: [(u16, u16, u32, u32); ENTRY_COUNT as usize] = [
+            (256, LONG, 1, self.width), //ImageWidth
+            (257, LONG, 1, self.height), //ImageLength
+            (258, SHORT, 3, bits_per_sample), //BitsPerSample
+            (259, SHORT, 1, 1), //Compression: none
+            (262, SHORT, 1, 2), //PhotometricInterpretation: RGB
+            (273, LONG, strip_count, strip_offsets), //StripOffsets
+            (277, SHORT, 1, 3), //SamplesPerPixel
+            (278, LONG, 1, TIFF_ROWS_PER_STRIP), //RowsPerStrip
+            (279, LONG, strip_count, strip_sizes), //StripByteCounts
+            (282, RATIONAL, 1, resolution), //XResolution
+            (283, RATIONAL, 1, resolution), //YResolution
+            (296, SHORT, 1, 2), //ResolutionUnit: inch
+        ];
+        let mut directory = Vec::new();
+        if data_size % 2 == 1 {directory.push(0)} //The directory starts on a word boundary
+        directory.extend((ENTRY_COUNT as u16).to_le_bytes());
+        for (tag, value_type, count, value) in entries {
+            directory.extend(tag.to_le_bytes());
+            directory.extend(value_type.to_le_bytes());
+            directory.extend(count.to_le_bytes());
+            directory.extend(value.to_le_bytes()); //Shorts are in the first two bytes, as little endian puts them
+        }
+        directory.extend(0u32.to_le_bytes()); //No next directory
+        directory.extend(values);
+        self.file.write_all(&directory)?;
+        return self.file.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{View, PushConstants};
+    use super::super::coloring::Coloring;
+    use super::super::deep_zoom::DoubleDouble;
+    use super::super::formula::Formula;
+    use super::super::julia::Layout;
+
+    use std::path::PathBuf;
+
+    //A file of its own in the temporary directory, removed when dropped
+    struct TempFile(PathBuf);
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            return TempFile(std::env::temp_dir().join(format!("poster_test_{}_{}", std::process::id(), name)));
+        }
+    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    //Distinct RGBA for every pixel of a small image
+    fn pixel(x: u32, y: u32) -> [u8; 4] {
+        return [x as u8, y as u8, (x * 7 + y * 13) as u8, 255];
+    }
+
+    fn read_u16(bytes: &[u8], offset: u32) -> u16 {
+        return u16::from_le_bytes(bytes[offset as usize..offset as usize + 2].try_into().unwrap());
+    }
+    fn read_u32(bytes: &[u8], offset: u32) -> u32 {
+        return u32::from_le_bytes(bytes[offset as usize..offset as usize + 4].try_into().unwrap());
+    }
+
+    //Values of the TIFF directory entry with tag, read from after the directory when they don't fit in the entry
+    fn tiff_values(file: &[u8], tag: u16) -> Vec<u32> {
+        let directory = read_u32(file, 4);
+        let entry_count = read_u16(file, directory) as u32;
+        let entry = (0..entry_count).map(|index| directory + 2 + index * 12)
+            .find(|&entry| read_u16(file, entry) == tag)
+            .unwrap_or_else(|| panic!("No entry for tag {}", tag));
+        let value_type = read_u16(file, entry + 2);
+        let count = read_u32(file, entry + 4);
+        let size = match value_type {3 => 2, 4 => 4, other => panic!("Unexpected type {}", other)};
+        let start = if count * size <= 4 {entry + 8} else {read_u32(file, entry + 8)};
+        return (0..count).map(|index| match size {
+            2 => read_u16(file, start + index * 2) as u32,
+            _ => read_u32(file, start + index * 4),
+        }).collect();
+    }
+
+    #[test]
+    fn tiff_strips_hold_the_rows() {
+        for height in [10, 130, 131] {
+            let width = 3;
+            let path = TempFile::new(&format!("strips_{}.tif", height));
+            let mut writer = ImageWriter::new(&path.0, width, height).unwrap();
+            assert!(matches!(writer, ImageWriter::Tiff(_)));
+            let rgba: Vec<u8> = (0..height).flat_map(|y| (0..width).flat_map(move |x| pixel(x, y))).collect();
+            //Rows arrive in bands that don't line up with the strips
+            for band in rgba.chunks(width as usize * 4 * 50) {
+                writer.write_rows(band).unwrap();
+            }
+            writer.finish().unwrap();
+            let file = std::fs::read(&path.0).unwrap();
+
+            assert_eq!(&file[0..4], b"II\x2a\x00");
+            let data_size = width * height * 3;
+            let directory = read_u32(&file, 4);
+            assert_eq!(directory, 8 + data_size + data_size % 2);
+            assert_eq!(read_u16(&file, directory), 12);
+            assert_eq!(read_u32(&file, directory + 2 + 12 * 12), 0);
+            assert_eq!(tiff_values(&file, 256), vec![width]);
+            assert_eq!(tiff_values(&file, 257), vec![height]);
+            assert_eq!(tiff_values(&file, 258), vec![8, 8, 8]);
+            assert_eq!(tiff_values(&file, 278), vec![TIFF_ROWS_PER_STRIP]);
+
+            let strip_rows: Vec<u32> = (0..height).step_by(TIFF_ROWS_PER_STRIP as usize).map(|y| TIFF_ROWS_PER_STRIP.min(height - y)).collect();
+            let offsets = tiff_values(&file, 273);
+            let sizes = tiff_values(&file, 279);
+            assert_eq!(sizes, strip_rows.iter().map(|rows| rows * width * 3).collect::<Vec<u32>>());
+            assert_eq!(offsets.len(), strip_rows.len());
+            assert_eq!(offsets[0], 8);
+            for strip in 1..offsets.len() {
+                assert_eq!(offsets[strip], offsets[strip - 1] + sizes[strip - 1]);
+            }
+
+            for (strip, (offset, size)) in offsets.iter().zip(&sizes).enumerate() {
+                let first_row = strip as u32 * TIFF_ROWS_PER_STRIP;
+                let expected: Vec<u8> = (first_row..first_row + size / (width * 3))
+                    .flat_map(|y| (0..width).flat_map(move |x| {let [r, g, b, _] = pixel(x, y); [r, g, b]}))
+                    .collect();
+                assert_eq!(&file[*offset as usize..(offset + size) as usize], &expected[..], "strip {} of {} rows", strip, height);
+            }
+        }
+    }
+
+    #[test]
+    fn stitched_bands_round_trip_through_png() {
+        let (width, height, tile_size) = (150, 140, 64);
+        let path = TempFile::new("stitched.png");
+        let mut writer = ImageWriter::new(&path.0, width, height).unwrap();
+        assert!(matches!(writer, ImageWriter::Png(_)));
+        for band_y in (0..height).step_by(tile_size as usize) {
+            let band_height = tile_size.min(height - band_y);
+            let mut band = vec![0u8; (width * band_height * 4) as usize];
+            for tile_x in (0..width).step_by(tile_size as usize) {
+                let tile_width = tile_size.min(width - tile_x);
+                let pixels: Vec<u8> = (band_y..band_y + band_height)
+                    .flat_map(|y| (tile_x..tile_x + tile_width).flat_map(move |x| pixel(x, y)))
+                    .collect();
+                stitch(&mut band, width, tile_x, tile_width, &pixels);
+            }
+            writer.write_rows(&band).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = png::Decoder::new(File::open(&path.0).unwrap()).read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+        assert_eq!((info.width, info.height), (width, height));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        let expected: Vec<u8> = (0..height).flat_map(|y| (0..width).flat_map(move |x| pixel(x, y))).collect();
+        assert_eq!(&decoded[..info.buffer_size()], &expected[..]);
+    }
+
+    #[test]
+    fn cropped_panes_cover_every_pixel_once() {
+        let extent = vk::Extent2D{width: 301, height: 203};
+        let tile_size = 64;
+        let view = View {
+            center: [DoubleDouble::from(-0.5), DoubleDouble::ZERO],
+            zoom: 1.0,
+            max_iterations: 100,
+            coloring: Coloring::Smooth,
+            formula: Formula::Mandelbrot,
+        };
+        let panes: Vec<Pane> = Layout::SideBySide.panes(extent).into_iter().map(|(_, area)| Pane {
+            area,
+            pipeline: vk::Pipeline(1),
+            push_constants: PushConstants::new(&view, area.extent, Precision::Single, 0),
+            iterate: None,
+        }).collect();
+        let precisions = vec![Precision::Single; panes.len()];
+
+        let mut covered = vec![0; (extent.width * extent.height) as usize];
+        for tile_y in (0..extent.height).step_by(tile_size as usize) {
+            for tile_x in (0..extent.width).step_by(tile_size as usize) {
+                let tile = vk::Rect2D{
+                    offset: vk::Offset2D{x: tile_x as i32, y: tile_y as i32},
+                    extent: vk::Extent2D{width: tile_size.min(extent.width - tile_x), height: tile_size.min(extent.height - tile_y)},
+                };
+                for cropped in crop_panes(&panes, &precisions, tile) {
+                    let area = cropped.area;
+                    assert!(area.offset.x >= 0 && area.offset.y >= 0);
+                    assert!(area.offset.x as u32 + area.extent.width <= tile.extent.width);
+                    assert!(area.offset.y as u32 + area.extent.height <= tile.extent.height);
+                    let left = tile.offset.x + area.offset.x;
+                    let top = tile.offset.y + area.offset.y;
+                    //The crop lies in the pane it came from, and shows its part of the view
+                    let pane = panes.iter().find(|pane| pane.area.offset.x <= left && left < pane.area.offset.x + pane.area.extent.width as i32).unwrap();
+                    assert!(left + area.extent.width as i32 <= pane.area.offset.x + pane.area.extent.width as i32);
+                    let point = |push_constants: &PushConstants, extent: vk::Extent2D, x: i32, y: i32| [
+                        push_constants.center[0] as f64 + (2.0 * (x as f64 + 0.5) / extent.width as f64 - 1.0) * push_constants.scale[0] as f64,
+                        push_constants.center[1] as f64 + (2.0 * (y as f64 + 0.5) / extent.height as f64 - 1.0) * push_constants.scale[1] as f64,
+                    ];
+                    let cropped_point = point(&cropped.push_constants, area.extent, 0, 0);
+                    let pane_point = point(&pane.push_constants, pane.area.extent, left - pane.area.offset.x, top - pane.area.offset.y);
+                    assert!((cropped_point[0] - pane_point[0]).abs() < 1e-5 && (cropped_point[1] - pane_point[1]).abs() < 1e-5);
+
+                    for y in top..top + area.extent.height as i32 {
+                        for x in left..left + area.extent.width as i32 {
+                            covered[(y as u32 * extent.width + x as u32) as usize] += 1;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+}
@@ -0,0 +1,187 @@
+// GPU queries, for profiling the shaders rather than guessing: timestamps around named regions of a command buffer,
+// and pipeline statistics such as fragment shader invocations where the device supports them.
+// Every frame in flight has queries of its own, read back after its fence has signalled so the frame loop never waits on them.
+
+use erupt::{vk, DeviceLoader};
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, GRAPHICS_Q_IDX};
+
+use std::os::raw::c_void;
+use std::mem::size_of_val;
+use std::time::Duration;
+
+const MAX_REGIONS: usize = 8; //Named regions timed per frame, each takes two timestamps
+
+//What the statistics query counts, in the order the results come in (that of the flag bits)
+const PIPELINE_STATISTICS: [vk::QueryPipelineStatisticFlags; 3] = [
+    vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS,
+    vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES,
+    vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS,
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStatistics {
+    pub vertex_invocations: u64,
+    pub clipping_primitives: u64, //Primitives that made it past clipping
+    pub fragment_invocations: u64, //One per pixel, or per sample with MSAA. Jittered supersampling iterates more within each
+}
+
+//The results of one frame's queries
+#[derive(Clone, Debug, Default)]
+pub struct QueryResults {
+    pub regions: Vec<(&'static str, Duration)>,
+    pub statistics: Option<PipelineStatistics>,
+}
+impl QueryResults {
+    pub fn region(&self, name: &str) -> Option<Duration> {
+        return self.regions.iter().find(|(region, _)| *region == name).map(|(_, duration)| *duration);
+    }
+}
+
+//A timed region being recorded, handed from Queries::begin_region to Queries::end_region
+#[must_use]
+pub struct Region {
+    frame: usize,
+    index: usize, //Into Queries::regions
+}
+
+//What was written into the queries of a frame since they were last read
+#[derive(Clone, Default)]
+struct FrameQueries {
+    regions: Vec<usize>, //Indices into Queries::regions
+    statistics: bool,
+}
+
+pub struct Queries {
+    timestamps: Option<vk::QueryPool>, //None if the queue has no timestamps
+    statistics: Option<vk::QueryPool>, //None if the device has no pipeline statistics queries
+    nanoseconds_per_tick: f64,
+    valid_bits_mask: u64, //The bits of a timestamp that count, the rest are garbage
+    regions: Vec<&'static str>, //Names of the regions, in the order they were first timed
+    frames: Vec<FrameQueries>,
+}
+impl Queries {
+    //Pipeline statistics need the pipeline_statistics_query feature, see ContextOptions::optional_features
+    pub fn new(context: &VulkanContext, frames_in_flight: usize) -> Result<Queries, VulkanAppError> {
+        let logical_device = &context.device;
+        let queue_families = unsafe {context.instance.get_physical_device_queue_family_properties(context.physical_device, None)};
+        let valid_bits = queue_families[context.queue_family_indices[GRAPHICS_Q_IDX] as usize].timestamp_valid_bits;
+        let limits = unsafe {context.instance.get_physical_device_properties(context.physical_device)}.limits;
+
+        let timestamps = if valid_bits == 0 {None} else {
+            let query_pool_info = vk::QueryPoolCreateInfoBuilder::new()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count((frames_in_flight * 2 * MAX_REGIONS) as u32);
+            Some(unsafe {logical_device.create_query_pool(&query_pool_info, None)}.map_err(VulkanAppError::vulkan("create query pool"))?)
+        };
+        let statistics = if context.enabled_features.pipeline_statistics_query != vk::TRUE {None} else {
+            let query_pool_info = vk::QueryPoolCreateInfoBuilder::new()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .query_count(frames_in_flight as u32)
+                .pipeline_statistics(PIPELINE_STATISTICS.iter().fold(vk::QueryPipelineStatisticFlags::empty(), |flags, flag| flags | *flag));
+            Some(unsafe {logical_device.create_query_pool(&query_pool_info, None)}.map_err(VulkanAppError::vulkan("create query pool"))?)
+        };
+        return Ok(Queries{
+            timestamps,
+            statistics,
+            nanoseconds_per_tick: limits.timestamp_period as f64,
+            valid_bits_mask: if valid_bits >= 64 {u64::MAX} else {(1 << valid_bits) - 1},
+            regions: Vec::new(),
+            frames: vec![FrameQueries::default(); frames_in_flight],
+        });
+    }
+
+    //Resets the queries of frame for recording into command_buffer. Must come before any other query of frame and outside a render pass
+    pub fn begin_frame(&mut self, logical_device: &DeviceLoader, command_buffer: vk::CommandBuffer, frame: usize) {
+        unsafe {
+            if let Some(timestamps) = self.timestamps {
+                logical_device.cmd_reset_query_pool(command_buffer, timestamps, (frame * 2 * MAX_REGIONS) as u32, (2 * MAX_REGIONS) as u32);
+            }
+            if let Some(statistics) = self.statistics {
+                logical_device.cmd_reset_query_pool(command_buffer, statistics, frame as u32, 1);
+            }
+        }
+        self.frames[frame] = FrameQueries::default();
+    }
+
+    //Starts timing the commands recorded into command_buffer from here on, reported under name. Each region is timed once per frame
+    pub fn begin_region(&mut self, logical_device: &DeviceLoader, command_buffer: vk::CommandBuffer, frame: usize, name: &'static str) -> Region {
+        let region = match self.regions.iter().position(|region| *region == name) {
+            Some(region) => region,
+            None => {
+                assert!(self.regions.len() < MAX_REGIONS, "More than {} query regions!", MAX_REGIONS);
+                self.regions.push(name);
+                self.regions.len() - 1
+            },
+        };
+        if let Some(timestamps) = self.timestamps {
+            let query = (frame * 2 * MAX_REGIONS + 2 * region) as u32;
+            unsafe {logical_device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlagBits::TOP_OF_PIPE, timestamps, query)};
+        }
+        return Region{frame, index: region};
+    }
+
+    pub fn end_region(&mut self, logical_device: &DeviceLoader, command_buffer: vk::CommandBuffer, region: Region) {
+        let Some(timestamps) = self.timestamps else {return};
+        let query = (region.frame * 2 * MAX_REGIONS + 2 * region.index + 1) as u32;
+        unsafe {logical_device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlagBits::BOTTOM_OF_PIPE, timestamps, query)};
+        self.frames[region.frame].regions.push(region.index);
+    }
+
+    //Starts counting the pipeline statistics of the commands recorded into command_buffer from here on, if the device can.
+    //Either both ends are outside a render pass or both are in the same subpass
+    pub fn begin_statistics(&mut self, logical_device: &DeviceLoader, command_buffer: vk::CommandBuffer, frame: usize) {
+        let Some(statistics) = self.statistics else {return};
+        unsafe {logical_device.cmd_begin_query(command_buffer, statistics, frame as u32, vk::QueryControlFlags::empty())};
+    }
+
+    pub fn end_statistics(&mut self, logical_device: &DeviceLoader, command_buffer: vk::CommandBuffer, frame: usize) {
+        let Some(statistics) = self.statistics else {return};
+        unsafe {logical_device.cmd_end_query(command_buffer, statistics, frame as u32)};
+        self.frames[frame].statistics = true;
+    }
+
+    //Results of the queries of frame since it last began, None if they were read already.
+    //Doesn't wait, so call it once the frame's fence has signalled; queries the GPU isn't done with are left out
+    pub fn read(&mut self, logical_device: &DeviceLoader, frame: usize) -> Option<QueryResults> {
+        let written = std::mem::take(&mut self.frames[frame]);
+        if written.regions.is_empty() && !written.statistics {return None}
+
+        let mut results = QueryResults::default();
+        if let Some(timestamps) = self.timestamps {
+            for region in written.regions {
+                let mut ticks = [0u64; 2];
+                if !get_results(logical_device, timestamps, (frame * 2 * MAX_REGIONS + 2 * region) as u32, 2, &mut ticks) {continue}
+                let elapsed = ticks[1].wrapping_sub(ticks[0]) & self.valid_bits_mask;
+                results.regions.push((self.regions[region], Duration::from_nanos((elapsed as f64 * self.nanoseconds_per_tick) as u64)));
+            }
+        }
+        if let (Some(statistics), true) = (self.statistics, written.statistics) {
+            let mut counts = [0u64; PIPELINE_STATISTICS.len()];
+            if get_results(logical_device, statistics, frame as u32, 1, &mut counts) {
+                results.statistics = Some(PipelineStatistics{vertex_invocations: counts[0], clipping_primitives: counts[1], fragment_invocations: counts[2]});
+            }
+        }
+        return Some(results);
+    }
+
+    pub fn destroy(&self, logical_device: &DeviceLoader) {
+        for query_pool in self.timestamps.iter().chain(&self.statistics) {
+            unsafe {logical_device.destroy_query_pool(*query_pool, None)};
+        }
+    }
+}
+
+//Fills values with the 64 bit results of query_count queries, evenly split between them. False if they aren't available yet
+fn get_results(logical_device: &DeviceLoader, query_pool: vk::QueryPool, first_query: u32, query_count: u32, values: &mut [u64]) -> bool {
+    let stride = size_of_val(values) / query_count as usize;
+    let result = unsafe {logical_device.get_query_pool_results(
+        query_pool,
+        first_query,
+        query_count,
+        size_of_val(values),
+        values.as_mut_ptr() as *mut c_void,
+        stride as vk::DeviceSize,
+        vk::QueryResultFlags::_64
+    )};
+    return result.raw == vk::Result::SUCCESS; //NOT_READY otherwise
+}
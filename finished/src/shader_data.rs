@@ -0,0 +1,369 @@
+// Buffers read by the mandelbrot fragment shaders, all bound in descriptor set 0:
+//   binding 0: reference orbit for the perturbation renderer (storage buffer, vec2 per iteration)
+//   binding 1: cumulative histogram for histogram coloring (storage buffer, float per bin)
+//   binding 2: the current palette (uniform buffer, vec4 per color)
+//   binding 3: parameters of the formulas (uniform buffer, FormulaParameters)
+//   binding 4: per pixel samples of the compute renderer (storage buffer, device local, see compute.rs)
+// The pipeline layout always includes the set, so every precision, coloring and renderer can share it.
+// The orbit and histogram are computed on the CPU and only rewritten when the view they depend on changes.
+// Each frame in flight has a set and copies of the buffers the CPU writes of its own, written only when the frame
+// is about to be recorded, so the frames still drawing keep reading what they were recorded with.
+// The samples are written by the GPU and shared by all frames.
+
+use erupt::{vk, DeviceLoader};
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, find_memory_type};
+
+use std::mem::size_of;
+
+use super::View;
+use super::deep_zoom::{DoubleDouble, Precision, reference_orbit};
+use super::coloring::{Coloring, iteration_cdf, HISTOGRAM_BINS};
+use super::compute::SAMPLE_SIZE;
+use super::formula::FormulaParameters;
+use super::palette::{Palette, PALETTE_SIZE};
+
+//Host visible and coherent buffer, mapped for its whole life
+struct MappedBuffer {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    mapped: *mut u8,
+    size: usize,
+}
+impl MappedBuffer {
+    fn new(context: &VulkanContext, size: usize, usage: vk::BufferUsageFlags) -> Result<MappedBuffer, VulkanAppError> {
+        let logical_device = &context.device;
+        let buffer_info = vk::BufferCreateInfoBuilder::new()
+            .size(size as vk::DeviceSize)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = unsafe {logical_device.create_buffer(&buffer_info, None)}.map_err(VulkanAppError::vulkan("create buffer"))?;
+        let memory_requirements = unsafe {logical_device.get_buffer_memory_requirements(buffer)};
+        let properties = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let mem_type = find_memory_type(&context.instance, context.physical_device, memory_requirements.memory_type_bits, properties)
+            .ok_or(VulkanAppError::NoMemoryType(properties))?;
+        let alloc_info = vk::MemoryAllocateInfoBuilder::new()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(mem_type);
+        let memory = unsafe {logical_device.allocate_memory(&alloc_info, None)}.map_err(VulkanAppError::vulkan("allocate memory"))?;
+        unsafe {logical_device.bind_buffer_memory(buffer, memory, 0)}.map_err(VulkanAppError::vulkan("bind buffer memory"))?;
+        let mapped = unsafe {logical_device.map_memory(memory, 0, size as vk::DeviceSize, vk::MemoryMapFlags::empty())}.map_err(VulkanAppError::vulkan("map memory"))? as *mut u8;
+        Ok(MappedBuffer {buffer, memory, mapped, size})
+    }
+
+    //Caller must make sure the device is not reading the buffer
+    fn write<T: Copy>(&self, data: &[T]) {
+        assert!(std::mem::size_of_val(data) <= self.size, "Data larger than its buffer!");
+        unsafe {std::ptr::copy_nonoverlapping(data.as_ptr(), self.mapped as *mut T, data.len())};
+    }
+
+    fn destroy(&self, logical_device: &DeviceLoader) {
+        unsafe {
+            logical_device.unmap_memory(self.memory);
+            logical_device.destroy_buffer(self.buffer, None);
+            logical_device.free_memory(self.memory, None);
+        }
+    }
+}
+
+//Device local buffer, only the GPU reads and writes it
+struct DeviceBuffer {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+}
+impl DeviceBuffer {
+    fn new(context: &VulkanContext, size: usize, usage: vk::BufferUsageFlags) -> Result<DeviceBuffer, VulkanAppError> {
+        let logical_device = &context.device;
+        let buffer_info = vk::BufferCreateInfoBuilder::new()
+            .size(size as vk::DeviceSize)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = unsafe {logical_device.create_buffer(&buffer_info, None)}.map_err(VulkanAppError::vulkan("create buffer"))?;
+        let memory_requirements = unsafe {logical_device.get_buffer_memory_requirements(buffer)};
+        let properties = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let mem_type = find_memory_type(&context.instance, context.physical_device, memory_requirements.memory_type_bits, properties)
+            .ok_or(VulkanAppError::NoMemoryType(properties))?;
+        let alloc_info = vk::MemoryAllocateInfoBuilder::new()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(mem_type);
+        let memory = unsafe {logical_device.allocate_memory(&alloc_info, None)}.map_err(VulkanAppError::vulkan("allocate memory"))?;
+        unsafe {logical_device.bind_buffer_memory(buffer, memory, 0)}.map_err(VulkanAppError::vulkan("bind buffer memory"))?;
+        Ok(DeviceBuffer {buffer, memory})
+    }
+
+    fn destroy(&self, logical_device: &DeviceLoader) {
+        unsafe {
+            logical_device.destroy_buffer(self.buffer, None);
+            logical_device.free_memory(self.memory, None);
+        }
+    }
+}
+
+//Which buffers of a frame are out of date
+#[derive(Clone, Copy)]
+struct Stale {
+    orbit: bool,
+    histogram: bool,
+    palette: bool,
+    formula_parameters: bool,
+}
+impl Stale {
+    const ALL: Stale = Stale {orbit: true, histogram: true, palette: true, formula_parameters: true};
+}
+
+//The descriptor set of a frame in flight and the buffers it points to, apart from the shared samples
+struct FrameData {
+    descriptor_set: vk::DescriptorSet,
+    reference_orbit: MappedBuffer,
+    histogram: MappedBuffer,
+    palette: MappedBuffer,
+    formula_parameters: MappedBuffer,
+    stale: Stale,
+}
+impl FrameData {
+    fn new(context: &VulkanContext, descriptor_set: vk::DescriptorSet, max_iterations: u32) -> Result<FrameData, VulkanAppError> {
+        let reference_orbit = MappedBuffer::new(context, (max_iterations as usize + 1) * size_of::<[f32; 2]>(), vk::BufferUsageFlags::STORAGE_BUFFER)?;
+        let histogram = MappedBuffer::new(context, HISTOGRAM_BINS * size_of::<f32>(), vk::BufferUsageFlags::STORAGE_BUFFER)?;
+        let palette = MappedBuffer::new(context, PALETTE_SIZE * size_of::<[f32; 4]>(), vk::BufferUsageFlags::UNIFORM_BUFFER)?;
+        let formula_parameters = MappedBuffer::new(context, size_of::<FormulaParameters>(), vk::BufferUsageFlags::UNIFORM_BUFFER)?;
+
+        let orbit_infos = [vk::DescriptorBufferInfoBuilder::new().buffer(reference_orbit.buffer).offset(0).range(vk::WHOLE_SIZE)];
+        let histogram_infos = [vk::DescriptorBufferInfoBuilder::new().buffer(histogram.buffer).offset(0).range(vk::WHOLE_SIZE)];
+        let palette_infos = [vk::DescriptorBufferInfoBuilder::new().buffer(palette.buffer).offset(0).range(vk::WHOLE_SIZE)];
+        let formula_infos = [vk::DescriptorBufferInfoBuilder::new().buffer(formula_parameters.buffer).offset(0).range(vk::WHOLE_SIZE)];
+        let writes = [
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&orbit_infos),
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&histogram_infos),
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(descriptor_set)
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&palette_infos),
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(descriptor_set)
+                .dst_binding(3)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&formula_infos),
+        ];
+        unsafe {context.device.update_descriptor_sets(&writes, &[])};
+
+        return Ok(FrameData {descriptor_set, reference_orbit, histogram, palette, formula_parameters, stale: Stale::ALL});
+    }
+
+    fn destroy(&self, logical_device: &DeviceLoader) {
+        self.reference_orbit.destroy(logical_device);
+        self.histogram.destroy(logical_device);
+        self.palette.destroy(logical_device);
+        self.formula_parameters.destroy(logical_device);
+    }
+}
+
+pub struct ShaderData {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    frames: Vec<FrameData>, //One per frame in flight
+    samples: DeviceBuffer,
+    previous_samples: Option<DeviceBuffer>, //Where the samples are copied to be shifted back in place when panning
+    sample_count: usize,
+    //What the frames' buffers are brought up to date with, and what the orbit and histogram were computed for
+    orbit: Vec<[f64; 2]>,
+    orbit_center: Option<[DoubleDouble; 2]>,
+    cdf: Vec<f32>,
+    histogram_view: Option<([DoubleDouble; 2], [f64; 2])>,
+    palette: Vec<[f32; 4]>,
+    formula_parameters: Option<FormulaParameters>,
+}
+impl ShaderData {
+    //Buffers and descriptor sets for frame_count frames in flight, 1 when rendering a single image
+    pub fn new(context: &VulkanContext, max_iterations: u32, frame_count: usize) -> Result<ShaderData, VulkanAppError> {
+        let logical_device = &context.device;
+
+        //A placeholder until the compute renderer sizes it for its image
+        let samples = DeviceBuffer::new(context, SAMPLE_SIZE, vk::BufferUsageFlags::STORAGE_BUFFER)?;
+
+        //// Descriptor sets
+        let descriptor_types = [
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ];
+        let bindings: Vec<_> = descriptor_types.iter().enumerate().map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBindingBuilder::new()
+                .binding(binding as u32)
+                .descriptor_type(*descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE)
+        }).collect();
+        let descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfoBuilder::new().bindings(&bindings);
+        let descriptor_set_layout = unsafe {logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)}.map_err(VulkanAppError::vulkan("create descriptor set layout"))?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(3 * frame_count as u32),
+            vk::DescriptorPoolSizeBuilder::new()
+                ._type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(2 * frame_count as u32),
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfoBuilder::new()
+            .pool_sizes(&pool_sizes)
+            .max_sets(frame_count as u32);
+        let descriptor_pool = unsafe {logical_device.create_descriptor_pool(&descriptor_pool_info, None)}.map_err(VulkanAppError::vulkan("create descriptor pool"))?;
+
+        let set_layouts = vec![descriptor_set_layout; frame_count];
+        let descriptor_set_info = vk::DescriptorSetAllocateInfoBuilder::new()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe {logical_device.allocate_descriptor_sets(&descriptor_set_info)}.map_err(VulkanAppError::vulkan("allocate descriptor sets"))?;
+
+        //// Buffers of each frame
+        let frames = descriptor_sets.iter().map(|descriptor_set| FrameData::new(context, *descriptor_set, max_iterations))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let shader_data = ShaderData {
+            descriptor_set_layout,
+            descriptor_pool,
+            frames,
+            samples,
+            previous_samples: None,
+            sample_count: 1,
+            orbit: Vec::new(),
+            orbit_center: None,
+            cdf: Vec::new(),
+            histogram_view: None,
+            palette: Palette::built_in().colors,
+            formula_parameters: None,
+        };
+        shader_data.write_samples_descriptor(logical_device);
+        Ok(shader_data)
+    }
+
+    //Makes room for a sample per pixel of an image with pixel_count pixels, for the compute renderer.
+    //Shiftable samples get a second buffer to be shifted through, see compute::iterate.
+    //The device must be idle, the old samples are dropped
+    pub fn resize_samples(&mut self, context: &VulkanContext, pixel_count: usize, shiftable: bool) -> Result<(), VulkanAppError> {
+        if pixel_count == self.sample_count && shiftable == self.previous_samples.is_some() {return Ok(())}
+        let size = pixel_count.max(1) * SAMPLE_SIZE;
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+        self.samples.destroy(&context.device);
+        self.samples = DeviceBuffer::new(context, size, usage)?;
+        if let Some(previous_samples) = self.previous_samples.take() {
+            previous_samples.destroy(&context.device);
+        }
+        if shiftable {
+            self.previous_samples = Some(DeviceBuffer::new(context, size, usage)?);
+        }
+        self.sample_count = pixel_count;
+        self.write_samples_descriptor(&context.device);
+        return Ok(());
+    }
+
+    pub fn samples(&self) -> vk::Buffer {
+        return self.samples.buffer;
+    }
+
+    //Only there for shiftable samples
+    pub fn previous_samples(&self) -> Option<vk::Buffer> {
+        return self.previous_samples.as_ref().map(|buffer| buffer.buffer);
+    }
+
+    pub fn sample_count(&self) -> usize {
+        return self.sample_count;
+    }
+
+    fn write_samples_descriptor(&self, logical_device: &DeviceLoader) {
+        let sample_infos = [vk::DescriptorBufferInfoBuilder::new().buffer(self.samples.buffer).offset(0).range(vk::WHOLE_SIZE)];
+        let writes: Vec<_> = self.frames.iter().map(|frame| {
+            vk::WriteDescriptorSetBuilder::new()
+                .dst_set(frame.descriptor_set)
+                .dst_binding(4)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&sample_infos)
+        }).collect();
+        unsafe {logical_device.update_descriptor_sets(&writes, &[])};
+    }
+
+    //Brings the orbit and histogram the precision and coloring need up to date with view, and returns the reference orbit length.
+    //Only computes them, the frames' buffers are written by prepare_frame
+    pub fn update(&mut self, view: &View, extent: vk::Extent2D, precision: Precision) -> u32 {
+        let needs_orbit = precision == Precision::Perturbation || view.coloring() == Coloring::Histogram;
+        if needs_orbit && self.orbit_center != Some(view.center) {
+            self.orbit = reference_orbit(view.center, view.max_iterations);
+            self.orbit_center = Some(view.center);
+            for frame in &mut self.frames {
+                frame.stale.orbit = true;
+            }
+        }
+
+        let scale = view.scale(extent);
+        if view.coloring() == Coloring::Histogram && self.histogram_view != Some((view.center, scale)) {
+            self.cdf = iteration_cdf(&self.orbit, scale, view.max_iterations);
+            self.histogram_view = Some((view.center, scale));
+            for frame in &mut self.frames {
+                frame.stale.histogram = true;
+            }
+        }
+
+        return self.orbit.len() as u32;
+    }
+
+    //Written by prepare_frame, like update
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.colors.clone();
+        for frame in &mut self.frames {
+            frame.stale.palette = true;
+        }
+    }
+
+    //Written by prepare_frame, like update
+    pub fn set_formula_parameters(&mut self, parameters: &FormulaParameters) {
+        self.formula_parameters = Some(*parameters);
+        for frame in &mut self.frames {
+            frame.stale.formula_parameters = true;
+        }
+    }
+
+    //Writes what changed since frame was last prepared to its buffers, and returns its descriptor set.
+    //The frame must not be in flight, its fence having been waited for
+    pub fn prepare_frame(&mut self, frame: usize) -> vk::DescriptorSet {
+        let data = &mut self.frames[frame];
+        if data.stale.orbit {
+            let orbit_f32: Vec<[f32; 2]> = self.orbit.iter().map(|z| [z[0] as f32, z[1] as f32]).collect();
+            data.reference_orbit.write(&orbit_f32);
+        }
+        if data.stale.histogram {
+            data.histogram.write(&self.cdf);
+        }
+        if data.stale.palette {
+            data.palette.write(&self.palette);
+        }
+        if let (true, Some(parameters)) = (data.stale.formula_parameters, &self.formula_parameters) {
+            data.formula_parameters.write(&[*parameters]);
+        }
+        data.stale = Stale {orbit: false, histogram: false, palette: false, formula_parameters: self.formula_parameters.is_none()};
+        return data.descriptor_set;
+    }
+
+    pub fn destroy(&self, logical_device: &DeviceLoader) {
+        unsafe {
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+        for frame in &self.frames {
+            frame.destroy(logical_device);
+        }
+        self.samples.destroy(logical_device);
+        if let Some(previous_samples) = &self.previous_samples {
+            previous_samples.destroy(logical_device);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Supersampling, against the aliasing along the boundary of the set where neighbouring pixels end up with wildly
+// different iteration counts. Either way every pixel averages factor x factor points of the plane:
+// MSAA renders into a multisampled image resolved at the end of the render pass, running the fragment shader once per sample,
+// jitter has the fragment shaders themselves iterate a grid of points in each pixel, each moved randomly within its cell.
+
+use clap::ValueEnum;
+use erupt::{vk, DeviceLoader};
+use vulkan_bootstrap::{VulkanContext, VulkanAppError};
+
+use super::compute::Renderer;
+use super::offscreen::allocate;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SupersampleMode {
+    /// Multisampled framebuffer with sample shading. Falls back to jitter if the device can't render factor² samples per pixel
+    Msaa,
+    /// The fragment shaders iterate a jittered grid of points per pixel, works on any device and with any factor
+    Jitter,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Supersampling {
+    pub mode: SupersampleMode,
+    pub factor: u32, //Points along each side of a pixel, 1 for none
+}
+impl Supersampling {
+    pub const NONE: Supersampling = Supersampling{mode: SupersampleMode::Jitter, factor: 1};
+
+    //Samples per pixel of the framebuffer and pipelines
+    pub fn samples(self) -> vk::SampleCountFlagBits {
+        if self.mode != SupersampleMode::Msaa {return vk::SampleCountFlagBits::_1}
+        return vk::SampleCountFlagBits(self.factor * self.factor);
+    }
+
+    //Points along each side of a pixel the fragment shaders iterate, the supersample push constant
+    pub fn jitter(self) -> u32 {
+        return if self.mode == SupersampleMode::Jitter {self.factor} else {1};
+    }
+
+    //self if context can render it with renderer, otherwise the closest it can. MSAA needs sample rate shading,
+    //enabled through ContextOptions::optional_features, and factor² samples in a color attachment
+    pub fn available(self, context: &VulkanContext, renderer: Renderer) -> Supersampling {
+        if self.factor == 1 {return Supersampling::NONE}
+        if renderer == Renderer::Compute { //colorize.frag only reads the one sample per pixel
+            println!("The compute renderer does not supersample");
+            return Supersampling::NONE;
+        }
+        if self.mode == SupersampleMode::Msaa {
+            let count = self.factor * self.factor;
+            let limits = unsafe {context.instance.get_physical_device_properties(context.physical_device)}.limits;
+            let supported_counts = limits.framebuffer_color_sample_counts;
+            if context.enabled_features.sample_rate_shading != vk::TRUE {
+                println!("Device does not support sample rate shading, supersampling with jitter instead");
+                return Supersampling{mode: SupersampleMode::Jitter, ..self};
+            }
+            if !count.is_power_of_two() || !supported_counts.contains(vk::SampleCountFlags::from_bits_truncate(count)) {
+                println!("Device can't render {} samples per pixel (supported: {:?}), supersampling with jitter instead", count, supported_counts);
+                return Supersampling{mode: SupersampleMode::Jitter, ..self};
+            }
+        }
+        return self;
+    }
+}
+
+//The multisampled color attachment MSAA draws into, resolved into the image presented or read back
+pub struct MultisampleImage {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+}
+impl MultisampleImage {
+    //None unless supersampling samples more than once per pixel
+    pub fn new(context: &VulkanContext, format: vk::Format, extent: vk::Extent2D, supersampling: Supersampling) -> Result<Option<MultisampleImage>, VulkanAppError> {
+        let samples = supersampling.samples();
+        if samples == vk::SampleCountFlagBits::_1 {return Ok(None)}
+        let logical_device = &context.device;
+
+        //Only ever used within the render pass, so it needs no memory on tiled GPUs that support lazy allocation
+        let image_info = vk::ImageCreateInfoBuilder::new()
+            .image_type(vk::ImageType::_2D)
+            .format(format)
+            .extent(vk::Extent3D{width: extent.width, height: extent.height, depth: 1})
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image = unsafe {logical_device.create_image(&image_info, None)}.map_err(VulkanAppError::vulkan("create multisampled image"))?;
+        let memory_requirements = unsafe {logical_device.get_image_memory_requirements(image)};
+        let memory = allocate(context, memory_requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED)
+            .or_else(|_| allocate(context, memory_requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL))?;
+        unsafe {logical_device.bind_image_memory(image, memory, 0)}.map_err(VulkanAppError::vulkan("bind image memory"))?;
+
+        let image_view_info = vk::ImageViewCreateInfoBuilder::new()
+            .image(image)
+            .view_type(vk::ImageViewType::_2D)
+            .format(format)
+            .subresource_range(vk::ImageSubresourceRange{
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        let view = unsafe {logical_device.create_image_view(&image_view_info, None)}.map_err(VulkanAppError::vulkan("create image view"))?;
+        return Ok(Some(MultisampleImage{image, memory, view}));
+    }
+
+    pub fn destroy(&self, logical_device: &DeviceLoader) {
+        unsafe {
+            logical_device.destroy_image_view(self.view, None);
+            logical_device.destroy_image(self.image, None);
+            logical_device.free_memory(self.memory, None);
+        }
+    }
+}
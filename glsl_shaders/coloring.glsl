@@ -0,0 +1,77 @@
+// Coloring, shared by the fragment shaders: turns what iterating a point left behind into a color from the palette.
+// Needs PushConstants with coloring and max_iter declared before it is included.
+
+#include "formula_parameters.glsl"
+
+//Values of the coloring push constant, must match Coloring in coloring.rs
+const uint COLORING_ESCAPE_TIME = 0;
+const uint COLORING_SMOOTH = 1;
+const uint COLORING_HISTOGRAM = 2;
+const uint COLORING_DISTANCE = 3;
+const uint COLORING_ORBIT_TRAP = 4;
+//Must match coloring.rs
+const uint HISTOGRAM_BINS = 256;
+
+//Cumulative distribution of smooth iteration counts in view, computed on the CPU
+layout(std430, set = 0, binding = 1) readonly buffer IterationHistogram {
+    float cdf[];
+} Histogram;
+
+//Must match palette.rs
+const uint PALETTE_SIZE = 256;
+
+//The current palette, resampled to PALETTE_SIZE linear RGB colors on the CPU
+layout(std140, set = 0, binding = 2) uniform PaletteColors {
+    vec4 colors[PALETTE_SIZE];
+} Palette;
+
+vec3 colormap(float n) {
+    float x = clamp(n, 0.0, 1.0) * float(PALETTE_SIZE - 1);
+    uint i0 = min(uint(x), PALETTE_SIZE - 2);
+    float t = x - float(i0);
+
+    return mix(Palette.colors[i0].rgb, Palette.colors[i0+1].rgb, t);
+}
+
+//i: iterations done, z: last value, der: derivative dz/dc, trap: closest approach to the axes,
+//pixel_size: distance between pixels in the complex plane, power: of z in the formula
+vec3 color(int i, vec2 z, vec2 der, float trap, float pixel_size, float power) {
+    uint coloring = PushConstants.coloring;
+    float max_iter = float(PushConstants.max_iter);
+    if (coloring == COLORING_ORBIT_TRAP) {
+        return colormap(exp(-8.0 * trap));
+    }
+    if (i == int(PushConstants.max_iter)) { //Inside the set
+        return vec3(0.0, 0.0, 0.0);
+    }
+
+    float z_squared = dot(z, z);
+    float smooth_iter = float(i) + 1.0 - log(0.5 * log(z_squared)) / log(power);
+    if (coloring == COLORING_ESCAPE_TIME) {
+        return colormap(float(i) / max_iter);
+    } else if (coloring == COLORING_HISTOGRAM) {
+        float x = clamp(smooth_iter / max_iter, 0.0, 1.0) * float(HISTOGRAM_BINS);
+        uint bin = min(uint(x), HISTOGRAM_BINS - 1);
+        float below = bin == 0 ? 0.0 : Histogram.cdf[bin - 1];
+        return colormap(mix(below, Histogram.cdf[bin], fract(x)));
+    } else if (coloring == COLORING_DISTANCE) {
+        //Exterior distance estimate |z| log|z| / |dz/dc|, in pixels
+        float distance = 0.5 * sqrt(z_squared) * log(z_squared) / length(der);
+        return colormap(1.0 - clamp(log2(1.0 + distance / pixel_size) / 8.0, 0.0, 1.0));
+    }
+    return colormap(smooth_iter / max_iter);
+}
+
+//Newton fractals converge instead of escaping. Colored by the root reached, darker the more iterations it took
+vec3 newton_color(int i, vec2 z) {
+    if (i == int(PushConstants.max_iter)) { //Did not converge
+        return vec3(0.0, 0.0, 0.0);
+    }
+    uint nearest = 0;
+    for (uint r = 1; r < Formula.degree; r++) {
+        if (distance(z, Formula.roots[r].xy) < distance(z, Formula.roots[nearest].xy)) {
+            nearest = r;
+        }
+    }
+    return colormap((float(nearest) + 0.5) / float(Formula.degree)) * exp(-0.05 * float(i));
+}
@@ -0,0 +1,48 @@
+#version 450
+// Coloring pass of the compute renderer, colors the samples mandelbrot.comp wrote for each pixel.
+// Changing the palette or coloring only needs this pass, the iterations are kept.
+
+layout(push_constant) uniform UBlock {
+    vec2 center;
+    vec2 scale;
+    uint max_iter;
+    uint reference_length;
+    uint coloring;
+    uint padding;
+    vec4 center_f64; //Double precision center of mandelbrot_f64.frag, unused here
+    vec2 julia_c;
+    uvec2 pane_offset; //Of the pane in the image, in pixels
+    uvec2 pane_extent;
+    uint image_width; //Samples per row of the sample buffer
+    uint formula;
+} PushConstants;
+layout(location = 0) out vec4 outColor;
+
+#include "coloring.glsl"
+
+//What the coloring needs of each pixel, must match SAMPLE_SIZE in compute.rs
+struct Sample {
+    vec2 z; //Last value
+    float iterations;
+    float trap; //Closest approach to the axes
+    float der_length; //Of the derivative dz/dc
+    float padding;
+};
+
+layout(std430, set = 0, binding = 4) readonly buffer SampleBuffer {
+    Sample samples[];
+} Samples;
+
+void main() {
+    uvec2 pixel = uvec2(gl_FragCoord.xy);
+    Sample s = Samples.samples[pixel.y * PushConstants.image_width + pixel.x];
+    int i = int(s.iterations);
+    if (PushConstants.formula == uint(FORMULA_NEWTON)) {
+        outColor = vec4(newton_color(i, s.z), 1.0);
+        return;
+    }
+    float power = PushConstants.formula == uint(FORMULA_MULTIBROT) ? float(Formula.power) : 2.0;
+    float pixel_size = 2.0 * PushConstants.scale.y / float(PushConstants.pane_extent.y);
+    //Only the length of the derivative is kept, which is all the distance estimate uses
+    outColor = vec4(color(i, s.z, vec2(s.der_length, 0.0), s.trap, pixel_size, power), 1.0);
+}
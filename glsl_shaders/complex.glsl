@@ -0,0 +1,8 @@
+// Complex arithmetic on vec2s, the real part in [0] and the imaginary part in [1].
+
+vec2 complex_mul(vec2 a, vec2 b) {
+    return vec2(a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]);
+}
+vec2 complex_div(vec2 a, vec2 b) {
+    return vec2(a[0]*b[0] + a[1]*b[1], a[1]*b[0] - a[0]*b[1]) / dot(b, b);
+}
@@ -0,0 +1,26 @@
+// The formulas and the parameters they share across pixels, for every shader that iterates or colors them.
+
+//Compiled once per formula, the values must match Formula in formula.rs
+#define FORMULA_MANDELBROT 0
+#define FORMULA_MULTIBROT 1
+#define FORMULA_BURNING_SHIP 2
+#define FORMULA_TRICORN 3
+#define FORMULA_NEWTON 4
+#ifndef FORMULA
+#define FORMULA FORMULA_MANDELBROT
+#endif
+
+//Must match formula.rs
+const uint MAX_DEGREE = 8;
+//Newton converges once its steps get this short
+const float NEWTON_TOLERANCE_SQUARED = 1e-10;
+//Escape radius, must match coloring.rs
+const float ESCAPE_RADIUS_SQUARED = 65536.0;
+
+//Parameters of the formulas, the same for every pixel
+layout(std140, set = 0, binding = 3) uniform FormulaParameters {
+    vec4 coefficients[MAX_DEGREE + 1]; //Newton polynomial, the coefficient of z^k in coefficients[k].xy
+    vec4 roots[MAX_DEGREE]; //Of the Newton polynomial, in .xy
+    uint power; //Multibrot exponent
+    uint degree; //Newton polynomial degree
+} Formula;
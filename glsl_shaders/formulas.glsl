@@ -0,0 +1,85 @@
+// Iterating the formulas in single precision, shared by mandelbrot.frag and mandelbrot.comp of the compute renderer.
+// Needs PushConstants with max_iter and julia_c declared before it is included.
+// JULIA is defined for the variants drawing Julia sets, iterating from the point with a fixed c
+
+#include "formula_parameters.glsl"
+#include "complex.glsl"
+
+//What iterating a point leaves for the coloring
+struct Orbit {
+    int i; //Iterations done
+    vec2 z; //Last value
+    vec2 der; //Derivative dz/dc, or dz/dz_0 for Julia sets. Zero for Newton
+    float trap; //Closest approach to the axes, zero for Newton
+    float power; //Of z in the formula
+};
+
+Orbit iterate(vec2 pos) {
+    int max_iter = int(PushConstants.max_iter);
+    int i = 0;
+
+#if FORMULA == FORMULA_NEWTON
+    //z_n+1 = z_n - p(z_n)/p'(z_n) from the pixel, c is not used
+    vec2 z = pos;
+    while (i < max_iter) {
+        //Horner's scheme for p and p' together
+        vec2 p = Formula.coefficients[Formula.degree].xy;
+        vec2 dp = vec2(0.0,0.0);
+        for (int k = int(Formula.degree) - 1; k >= 0; k--) {
+            dp = complex_mul(dp, z) + p;
+            p = complex_mul(p, z) + Formula.coefficients[k].xy;
+        }
+        vec2 step = complex_div(p, dp);
+        z = z - step;
+        i = i+1;
+        if (dot(step, step) < NEWTON_TOLERANCE_SQUARED) {
+            break;
+        }
+    }
+    return Orbit(i, z, vec2(0.0,0.0), 0.0, 2.0);
+#else
+#ifdef JULIA
+    vec2 c = PushConstants.julia_c;
+    vec2 z = pos;
+    vec2 der = vec2(1.0,0.0); //dz/dz_0 rather than dz/dc
+    const vec2 der_c = vec2(0.0,0.0);
+#else
+    vec2 c = pos;
+    vec2 z = vec2(0.0,0.0);
+    vec2 der = vec2(0.0,0.0);
+    const vec2 der_c = vec2(1.0,0.0);
+#endif
+    float power = FORMULA == FORMULA_MULTIBROT ? float(Formula.power) : 2.0;
+    float trap = 1e20;
+
+    while (z[0]*z[0] + z[1]*z[1] <= ESCAPE_RADIUS_SQUARED && i < max_iter) {
+#if FORMULA == FORMULA_MULTIBROT
+        //z^n + c, with z^(n-1) kept for the derivative n*z^(n-1)*dz
+        vec2 z_power = z;
+        for (uint k = 2; k < Formula.power; k++) {
+            z_power = complex_mul(z_power, z);
+        }
+        der = power*complex_mul(z_power, der) + der_c;
+        z = complex_mul(z_power, z) + c;
+#else
+#if FORMULA == FORMULA_BURNING_SHIP
+        //(|re z| + i|im z|)^2 + c, the fold flips the derivative along with z
+        der = der * vec2(z[0] < 0.0 ? -1.0 : 1.0, z[1] < 0.0 ? -1.0 : 1.0);
+        z = abs(z);
+#elif FORMULA == FORMULA_TRICORN
+        //conj(z)^2 + c
+        der = vec2(der[0], -der[1]);
+        z = vec2(z[0], -z[1]);
+#endif
+        der = 2.0*complex_mul(z, der) + der_c;
+        float tmp_r = z[0];
+        z[0] = z[0]*z[0] - z[1]*z[1] + c[0];
+        z[1] = 2*tmp_r*z[1] + c[1];
+#endif
+        trap = min(trap, min(abs(z[0]), abs(z[1])));
+        i = i+1;
+    }
+
+    return Orbit(i, z, der, trap, power);
+#endif
+}
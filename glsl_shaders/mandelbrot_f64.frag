@@ -0,0 +1,85 @@
+#version 450
+
+layout(push_constant) uniform UBlock {
+    vec2 center; //Zero, the vertex shader only outputs the offset from the center
+    vec2 scale;
+    uint max_iter;
+    uint reference_length;
+    uint coloring;
+    uint supersample; //Points along each side of a pixel, see supersampling.glsl. Also puts dvec2 at a multiple of 16 bytes
+    dvec2 center_f64;
+} PushConstants;
+layout(location = 0) in vec2 complexPos;
+layout(location = 0) out vec4 outColor;
+
+#include "formula_parameters.glsl"
+#include "complex.glsl"
+#include "coloring.glsl"
+
+dvec2 complex_mul(dvec2 a, dvec2 b) {
+    return dvec2(a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]);
+}
+dvec2 complex_div(dvec2 a, dvec2 b) {
+    return dvec2(a[0]*b[0] + a[1]*b[1], a[1]*b[0] - a[0]*b[1]) / dot(b, b);
+}
+
+//Same formulas as mandelbrot.frag, with z and c in double precision. pos is the offset from the center
+vec3 shade(vec2 pos, float pixel_size) {
+    int max_iter = int(PushConstants.max_iter);
+    int i = 0;
+
+#if FORMULA == FORMULA_NEWTON
+    dvec2 z = PushConstants.center_f64 + dvec2(pos);
+    while (i < max_iter) {
+        dvec2 p = dvec2(Formula.coefficients[Formula.degree].xy);
+        dvec2 dp = dvec2(0.0,0.0);
+        for (int k = int(Formula.degree) - 1; k >= 0; k--) {
+            dp = complex_mul(dp, z) + p;
+            p = complex_mul(p, z) + dvec2(Formula.coefficients[k].xy);
+        }
+        dvec2 step = complex_div(p, dp);
+        z = z - step;
+        i = i+1;
+        if (dot(step, step) < NEWTON_TOLERANCE_SQUARED) {
+            break;
+        }
+    }
+    return newton_color(i, vec2(z));
+#else
+    dvec2 c = PushConstants.center_f64 + dvec2(pos);
+    dvec2 z = dvec2(0.0,0.0);
+    vec2 der = vec2(0.0,0.0); //Only needs to be roughly right, single precision is fine
+    float power = FORMULA == FORMULA_MULTIBROT ? float(Formula.power) : 2.0;
+    float trap = 1e20;
+
+    while (z[0]*z[0] + z[1]*z[1] <= ESCAPE_RADIUS_SQUARED && i < max_iter) {
+#if FORMULA == FORMULA_MULTIBROT
+        dvec2 z_power = z;
+        for (uint k = 2; k < Formula.power; k++) {
+            z_power = complex_mul(z_power, z);
+        }
+        der = power*complex_mul(vec2(z_power), der) + vec2(1.0, 0.0);
+        z = complex_mul(z_power, z) + c;
+#else
+#if FORMULA == FORMULA_BURNING_SHIP
+        der = der * vec2(z[0] < 0.0 ? -1.0 : 1.0, z[1] < 0.0 ? -1.0 : 1.0);
+        z = abs(z);
+#elif FORMULA == FORMULA_TRICORN
+        der = vec2(der[0], -der[1]);
+        z = dvec2(z[0], -z[1]);
+#endif
+        vec2 z_f32 = vec2(z);
+        der = 2.0*vec2(z_f32[0]*der[0] - z_f32[1]*der[1], z_f32[0]*der[1] + z_f32[1]*der[0]) + vec2(1.0, 0.0);
+        double tmp_r = z[0];
+        z[0] = z[0]*z[0] - z[1]*z[1] + c[0];
+        z[1] = 2*tmp_r*z[1] + c[1];
+#endif
+        trap = min(trap, float(min(abs(z[0]), abs(z[1]))));
+        i = i+1;
+    }
+
+    return color(i, vec2(z), der, trap, pixel_size, power);
+#endif
+}
+
+#include "supersampling.glsl"
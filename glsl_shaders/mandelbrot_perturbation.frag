@@ -0,0 +1,55 @@
+#version 450
+
+//Only the quadratic Mandelbrot formula, the others fall back to double or single precision
+
+layout(push_constant) uniform UBlock {
+    vec2 center; //Zero, the vertex shader only outputs the offset from the reference point
+    vec2 scale;
+    uint max_iter;
+    uint reference_length;
+    uint coloring;
+    uint supersample; //Points along each side of a pixel, see supersampling.glsl
+} PushConstants;
+//Orbit of the reference point (the center of the view) computed on the CPU in high precision
+layout(std430, set = 0, binding = 0) readonly buffer ReferenceOrbit {
+    vec2 orbit[];
+} Reference;
+layout(location = 0) in vec2 complexPos;
+layout(location = 0) out vec4 outColor;
+
+#include "complex.glsl"
+#include "coloring.glsl"
+
+//Color of the point pos away from the reference point, with pixels pixel_size apart
+vec3 shade(vec2 pos, float pixel_size) {
+    int max_iter = int(PushConstants.max_iter);
+    int last_ref = int(PushConstants.reference_length) - 1;
+    vec2 dc = pos; //Offset of this pixel from the reference point
+    vec2 dz = vec2(0.0,0.0); //Offset of this pixel's orbit from the reference orbit
+    int ref_i = 0;
+    vec2 der = vec2(0.0,0.0);
+    float trap = 1e20;
+
+    int i = 0;
+    vec2 z = vec2(0.0,0.0);
+    while (z[0]*z[0] + z[1]*z[1] <= ESCAPE_RADIUS_SQUARED && i < max_iter) {
+        der = 2.0*complex_mul(z, der) + vec2(1.0, 0.0);
+        //z_n+1 = z_n^2 + c with z_n = Z_n + dz_n gives dz_n+1 = (2*Z_n + dz_n)*dz_n + dc
+        dz = complex_mul(2.0*Reference.orbit[ref_i] + dz, dz) + dc;
+        ref_i = ref_i+1;
+        z = Reference.orbit[ref_i] + dz;
+        trap = min(trap, min(abs(z[0]), abs(z[1])));
+        i = i+1;
+
+        //Rebase when the pixel's orbit comes closer to zero than to the reference orbit, or the reference orbit runs out,
+        //continuing from the start of the reference orbit (which is zero) avoids the precision loss (glitches)
+        if (z[0]*z[0] + z[1]*z[1] < dz[0]*dz[0] + dz[1]*dz[1] || ref_i == last_ref) {
+            dz = z;
+            ref_i = 0;
+        }
+    }
+
+    return color(i, z, der, trap, pixel_size, 2.0);
+}
+
+#include "supersampling.glsl"
@@ -0,0 +1,29 @@
+// Supersampling, shared by the fragment shaders.
+// Needs PushConstants with supersample, the complexPos input, the outColor output and
+// vec3 shade(vec2 pos, float pixel_size) declared before it is included.
+
+//Pseudo random point in the unit square for sub-sample index of pixel (pcg3d), the same every frame so still views don't shimmer
+vec2 jitter(uvec2 pixel, uint index) {
+    uvec3 v = uvec3(pixel, index) * 1664525u + 1013904223u;
+    v.x += v.y*v.z; v.y += v.z*v.x; v.z += v.x*v.y;
+    v ^= v >> 16u;
+    v.x += v.y*v.z; v.y += v.z*v.x;
+    return vec2(v.xy) * (1.0 / 4294967296.0);
+}
+
+//Averages shade over a grid of supersample x supersample points spread over the pixel, each jittered within its cell
+void main() {
+    float pixel_size = fwidth(complexPos.y);
+    uint n = max(PushConstants.supersample, 1u);
+    if (n == 1u) {
+        outColor = vec4(shade(complexPos, pixel_size), 1.0);
+        return;
+    }
+    vec2 pixel_step = vec2(dFdx(complexPos.x), dFdy(complexPos.y));
+    vec3 sum = vec3(0.0, 0.0, 0.0);
+    for (uint k = 0u; k < n*n; k++) {
+        vec2 cell = (vec2(k % n, k / n) + jitter(uvec2(gl_FragCoord.xy), k)) / float(n) - 0.5;
+        sum += shade(complexPos + cell * pixel_step, pixel_size);
+    }
+    outColor = vec4(sum / float(n*n), 1.0);
+}
@@ -0,0 +1,171 @@
+// Compiles the GLSL sources in glsl_shaders/ to SPIR-V while a crate builds, so a binary can never include
+// shaders older than their sources. Called from the build script of every crate with shaders:
+//
+//     shader_build::compile(&[Shader::new("../glsl_shaders/triangle.vert", "tri_vert.spv")]);
+//
+// after which the crate includes the result with include_bytes!(concat!(env!("OUT_DIR"), "/tri_vert.spv")).
+// Shader errors fail the build, printed with the offending GLSL lines.
+// compile_glsl can also be called at runtime, to reload shaders without rebuilding.
+//
+// Code shared between shaders lives in .glsl files of its own, pulled in with #include "file" (see preprocess),
+// which naga's GLSL frontend doesn't do by itself.
+
+use naga::back::spv;
+use naga::front::glsl;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::ShaderStage;
+
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+//One SPIR-V module to build
+pub struct Shader {
+    pub source: &'static str, //GLSL file relative to the crate being built, the stage is given by the extension
+    pub output: &'static str, //File name in OUT_DIR
+    pub defines: &'static [(&'static str, &'static str)], //Like #define name value at the top of the source
+}
+impl Shader {
+    pub const fn new(source: &'static str, output: &'static str) -> Shader {
+        return Shader{source, output, defines: &[]};
+    }
+
+    pub const fn with_defines(source: &'static str, output: &'static str, defines: &'static [(&'static str, &'static str)]) -> Shader {
+        return Shader{source, output, defines};
+    }
+}
+
+//Compiles every shader into OUT_DIR, reporting all errors before failing the build
+pub fn compile(shaders: &[Shader]) {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR not set, shaders must be compiled from a build script!"));
+    let mut failed = false;
+    for shader in shaders {
+        println!("cargo:rerun-if-changed={}", shader.source);
+        if let Ok((_, includes)) = preprocess(Path::new(shader.source)) {
+            for include in includes {
+                println!("cargo:rerun-if-changed={}", include.display());
+            }
+        }
+        match compile_glsl(Path::new(shader.source), shader.defines) {
+            Ok(words) => {
+                let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+                fs::write(out_dir.join(shader.output), bytes).expect("Could not write SPIR-V!");
+            },
+            Err(message) => {
+                eprintln!("{}", message);
+                failed = true;
+            },
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+//Compiles one GLSL file to SPIR-V words, or returns the diagnostics
+pub fn compile_glsl(path: &Path, defines: &[(&str, &str)]) -> Result<Vec<u32>, String> {
+    //Shown in diagnostics, with the defines as one source compiles to several variants
+    let mut name = path.display().to_string();
+    if !defines.is_empty() {
+        let defines: Vec<String> = defines.iter().map(|(define, value)| format!("{}={}", define, value)).collect();
+        name = format!("{} ({})", name, defines.join(", "));
+    }
+
+    let (source, _) = preprocess(path)?;
+    let stage = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("vert") => ShaderStage::Vertex,
+        Some("frag") => ShaderStage::Fragment,
+        Some("comp") => ShaderStage::Compute,
+        _ => return Err(format!("error: {}: unknown shader stage, expected .vert, .frag or .comp", path.display())),
+    };
+
+    let mut options = glsl::Options::from(stage);
+    for (define, value) in defines {
+        options.defines.insert(define.to_string(), value.to_string());
+    }
+    let module = glsl::Frontend::default().parse(&options, &source)
+        .map_err(|errors| errors.emit_to_string_with_path(&source, &name))?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module)
+        .map_err(|error| error.emit_to_string_with_path(&source, &name))?;
+
+    let mut spv_options = spv::Options::default();
+    spv_options.flags.remove(spv::WriterFlags::ADJUST_COORDINATE_SPACE); //Vulkan GLSL already has y pointing down
+    return spv::write_vec(&module, &info, &spv_options, None).map_err(|error| format!("error: {}: {}", name, error));
+}
+
+//The source of path with every #include "file" line replaced by that file, found next to the file including it.
+//Each file is pulled in once however often it is included, so shared files can include what they need themselves.
+//Also returns the files included, which the SPIR-V depends on as much as on path. Diagnostics quote the expanded source
+pub fn preprocess(path: &Path) -> Result<(String, Vec<PathBuf>), String> {
+    let mut seen = vec![path.to_path_buf()];
+    let source = expand_includes(path, &mut seen)?;
+    seen.remove(0);
+    return Ok((source, seen));
+}
+
+fn expand_includes(path: &Path, seen: &mut Vec<PathBuf>) -> Result<String, String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("error: could not read {}: {}", path.display(), err))?;
+    let mut expanded = String::with_capacity(source.len());
+    for (line_number, line) in source.lines().enumerate() {
+        let Some(directive) = line.trim_start().strip_prefix('#').and_then(|rest| rest.trim_start().strip_prefix("include")) else {
+            expanded += line;
+            expanded.push('\n');
+            continue;
+        };
+        let file_name = directive.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| format!("error: {}:{}: expected #include \"file\"", path.display(), line_number + 1))?;
+        let include = path.parent().unwrap_or(Path::new("")).join(file_name);
+        if !seen.contains(&include) {
+            seen.push(include.clone());
+            expanded += &expand_includes(&include, seen)?;
+        }
+    }
+    return Ok(expanded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A directory of its own under the system's temporary one, with files of the given names and contents
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("shader_build_{}_{}", test, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files {
+            fs::write(directory.join(name), contents).unwrap();
+        }
+        return directory;
+    }
+
+    #[test]
+    fn includes_are_expanded_once() {
+        let directory = write_files("once", &[
+            ("main.frag", "#version 450\n#include \"a.glsl\"\n  #  include \"b.glsl\"\nvoid main() {}\n"),
+            ("a.glsl", "#include \"b.glsl\"\nfloat a;\n"),
+            ("b.glsl", "float b;\n"),
+        ]);
+        let (source, includes) = preprocess(&directory.join("main.frag")).unwrap();
+        assert_eq!(source, "#version 450\nfloat b;\nfloat a;\nvoid main() {}\n");
+        assert_eq!(includes, vec![directory.join("a.glsl"), directory.join("b.glsl")]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn a_file_including_itself_is_not_expanded_again() {
+        let directory = write_files("self", &[("main.frag", "#include \"main.frag\"\nvoid main() {}\n")]);
+        let (source, includes) = preprocess(&directory.join("main.frag")).unwrap();
+        assert_eq!(source, "void main() {}\n");
+        assert!(includes.is_empty());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn missing_and_malformed_includes_are_errors() {
+        let directory = write_files("errors", &[
+            ("missing.frag", "#include \"nowhere.glsl\"\n"),
+            ("malformed.frag", "void main() {}\n#include <a.glsl>\n"),
+        ]);
+        assert!(preprocess(&directory.join("missing.frag")).unwrap_err().contains("nowhere.glsl"));
+        assert!(preprocess(&directory.join("malformed.frag")).unwrap_err().contains("malformed.frag:2"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,10 @@
+// Compiles the shaders in glsl_shaders/ into OUT_DIR, see shader_build
+
+use shader_build::{compile, Shader};
+
+fn main() {
+    compile(&[
+        Shader::new("../glsl_shaders/triangle.vert", "tri_vert.spv"),
+        Shader::new("../glsl_shaders/triangle.frag", "tri_frag.spv"),
+    ]);
+}
@@ -0,0 +1,91 @@
+use erupt::{vk, LoaderError};
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything that can go wrong while setting up Vulkan, from loading the library to building pipelines.
+/// Failed Vulkan calls carry the [`vk::Result`] they returned.
+#[derive(Debug)]
+pub enum VulkanAppError {
+    /// The Vulkan library could not be loaded, usually because no driver is installed
+    Loader(String),
+    /// Validation was asked for, but `VK_LAYER_KHRONOS_validation` is not installed
+    ValidationLayerMissing,
+    Instance(vk::Result),
+    Surface(vk::Result),
+    /// No physical device supports Vulkan at all
+    NoDevice,
+    /// A device was asked for by index, but there are only `count`
+    DeviceIndexOutOfRange{index: usize, count: usize},
+    /// A device was asked for by name, but none has it in theirs
+    DeviceNotFound(String),
+    /// The chosen device lacks a queue family, extension, feature or surface support the renderer needs
+    UnsuitableDevice(String),
+    Device(vk::Result),
+    Swapchain(vk::Result),
+    Pipeline(vk::Result),
+    /// SPIR-V that is not a whole number of 4-byte words, or lacks the magic number
+    ShaderDecode(String),
+    /// No memory type with the properties a buffer or image needs
+    NoMemoryType(vk::MemoryPropertyFlags),
+    /// Any other failed call, named by what it was doing
+    Vulkan{action: &'static str, result: vk::Result},
+    /// A validation error the debug messenger recorded, see [`crate::DebugMessages::fail_on_error`]
+    Validation(String),
+    /// A file the renderer writes, like an image or statistics, could not be created or written
+    Io{path: PathBuf, error: io::Error},
+}
+impl VulkanAppError {
+    /// For `map_err` on calls without their own variant, e.g. `.map_err(VulkanAppError::vulkan("create command pool"))`
+    pub fn vulkan(action: &'static str) -> impl FnOnce(vk::Result) -> VulkanAppError {
+        move |result| VulkanAppError::Vulkan{action, result}
+    }
+
+    /// For `map_err` on writing the file at `path`, taking anything that converts to an [`io::Error`] like encoder errors
+    pub fn io<E: Into<io::Error>>(path: &Path) -> impl FnOnce(E) -> VulkanAppError {
+        let path = path.to_path_buf();
+        move |error| VulkanAppError::Io{path, error: error.into()}
+    }
+
+    /// Process exit code: 2 if Vulkan itself is unavailable, 3 if no usable device was found, 1 for anything else
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            VulkanAppError::Loader(_) | VulkanAppError::ValidationLayerMissing | VulkanAppError::Instance(_) => 2,
+            VulkanAppError::NoDevice | VulkanAppError::DeviceIndexOutOfRange{..} | VulkanAppError::DeviceNotFound(_)
+                | VulkanAppError::UnsuitableDevice(_) => 3,
+            _ => 1,
+        }
+    }
+}
+impl fmt::Display for VulkanAppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VulkanAppError::Loader(message) => write!(f, "could not load the Vulkan library ({}), is a Vulkan driver installed?", message),
+            VulkanAppError::ValidationLayerMissing => write!(f, "validation was requested but the Khronos validation layer is not installed"),
+            VulkanAppError::Instance(result) => write!(f, "could not create the Vulkan instance: {}", result),
+            VulkanAppError::Surface(result) => write!(f, "could not create a surface for the window: {}", result),
+            VulkanAppError::NoDevice => write!(f, "no device with Vulkan support was found"),
+            VulkanAppError::DeviceIndexOutOfRange{index, count} => write!(f, "GPU index {} is out of range, only {} devices were found", index, count),
+            VulkanAppError::DeviceNotFound(name) => write!(f, "no device has \"{}\" in its name", name),
+            VulkanAppError::UnsuitableDevice(name) => write!(f, "{} lacks the queues, extensions or features the renderer needs", name),
+            VulkanAppError::Device(result) => write!(f, "could not create the logical device: {}", result),
+            VulkanAppError::Swapchain(result) => write!(f, "could not create the swapchain: {}", result),
+            VulkanAppError::Pipeline(result) => write!(f, "could not create a pipeline: {}", result),
+            VulkanAppError::ShaderDecode(message) => write!(f, "invalid SPIR-V: {}", message),
+            VulkanAppError::NoMemoryType(properties) => write!(f, "no memory type is {:?}", properties),
+            VulkanAppError::Vulkan{action, result} => write!(f, "could not {}: {}", action, result),
+            VulkanAppError::Validation(message) => write!(f, "validation error: {}", message),
+            VulkanAppError::Io{path, error} => write!(f, "could not write {}: {}", path.display(), error),
+        }
+    }
+}
+impl std::error::Error for VulkanAppError {}
+
+/// The loaders only fail with a symbol missing if the driver is broken, which Vulkan calls incompatible
+pub(crate) fn loader_result(error: LoaderError) -> vk::Result {
+    match error {
+        LoaderError::VulkanError(result) => result,
+        LoaderError::SymbolNotAvailable => vk::Result::ERROR_INCOMPATIBLE_DRIVER,
+    }
+}
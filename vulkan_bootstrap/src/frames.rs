@@ -0,0 +1,144 @@
+//! The frames in flight: while the GPU draws one frame the CPU prepares the next, each with semaphores and a fence of its own.
+
+use erupt::{vk, DeviceLoader};
+
+use crate::VulkanAppError;
+
+/// What one frame in flight synchronises with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameSync {
+    /// Signalled once the swapchain image the frame draws into has been acquired
+    pub image_available: vk::Semaphore,
+    /// Signalled once the frame is drawn, presentation waits for it
+    pub render_finished: vk::Semaphore,
+    /// Signalled once the GPU is done with the frame, so its command buffer and other per-frame resources can be reused
+    pub in_flight: vk::Fence,
+}
+
+/// The [`FrameSync`] of every frame in flight, handed out in turn.
+/// Anything else kept per frame, like command buffers, is indexed by [`FrameRing::index`]
+pub struct FrameRing {
+    frames: Vec<FrameSync>,
+    current: usize,
+}
+impl FrameRing {
+    /// `count` frames in flight, their fences signalled so that the first wait on each returns at once
+    pub fn new(device: &DeviceLoader, count: usize) -> Result<FrameRing, VulkanAppError> {
+        let mut frames = Vec::with_capacity(count);
+        for _ in 0..count {
+            unsafe {
+                frames.push(FrameSync {
+                    image_available: device.create_semaphore(&vk::SemaphoreCreateInfoBuilder::new(), None).map_err(VulkanAppError::vulkan("create semaphore"))?,
+                    render_finished: device.create_semaphore(&vk::SemaphoreCreateInfoBuilder::new(), None).map_err(VulkanAppError::vulkan("create semaphore"))?,
+                    in_flight: device.create_fence(&vk::FenceCreateInfoBuilder::new().flags(vk::FenceCreateFlags::SIGNALED), None).map_err(VulkanAppError::vulkan("create fence"))?,
+                });
+            }
+        }
+        Ok(FrameRing::from_frames(frames))
+    }
+
+    fn from_frames(frames: Vec<FrameSync>) -> FrameRing {
+        assert!(!frames.is_empty(), "At least one frame must be in flight!");
+        FrameRing {frames, current: 0}
+    }
+
+    /// Number of frames in flight
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Never true, there is always at least one frame
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Slot of the frame being prepared, from 0 up to [`FrameRing::len`]
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    /// Semaphores and fence of the frame being prepared
+    pub fn current(&self) -> FrameSync {
+        self.frames[self.current]
+    }
+
+    /// Moves on to the next frame once the current one has been submitted, wrapping around to the first after the last
+    pub fn advance(&mut self) {
+        self.current = (self.current + 1) % self.frames.len();
+    }
+
+    /// The fences of every frame, e.g. to wait for all of them
+    pub fn fences(&self) -> Vec<vk::Fence> {
+        self.frames.iter().map(|frame| frame.in_flight).collect()
+    }
+
+    /// The device must be done with every frame
+    pub fn destroy(&self, device: &DeviceLoader) {
+        for frame in &self.frames {
+            unsafe {
+                device.destroy_semaphore(frame.image_available, None);
+                device.destroy_semaphore(frame.render_finished, None);
+                device.destroy_fence(frame.in_flight, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Stand-in handles, never handed to Vulkan
+    fn ring(count: u64) -> FrameRing {
+        FrameRing::from_frames((0..count).map(|i| FrameSync {
+            image_available: vk::Semaphore(10 + i),
+            render_finished: vk::Semaphore(20 + i),
+            in_flight: vk::Fence(30 + i),
+        }).collect())
+    }
+
+    #[test]
+    fn every_slot_is_used_once_per_lap() {
+        for count in 1..=4 {
+            let mut ring = ring(count);
+            let mut visited = Vec::new();
+            for _ in 0..3 * count {
+                visited.push((ring.index(), ring.current()));
+                ring.advance();
+            }
+            for (frame, (index, sync)) in visited.iter().enumerate() {
+                let slot = frame % count as usize;
+                assert_eq!(*index, slot);
+                assert_eq!(sync.image_available, vk::Semaphore(10 + slot as u64));
+                assert_eq!(sync.render_finished, vk::Semaphore(20 + slot as u64));
+                assert_eq!(sync.in_flight, vk::Fence(30 + slot as u64));
+            }
+        }
+    }
+
+    #[test]
+    fn consecutive_frames_share_nothing() {
+        let mut ring = ring(2);
+        let first = ring.current();
+        ring.advance();
+        let second = ring.current();
+        assert_ne!(first.image_available, second.image_available);
+        assert_ne!(first.render_finished, second.render_finished);
+        assert_ne!(first.in_flight, second.in_flight);
+    }
+
+    #[test]
+    fn single_frame_reuses_its_objects() {
+        let mut ring = ring(1);
+        let first = ring.current();
+        ring.advance();
+        assert_eq!(ring.index(), 0);
+        assert_eq!(ring.current(), first);
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_frames_is_refused() {
+        FrameRing::from_frames(Vec::new());
+    }
+}
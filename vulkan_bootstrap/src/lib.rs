@@ -0,0 +1,559 @@
+//! Shared Vulkan bootstrap code for the tutorial crates and the finished Mandelbrot viewer.
+//!
+//! Creating the instance, debug messenger, window surface, physical device, logical device and queues
+//! is the same in every renderer, so it lives here once and is exposed as a [`VulkanContext`].
+
+use winit::window::Window;
+
+use erupt::{vk, {EntryLoader, InstanceLoader, DeviceLoader}, ExtendableFrom, utils::{surface}, cstr};
+
+use std::ffi::{CString, CStr};
+use std::os::raw::{c_char, c_void};
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+use std::str::FromStr;
+
+mod error;
+pub mod report;
+pub mod frames;
+pub use error::VulkanAppError;
+use error::loader_result;
+
+pub const VALIDATION_LAYERS: [*const c_char; 1] = [cstr!("VK_LAYER_KHRONOS_validation")];
+#[cfg(debug_assertions)]
+pub const VALIDATION_ENABLED: bool = true;
+#[cfg(not(debug_assertions))]
+pub const VALIDATION_ENABLED: bool = false;
+
+/// Index of the graphics queue family in the array returned by [`find_queue_families`]
+pub const GRAPHICS_Q_IDX: usize = 0;
+/// Index of the present queue family in the array returned by [`find_queue_families`]
+pub const PRESENT_Q_IDX: usize = 1;
+pub const DEVICE_EXTS: [*const c_char; 1] = [vk::KHR_SWAPCHAIN_EXTENSION_NAME];
+
+
+/// What the debug messenger reports and what it does about it, see [`ContextOptions::debug_messages`]
+#[derive(Clone, Debug)]
+pub struct DebugMessages {
+    /// Severities the messenger passes on. They are logged at the matching [`log::Level`], so the logger's own filter applies on top
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    /// Fail on validation errors, so tests fail on them instead of printing and carrying on.
+    /// The callback can't return an error to the caller, so it keeps the first one for [`VulkanContext::check_validation`]
+    pub fail_on_error: bool,
+}
+impl Default for DebugMessages {
+    fn default() -> Self {
+        DebugMessages {
+            severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING_EXT | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR_EXT,
+            fail_on_error: false,
+        }
+    }
+}
+
+/// What the debug messenger's callback reads, and the first validation error it records, through `p_user_data`
+pub(crate) struct MessengerState {
+    messages: DebugMessages,
+    first_error: Mutex<Option<String>>,
+}
+impl MessengerState {
+    pub(crate) fn new(messages: DebugMessages) -> MessengerState {
+        MessengerState {messages, first_error: Mutex::new(None)}
+    }
+}
+
+/// The [`log::Level`] messages of `severity` are logged at
+fn log_level(severity: vk::DebugUtilsMessageSeverityFlagBitsEXT) -> log::Level {
+    match severity {
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::ERROR_EXT => log::Level::Error,
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::WARNING_EXT => log::Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagBitsEXT::INFO_EXT => log::Level::Info,
+        _ => log::Level::Trace, //Verbose, mostly the loader and layers describing themselves
+    }
+}
+
+/// Logs the message through [`log`], at the level [`log_level`] gives and with the target `vulkan::validation`,
+/// `vulkan::performance` or `vulkan::general` after its type. Printed to stderr instead if no logger is set up.
+/// `p_user_data` points to the `MessengerState` it was created with, where the first validation error is recorded
+/// if [`DebugMessages::fail_on_error`] is set. It must not panic, a panic can't unwind into the driver and aborts the process
+///
+/// # Safety
+/// Only meant to be called by the Vulkan loader, which guarantees `p_callback_data` is valid
+pub unsafe extern "system" fn debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagBitsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut c_void
+) -> vk::Bool32 {
+    let level = log_level(message_severity);
+    let target = if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT) {
+        "vulkan::validation"
+    } else if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT) {
+        "vulkan::performance"
+    } else {
+        "vulkan::general"
+    };
+    let message = debug_message_text(&*p_callback_data);
+    if log::max_level() == log::LevelFilter::Off { //No logger was set up, as in the tutorial crates
+        eprintln!("{}", message);
+    } else {
+        log::log!(target: target, level, "{}", message);
+    }
+
+    let state = p_user_data as *const MessengerState;
+    if level == log::Level::Error && target == "vulkan::validation" && !state.is_null() && (*state).messages.fail_on_error {
+        //Poisoned only if a thread panicked holding it, the Option is still fine to use
+        (*state).first_error.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert(message);
+    }
+    vk::FALSE
+}
+
+/// The message with its ID, followed by the objects it concerns and the labels of the queue and command buffer regions it happened in
+unsafe fn debug_message_text(data: &vk::DebugUtilsMessengerCallbackDataEXT) -> String {
+    let text = |pointer: *const c_char| if pointer.is_null() {None} else {Some(CStr::from_ptr(pointer).to_string_lossy())};
+    let mut message = match text(data.p_message_id_name) {
+        Some(id_name) => format!("[{} {:#x}] ", id_name, data.message_id_number),
+        None => String::new(),
+    };
+    message += &text(data.p_message).unwrap_or_default();
+
+    let objects: Vec<String> = raw_slice(data.p_objects, data.object_count).iter().map(|object| match text(object.p_object_name) {
+        Some(name) => format!("{:?} {:#x} \"{}\"", object.object_type, object.object_handle, name),
+        None => format!("{:?} {:#x}", object.object_type, object.object_handle),
+    }).collect();
+    let labels = |labels: &[vk::DebugUtilsLabelEXT]| labels.iter().filter_map(|label| text(label.p_label_name)).collect::<Vec<_>>().join(", ");
+    let queue_labels = labels(raw_slice(data.p_queue_labels, data.queue_label_count));
+    let command_buffer_labels = labels(raw_slice(data.p_cmd_buf_labels, data.cmd_buf_label_count));
+    if !objects.is_empty() {
+        message += &format!("\n    objects: {}", objects.join(", "));
+    }
+    if !queue_labels.is_empty() {
+        message += &format!("\n    queue labels: {}", queue_labels);
+    }
+    if !command_buffer_labels.is_empty() {
+        message += &format!("\n    command buffer labels: {}", command_buffer_labels);
+    }
+    message
+}
+
+/// The Vulkan arrays in the callback data may be null when empty, which [`std::slice::from_raw_parts`] doesn't allow
+unsafe fn raw_slice<'a, T>(pointer: *const T, count: u32) -> &'a [T] {
+    if pointer.is_null() || count == 0 {&[]} else {std::slice::from_raw_parts(pointer, count as usize)}
+}
+
+/// Messenger for the severities in the debug messages of `state`, which has to outlive it as the callback reads it through `p_user_data`
+pub(crate) fn init_debug_messenger_info(state: &MessengerState) -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'_> {
+    let messenger_info = vk::DebugUtilsMessengerCreateInfoEXTBuilder::new()
+    .message_severity(state.messages.severity)
+    .message_type(
+        vk::DebugUtilsMessageTypeFlagsEXT::GENERAL_EXT |
+        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION_EXT |
+        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE_EXT
+    )
+    .pfn_user_callback(Some(debug_callback))
+    .user_data(state as *const MessengerState as *mut c_void);
+    messenger_info
+}
+
+pub fn check_validation_layer_support(entry: &EntryLoader) -> bool {
+    let available_layers = unsafe {entry.enumerate_instance_layer_properties(None).unwrap()};
+    for layer in &VALIDATION_LAYERS {
+        let mut found = false;
+        for layer_properties in &available_layers {
+            let layer_name_ptr = layer_properties.layer_name.as_ptr();
+            unsafe {
+                if CStr::from_ptr(layer_name_ptr) == CStr::from_ptr(*layer) {
+                    found = true; break
+                }
+            }
+        }
+        if !found {return false}
+    }
+    true
+}
+
+/// Returns the (capabilities, formats, present modes) supported by `device` for `surface`
+pub fn query_swap_chain_support(device: &vk::PhysicalDevice, surface: &vk::SurfaceKHR, instance: &InstanceLoader)
+-> (vk::SurfaceCapabilitiesKHR, Vec<vk::SurfaceFormatKHR>, Vec<vk::PresentModeKHR>) {
+    let surface_capabilities = unsafe {instance.get_physical_device_surface_capabilities_khr(*device, *surface)}.unwrap();
+    let formats = unsafe {instance.get_physical_device_surface_formats_khr(*device, *surface, None)}.unwrap();
+    let present_modes = unsafe {instance.get_physical_device_surface_present_modes_khr(*device, *surface, None)}.unwrap();
+    (surface_capabilities, formats.to_vec(), present_modes.to_vec())
+}
+
+/// Returns the graphics and present queue family indices, indexed by [`GRAPHICS_Q_IDX`] and [`PRESENT_Q_IDX`].
+/// With a null `surface` (headless) nothing is presented, so the present index is simply the graphics index.
+pub fn find_queue_families(device: &vk::PhysicalDevice, surface: &vk::SurfaceKHR, instance: &InstanceLoader) -> Option<[u32; 2]> {
+    let queue_family_properties = unsafe {instance.get_physical_device_queue_family_properties(*device, None)};
+    let mut indices = [0; 2];
+    let mut found_queues = [false; 2];
+    for (i, queue_family) in queue_family_properties.iter().enumerate() {
+        if !found_queues[GRAPHICS_Q_IDX] && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+            indices[GRAPHICS_Q_IDX] = i as u32; //Graphics queue found, look for present queue (probably the same)
+            found_queues[GRAPHICS_Q_IDX] = true;
+        }
+        if surface.is_null() {
+            indices[PRESENT_Q_IDX] = indices[GRAPHICS_Q_IDX];
+            found_queues[PRESENT_Q_IDX] = found_queues[GRAPHICS_Q_IDX];
+        } else if !found_queues[PRESENT_Q_IDX] && unsafe {instance.get_physical_device_surface_support_khr(*device, i as u32, *surface)}.unwrap() {
+            indices[PRESENT_Q_IDX] = i as u32;
+            found_queues[PRESENT_Q_IDX] = true;
+        }
+        if found_queues.iter().all(|found| *found) {
+            return Some(indices)
+        }
+    }
+    None
+}
+
+pub fn check_device_extension_support(device: &vk::PhysicalDevice, instance: &InstanceLoader, extensions: &[*const c_char]) -> bool {
+    let device_extension_properties = unsafe {instance.enumerate_device_extension_properties(*device, None, None)}.unwrap();
+    let available_extension_names: Vec<&str> = device_extension_properties
+        .iter()
+        .map(|ext| unsafe {CStr::from_ptr(ext.extension_name.as_ptr())}.to_str().unwrap() ).collect();
+    for &extension in extensions {
+        let ext_name = unsafe {CStr::from_ptr(extension)}.to_str().unwrap();
+        if !available_extension_names.contains(&ext_name) {
+            return false
+        }
+    }
+    true
+}
+
+/// `required_features` are the ones the renderer enables, see [`ContextOptions::required_features`].
+/// The device must be able to make a swapchain for `surface` if `swapchain` is set, see [`ContextOptions::swapchain`]
+pub fn is_device_suitable(device: &vk::PhysicalDevice, surface: &vk::SurfaceKHR, instance: &InstanceLoader, required_features: &vk::PhysicalDeviceFeatures, swapchain: bool) -> bool {
+    let device_features = unsafe {instance.get_physical_device_features(*device)};
+
+    let has_swapchain_support = surface.is_null() || !swapchain || { //Headless contexts never present, so don't need a swapchain
+        if !check_device_extension_support(device, instance, &DEVICE_EXTS) {return false} //Must have extension to query swap chain
+        let (_, formats, present_modes) = query_swap_chain_support(device, surface, instance);
+        !formats.is_empty() && !present_modes.is_empty()
+    };
+
+    supports_features(&device_features, required_features)
+        && find_queue_families(device, surface, instance).is_some()
+        && has_swapchain_support
+}
+
+/// Index of the first memory type allowed by `type_bits` (as in [`vk::MemoryRequirements`]) having all of `properties`
+pub fn find_memory_type(instance: &InstanceLoader, physical_device: vk::PhysicalDevice, type_bits: u32, properties: vk::MemoryPropertyFlags) -> Option<u32> {
+    let memory_properties = unsafe {instance.get_physical_device_memory_properties(physical_device)};
+    (0..memory_properties.memory_type_count).find(|&i| {
+        (type_bits & (1 << i)) != 0 && memory_properties.memory_types[i as usize].property_flags.contains(properties)
+    })
+}
+
+pub fn rate_device_suitability(device_properties: &vk::PhysicalDeviceProperties, device_features: &vk::PhysicalDeviceFeatures, required_features: &vk::PhysicalDeviceFeatures) -> u32 {
+    let mut score = 0;
+    if device_properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {score += 1000}
+    score += device_properties.limits.max_image_dimension2_d;
+    if !supports_features(device_features, required_features) {return 0}
+    score
+}
+
+/// Whether every feature set in `required` is also set in `supported`
+pub fn supports_features(supported: &vk::PhysicalDeviceFeatures, required: &vk::PhysicalDeviceFeatures) -> bool {
+    feature_flags(supported).iter().zip(feature_flags(required)).all(|(supported, required)| *supported == vk::TRUE || *required == vk::FALSE)
+}
+
+//The features struct is nothing but Bool32s in a row, so it can be compared field by field as a slice
+fn feature_flags(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32] {
+    let count = std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>();
+    unsafe {std::slice::from_raw_parts(features as *const vk::PhysicalDeviceFeatures as *const vk::Bool32, count)}
+}
+fn feature_flags_mut(features: &mut vk::PhysicalDeviceFeatures) -> &mut [vk::Bool32] {
+    let count = std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>();
+    unsafe {std::slice::from_raw_parts_mut(features as *mut vk::PhysicalDeviceFeatures as *mut vk::Bool32, count)}
+}
+
+pub fn device_name(instance: &InstanceLoader, device: vk::PhysicalDevice) -> String {
+    let device_properties = unsafe {instance.get_physical_device_properties(device)};
+    unsafe {CStr::from_ptr(device_properties.device_name.as_ptr())}.to_string_lossy().into_owned()
+}
+
+/// The device `selector` names, or the best one by [`rate_device_suitability`] if it is `None`
+pub fn pick_physical_device(instance: &InstanceLoader, devices: &[vk::PhysicalDevice], selector: Option<&DeviceSelector>, required_features: &vk::PhysicalDeviceFeatures)
+    -> Result<vk::PhysicalDevice, VulkanAppError> {
+    match selector {
+        Some(DeviceSelector::Index(index)) => devices.get(*index).copied().ok_or(VulkanAppError::DeviceIndexOutOfRange{index: *index, count: devices.len()}),
+        Some(DeviceSelector::Name(name)) => {
+            let wanted = name.to_lowercase();
+            devices.iter().copied()
+                .find(|device| device_name(instance, *device).to_lowercase().contains(&wanted))
+                .ok_or_else(|| VulkanAppError::DeviceNotFound(name.clone()))
+        },
+        None => devices.iter().copied().max_by_key(
+            |device| {
+                let device_properties = unsafe {instance.get_physical_device_properties(*device)};
+                let device_features = unsafe {instance.get_physical_device_features(*device)};
+                rate_device_suitability(&device_properties, &device_features, required_features)
+            }
+        ).ok_or(VulkanAppError::NoDevice),
+    }
+}
+
+/// A physical device picked by the user instead of by [`rate_device_suitability`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Position in the enumeration order, as listed by [`report::report_devices`]
+    Index(usize),
+    /// The first device whose name contains this, ignoring case
+    Name(String),
+}
+impl FromStr for DeviceSelector {
+    type Err = String;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        if string.is_empty() {return Err("empty device name".to_string())}
+        Ok(match string.parse::<usize>() {
+            Ok(index) => DeviceSelector::Index(index),
+            Err(_) => DeviceSelector::Name(string.to_string()),
+        })
+    }
+}
+
+
+/// Knobs for [`VulkanContext::with_options`]. The defaults match what [`VulkanContext::new`] does.
+#[derive(Clone, Debug)]
+pub struct ContextOptions {
+    /// Enable the Khronos validation layer and debug messenger
+    pub validation: bool,
+    /// Create a surface for the window, if there is one. Without it the context is headless as far as the device goes,
+    /// though the instance still has the extensions the window would need
+    pub surface: bool,
+    /// Enable VK_KHR_swapchain and pass over devices that can't present to the surface. Needs `surface`
+    pub swapchain: bool,
+    /// Use this physical device instead of the best rated one
+    pub device: Option<DeviceSelector>,
+    /// Features the renderer cannot do without. Devices lacking any are passed over, and they are all enabled on the one picked
+    pub required_features: vk::PhysicalDeviceFeatures,
+    /// Features the renderer can do without, enabled if the device supports them. Check [`VulkanContext::enabled_features`]
+    pub optional_features: vk::PhysicalDeviceFeatures,
+    /// What the debug messenger reports, if `validation` is on
+    pub debug_messages: DebugMessages,
+}
+impl Default for ContextOptions {
+    fn default() -> Self {
+        ContextOptions {
+            validation: VALIDATION_ENABLED,
+            surface: true,
+            swapchain: true,
+            device: None,
+            required_features: vk::PhysicalDeviceFeatures::default(),
+            optional_features: vk::PhysicalDeviceFeatures::default(),
+            debug_messages: DebugMessages::default(),
+        }
+    }
+}
+
+
+/// Everything up to and including the logical device and its queues.
+/// Renderers build their swapchain, pipelines etc. on top of this and must destroy those before it is dropped.
+///
+/// A context made with [`VulkanContext::new_headless`] has a null `surface` and no swapchain extension;
+/// it can only render to images it allocates itself.
+pub struct VulkanContext { //Members dropped in declared order. So they must be placed in opposite order of references
+    pub graphics_queue: vk::Queue,
+    pub present_queue: vk::Queue,
+    pub queue_family_indices: [u32; 2],
+    /// Features the logical device was created with
+    pub enabled_features: vk::PhysicalDeviceFeatures,
+    pub device: Box<DeviceLoader>,
+    pub physical_device: vk::PhysicalDevice,
+    pub surface: vk::SurfaceKHR,
+    pub messenger: vk::DebugUtilsMessengerEXT,
+    messenger_state: Box<MessengerState>, //Read and written by the messenger's callback until the instance is destroyed
+    pub instance: Box<InstanceLoader>,
+    pub entry: Box<EntryLoader>,
+}
+impl Drop for VulkanContext {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_device(None);
+            if !self.messenger.is_null() {
+                self.instance.destroy_debug_utils_messenger_ext(self.messenger, None)
+            }
+            if !self.surface.is_null() {
+                self.instance.destroy_surface_khr(self.surface, None);
+            }
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+impl VulkanContext {
+    /// Panics if anything fails, use [`VulkanContext::with_options`] to handle the error instead
+    pub fn new(window: &Window, app_name: &str) -> VulkanContext {
+        Self::with_options(Some(window), app_name, &ContextOptions::default()).unwrap_or_else(|err| panic!("{}!", err))
+    }
+
+    /// Context without a window surface, for rendering offscreen on machines without a display
+    pub fn new_headless(app_name: &str) -> VulkanContext {
+        Self::with_options(None, app_name, &ContextOptions::default()).unwrap_or_else(|err| panic!("{}!", err))
+    }
+
+    /// Headless if `window` is `None`, see [`VulkanContext::new_headless`]
+    pub fn with_options(window: Option<&Window>, app_name: &str, options: &ContextOptions) -> Result<VulkanContext, VulkanAppError> {
+        let validation_enabled = options.validation;
+        let entry = Box::new(load_entry()?);
+
+        if validation_enabled && !check_validation_layer_support(&entry) {
+            return Err(VulkanAppError::ValidationLayerMissing);
+        }
+
+        let messenger_state = Box::new(MessengerState::new(options.debug_messages.clone()));
+        let (instance, messenger) = create_instance(&entry, window, app_name, validation_enabled.then_some(&*messenger_state))?;
+        let instance = Box::new(instance);
+
+        //// Window surface creation
+        let surface = match window {
+            Some(window) if options.surface => unsafe { surface::create_surface(&instance, window, None) }.map_err(VulkanAppError::Surface)?,
+            _ => vk::SurfaceKHR::null(),
+        };
+        let swapchain = options.swapchain && !surface.is_null();
+
+        //// Physical device and queues
+        let (physical_device, queue_family_indices) = {
+            let devices = unsafe {instance.enumerate_physical_devices(None)}.map_err(VulkanAppError::vulkan("enumerate physical devices"))?;
+            if devices.is_empty() {return Err(VulkanAppError::NoDevice)}
+
+            let physical_device = pick_physical_device(&instance, &devices, options.device.as_ref(), &options.required_features)?;
+            let name = device_name(&instance, physical_device);
+            println!("Device name: {}", name);
+            let queue_family_indices = match find_queue_families(&physical_device, &surface, &instance) {
+                Some(indices) if is_device_suitable(&physical_device, &surface, &instance, &options.required_features, swapchain) => indices,
+                _ => return Err(VulkanAppError::UnsuitableDevice(name)),
+            };
+
+            (physical_device, queue_family_indices)
+        };
+
+        //// Logical device
+        let unique_queue_family_indices: Vec<u32> = HashSet::from(queue_family_indices).into_iter().collect();
+        let device_queue_infos: &[vk::DeviceQueueCreateInfoBuilder] = &unique_queue_family_indices.into_iter().map(|index| {
+            vk::DeviceQueueCreateInfoBuilder::new()
+            .queue_family_index(index)
+            .queue_priorities(&[1.0])
+        }).collect::<Vec<vk::DeviceQueueCreateInfoBuilder>>().into_boxed_slice();
+
+        let device_extensions: &[*const c_char] = if swapchain {&DEVICE_EXTS} else {&[]};
+        let supported_features = unsafe {instance.get_physical_device_features(physical_device)};
+        let mut device_features = options.required_features;
+        let optional_flags = feature_flags(&options.optional_features).iter().zip(feature_flags(&supported_features));
+        for (enabled, (optional, supported)) in feature_flags_mut(&mut device_features).iter_mut().zip(optional_flags) {
+            if *optional == vk::TRUE && *supported == vk::TRUE {*enabled = vk::TRUE}
+        }
+        let mut device_create_info = vk::DeviceCreateInfoBuilder::new()
+            .queue_create_infos(device_queue_infos)
+            .enabled_features(&device_features)
+            .enabled_extension_names(device_extensions);
+        if validation_enabled {
+            device_create_info = device_create_info.enabled_layer_names(&VALIDATION_LAYERS);
+        }
+        let device = Box::new(unsafe {DeviceLoader::new(&instance, physical_device, &device_create_info)}.map_err(|err| VulkanAppError::Device(loader_result(err)))?);
+
+        //// Queue handles
+        let graphics_queue = unsafe {device.get_device_queue(queue_family_indices[GRAPHICS_Q_IDX], 0)};
+        let present_queue = unsafe {device.get_device_queue(queue_family_indices[PRESENT_Q_IDX], 0)};
+
+        Ok(VulkanContext {
+            entry,
+            instance,
+            messenger,
+            messenger_state,
+            surface,
+            physical_device,
+            device,
+            enabled_features: device_features,
+            queue_family_indices,
+            graphics_queue,
+            present_queue,
+        })
+    }
+
+    /// [`VulkanAppError::Validation`] with the first validation error reported so far, if [`DebugMessages::fail_on_error`] is set.
+    /// The layer reports errors during the calls it validates, so check after submitting and presenting
+    pub fn check_validation(&self) -> Result<(), VulkanAppError> {
+        match &*self.messenger_state.first_error.lock().unwrap_or_else(PoisonError::into_inner) {
+            Some(message) => Err(VulkanAppError::Validation(message.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+
+pub(crate) fn load_entry() -> Result<EntryLoader, VulkanAppError> {
+    //The loader's own message is vague, the library error under it names the file that was missing
+    EntryLoader::new().map_err(|err| {
+        VulkanAppError::Loader(std::error::Error::source(&err).map_or(err.to_string(), |source| source.to_string()))
+    })
+}
+
+/// Instance with the extensions `window` needs for a surface, and the validation layer and debug messenger if `messenger_state` is given.
+/// The messenger uses `messenger_state` until the instance is destroyed
+pub(crate) fn create_instance(entry: &EntryLoader, window: Option<&Window>, app_name: &str, messenger_state: Option<&MessengerState>)
+    -> Result<(InstanceLoader, vk::DebugUtilsMessengerEXT), VulkanAppError> {
+    //// Application info
+    let app_name = CString::new(app_name).unwrap();
+    let engine_name = CString::new("No Engine").unwrap();
+
+    let app_info = vk::ApplicationInfoBuilder::new()
+        .application_name(&app_name)
+        .application_version(vk::make_api_version(0,1,0,0))
+        .engine_name(&engine_name)
+        .engine_version(vk::API_VERSION_1_0)
+        .api_version(vk::API_VERSION_1_0);
+
+    let mut instance_extensions = match window {
+        Some(window) => surface::enumerate_required_extensions(window).map_err(VulkanAppError::Surface)?,
+        None => Vec::new(),
+    };
+    if messenger_state.is_some() {
+        instance_extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION_NAME);
+    }
+
+    //// Instance info & debug messenger
+    let mut messenger_info = messenger_state.map(init_debug_messenger_info);
+    let mut instance_info = vk::InstanceCreateInfoBuilder::new()
+        .application_info(&app_info)
+        .enabled_extension_names(&instance_extensions);
+    if let Some(messenger_info) = &mut messenger_info {
+        instance_info = instance_info
+            .enabled_layer_names(&VALIDATION_LAYERS)
+            .extend_from(messenger_info);
+    }
+
+    //// Instance created
+    let instance = unsafe {InstanceLoader::new(entry, &instance_info)}.map_err(|err| VulkanAppError::Instance(loader_result(err)))?;
+    // Messenger attached
+    let messenger = match &messenger_info {
+        Some(messenger_info) => unsafe {instance.create_debug_utils_messenger_ext(messenger_info, None)}.map_err(VulkanAppError::vulkan("create debug messenger"))?,
+        None => vk::DebugUtilsMessengerEXT::default(),
+    };
+    Ok((instance, messenger))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severities_map_to_log_levels() {
+        assert_eq!(log_level(vk::DebugUtilsMessageSeverityFlagBitsEXT::ERROR_EXT), log::Level::Error);
+        assert_eq!(log_level(vk::DebugUtilsMessageSeverityFlagBitsEXT::WARNING_EXT), log::Level::Warn);
+        assert_eq!(log_level(vk::DebugUtilsMessageSeverityFlagBitsEXT::INFO_EXT), log::Level::Info);
+        assert_eq!(log_level(vk::DebugUtilsMessageSeverityFlagBitsEXT::VERBOSE_EXT), log::Level::Trace);
+    }
+
+    #[test]
+    fn more_severe_messages_log_at_higher_levels() {
+        let severities = [
+            vk::DebugUtilsMessageSeverityFlagBitsEXT::VERBOSE_EXT,
+            vk::DebugUtilsMessageSeverityFlagBitsEXT::INFO_EXT,
+            vk::DebugUtilsMessageSeverityFlagBitsEXT::WARNING_EXT,
+            vk::DebugUtilsMessageSeverityFlagBitsEXT::ERROR_EXT,
+        ];
+        for pair in severities.windows(2) {
+            assert!(log_level(pair[0]) > log_level(pair[1]), "{:?} should log below {:?}", pair[0], pair[1]);
+        }
+    }
+}
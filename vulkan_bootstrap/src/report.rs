@@ -0,0 +1,118 @@
+//! What every physical device supports, for picking one by hand and for bug reports.
+
+use winit::window::Window;
+
+use erupt::{vk, utils::surface};
+
+use std::ffi::CStr;
+
+use crate::{VulkanAppError, load_entry, create_instance, device_name, pick_physical_device, rate_device_suitability, is_device_suitable};
+
+pub struct QueueFamilyReport {
+    pub flags: vk::QueueFlags,
+    pub count: u32,
+    /// Whether the family can present to the window, always false without one
+    pub present: bool,
+}
+
+pub struct DeviceReport {
+    /// Position in the enumeration order, what [`crate::DeviceSelector::Index`] refers to
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub api_version: u32,
+    /// Encoded by the vendor, see [`DeviceReport::driver_version_string`]
+    pub driver_version: u32,
+    pub queue_families: Vec<QueueFamilyReport>,
+    /// Empty without a window to query the surface of
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+    pub present_modes: Vec<vk::PresentModeKHR>,
+    pub extensions: Vec<String>,
+    /// From [`rate_device_suitability`], 0 means unusable
+    pub score: u32,
+    /// Passes [`is_device_suitable`] for the window, or headless rendering without one
+    pub suitable: bool,
+    /// The device picked when none is asked for
+    pub default: bool,
+}
+impl DeviceReport {
+    pub fn api_version_string(&self) -> String {
+        format!("{}.{}.{}", vk::api_version_major(self.api_version), vk::api_version_minor(self.api_version), vk::api_version_patch(self.api_version))
+    }
+
+    /// NVIDIA packs its driver version differently from the Vulkan version encoding everyone else uses
+    pub fn driver_version_string(&self) -> String {
+        const NVIDIA: u32 = 0x10de;
+        let version = self.driver_version;
+        if self.vendor_id == NVIDIA {
+            format!("{}.{}.{}.{}", version >> 22, (version >> 14) & 0xff, (version >> 6) & 0xff, version & 0x3f)
+        } else {
+            format!("{}.{}.{}", vk::api_version_major(version), vk::api_version_minor(version), vk::api_version_patch(version))
+        }
+    }
+}
+
+/// Describes every physical device. With a `window` the surface formats, present modes and present support
+/// are filled in for its surface, the window is not shown or drawn to.
+/// Suitability and score are judged against `required_features`, as when creating a context.
+pub fn report_devices(window: Option<&Window>, app_name: &str, required_features: &vk::PhysicalDeviceFeatures) -> Result<Vec<DeviceReport>, VulkanAppError> {
+    let entry = load_entry()?;
+    let (instance, _) = create_instance(&entry, window, app_name, None)?;
+    let surface = match window {
+        Some(window) => unsafe {surface::create_surface(&instance, window, None)}.map_err(VulkanAppError::Surface)?,
+        None => vk::SurfaceKHR::null(),
+    };
+
+    let devices = unsafe {instance.enumerate_physical_devices(None)}.map_err(VulkanAppError::vulkan("enumerate physical devices"))?;
+    let default_device = pick_physical_device(&instance, &devices, None, required_features).ok();
+    let reports = devices.iter().enumerate().map(|(index, &device)| {
+        let properties = unsafe {instance.get_physical_device_properties(device)};
+        let features = unsafe {instance.get_physical_device_features(device)};
+
+        let queue_families = unsafe {instance.get_physical_device_queue_family_properties(device, None)}
+            .iter().enumerate().map(|(family, properties)| QueueFamilyReport {
+                flags: properties.queue_flags,
+                count: properties.queue_count,
+                present: !surface.is_null()
+                    && unsafe {instance.get_physical_device_surface_support_khr(device, family as u32, surface)}.ok().unwrap_or(false),
+            }).collect();
+        let (surface_formats, present_modes) = if surface.is_null() {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                unsafe {instance.get_physical_device_surface_formats_khr(device, surface, None)}.ok().unwrap_or_default().to_vec(),
+                unsafe {instance.get_physical_device_surface_present_modes_khr(device, surface, None)}.ok().unwrap_or_default().to_vec(),
+            )
+        };
+        let extensions = unsafe {instance.enumerate_device_extension_properties(device, None, None)}.ok().unwrap_or_default()
+            .iter().map(|extension| unsafe {CStr::from_ptr(extension.extension_name.as_ptr())}.to_string_lossy().into_owned())
+            .collect();
+
+        DeviceReport {
+            index,
+            name: device_name(&instance, device),
+            device_type: properties.device_type,
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            api_version: properties.api_version,
+            driver_version: properties.driver_version,
+            queue_families,
+            surface_formats,
+            present_modes,
+            extensions,
+            score: rate_device_suitability(&properties, &features, required_features),
+            suitable: is_device_suitable(&device, &surface, &instance, required_features, true),
+            default: default_device == Some(device),
+        }
+    }).collect();
+
+    unsafe {
+        if !surface.is_null() {
+            instance.destroy_surface_khr(surface, None);
+        }
+        instance.destroy_instance(None);
+    }
+    Ok(reports)
+}
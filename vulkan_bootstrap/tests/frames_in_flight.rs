@@ -0,0 +1,122 @@
+//! Runs a few headless frames on lavapipe, Mesa's software driver, and checks the frames in flight take turns.
+//! Ignored by default as it needs lavapipe installed, run it with `cargo test -- --ignored` where it is, like on CI.
+
+use erupt::vk;
+use vulkan_bootstrap::{VulkanContext, VulkanAppError, ContextOptions, DebugMessages, DeviceSelector, GRAPHICS_Q_IDX};
+use vulkan_bootstrap::frames::FrameRing;
+
+const FRAMES_IN_FLIGHT: usize = 3;
+const LAPS: usize = 3;
+const TIMEOUT_NS: u64 = 5_000_000_000;
+
+fn lavapipe_context() -> VulkanContext {
+    let mut options = ContextOptions {
+        device: Some(DeviceSelector::Name("llvmpipe".to_string())),
+        debug_messages: DebugMessages {fail_on_error: true, ..DebugMessages::default()},
+        ..ContextOptions::default()
+    };
+    //With validation where the layer is installed, to catch misused fences, without it elsewhere
+    let context = VulkanContext::with_options(None, "frames in flight test", &options).or_else(|err| match err {
+        VulkanAppError::ValidationLayerMissing => {
+            options.validation = false;
+            VulkanContext::with_options(None, "frames in flight test", &options)
+        },
+        err => Err(err),
+    });
+    return context.unwrap_or_else(|err| panic!("lavapipe is not available: {}", err));
+}
+
+#[test]
+#[ignore = "needs lavapipe, run with --ignored"]
+fn semaphores_and_fences_are_used_in_turn() {
+    let context = lavapipe_context();
+    let device = &context.device;
+    let mut ring = FrameRing::new(device, FRAMES_IN_FLIGHT).unwrap();
+
+    let command_pool_info = vk::CommandPoolCreateInfoBuilder::new()
+        .queue_family_index(context.queue_family_indices[GRAPHICS_Q_IDX])
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+    let command_pool = unsafe {device.create_command_pool(&command_pool_info, None)}.unwrap();
+    let command_buffer_allocate_info = vk::CommandBufferAllocateInfoBuilder::new()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(FRAMES_IN_FLIGHT as u32);
+    let command_buffers = unsafe {device.allocate_command_buffers(&command_buffer_allocate_info)}.unwrap();
+
+    let mut used = Vec::new();
+    //Signalled by the previous frame and waited for by the next, as presentation would
+    let mut previous_render_finished = None;
+    for frame in 0..LAPS * FRAMES_IN_FLIGHT {
+        let slot = ring.index();
+        assert_eq!(slot, frame % FRAMES_IN_FLIGHT);
+        let sync = ring.current();
+        used.push(sync);
+        unsafe {
+            //As the render loop does: wait until the slot is free, then reuse its command buffer and fence
+            device.wait_for_fences(&[sync.in_flight], true, TIMEOUT_NS).result().unwrap();
+            device.reset_fences(&[sync.in_flight]).unwrap();
+            assert_eq!(device.get_fence_status(sync.in_flight).raw, vk::Result::NOT_READY);
+
+            //Standing in for acquiring an image and presenting the previous one
+            let acquired = [sync.image_available];
+            let presented: Vec<vk::Semaphore> = previous_render_finished.into_iter().collect();
+            let present_stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; presented.len()];
+            let acquire = [vk::SubmitInfoBuilder::new()
+                .wait_semaphores(&presented)
+                .wait_dst_stage_mask(&present_stages)
+                .signal_semaphores(&acquired)];
+            device.queue_submit(context.graphics_queue, &acquire, vk::Fence::null()).unwrap();
+
+            let command_buffer = command_buffers[slot];
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty()).unwrap();
+            device.begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfoBuilder::new().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)).unwrap();
+            device.end_command_buffer(command_buffer).unwrap();
+            let submitted = [command_buffer];
+            let rendered = [sync.render_finished];
+            let render = [vk::SubmitInfoBuilder::new()
+                .wait_semaphores(&acquired)
+                .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
+                .command_buffers(&submitted)
+                .signal_semaphores(&rendered)];
+            device.queue_submit(context.graphics_queue, &render, sync.in_flight).unwrap();
+            previous_render_finished = Some(sync.render_finished);
+
+            //The fence of this slot signals, and no other slot's fence was reset along the way
+            device.wait_for_fences(&[sync.in_flight], true, TIMEOUT_NS).result().unwrap();
+            for (other, fence) in ring.fences().into_iter().enumerate() {
+                assert_eq!(device.get_fence_status(fence).raw, vk::Result::SUCCESS, "fence of slot {} after frame {}", other, frame);
+            }
+        }
+        ring.advance();
+    }
+
+    //Each lap hands out the same objects in the same order, and no two slots share any
+    for (frame, sync) in used.iter().enumerate().skip(FRAMES_IN_FLIGHT) {
+        assert_eq!(*sync, used[frame - FRAMES_IN_FLIGHT], "frame {}", frame);
+    }
+    let lap = &used[..FRAMES_IN_FLIGHT];
+    let mut semaphores: Vec<vk::Semaphore> = lap.iter().flat_map(|sync| [sync.image_available, sync.render_finished]).collect();
+    let mut fences: Vec<vk::Fence> = lap.iter().map(|sync| sync.in_flight).collect();
+    semaphores.sort_by_key(|semaphore| semaphore.0);
+    semaphores.dedup();
+    fences.sort_by_key(|fence| fence.0);
+    fences.dedup();
+    assert_eq!(semaphores.len(), 2 * FRAMES_IN_FLIGHT);
+    assert_eq!(fences.len(), FRAMES_IN_FLIGHT);
+
+    unsafe {
+        //The last frame's render finished semaphore, waited for as its presentation would
+        let presented: Vec<vk::Semaphore> = previous_render_finished.into_iter().collect();
+        let present = [vk::SubmitInfoBuilder::new()
+            .wait_semaphores(&presented)
+            .wait_dst_stage_mask(&[vk::PipelineStageFlags::ALL_COMMANDS])];
+        device.queue_submit(context.graphics_queue, &present, vk::Fence::null()).unwrap();
+        device.device_wait_idle().unwrap();
+    }
+    context.check_validation().unwrap();
+
+    unsafe {
+        device.destroy_command_pool(command_pool, None);
+    }
+    ring.destroy(device);
+}